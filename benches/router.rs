@@ -0,0 +1,125 @@
+//! Throughput and heap allocations of requests to static, param and nested routes, served
+//! over a loopback connection.
+//!
+//! Run with `cargo bench --bench router`. Allocations are counted for the client and the
+//! server together, so compare them between builds rather than reading them as the cost
+//! of routing alone.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::client::conn::http1::SendRequest;
+use hyper_util::rt::TokioIo;
+use lieweb::{App, Path, Router};
+use tokio::net::TcpStream;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const WARMUP: usize = 1_000;
+const REQUESTS: usize = 20_000;
+
+fn app() -> App {
+    let mut app = App::new();
+    app.get("/static", || async { "static" });
+    app.post("/static", || async { "created" });
+    app.get("/users/:id", |id: Path<u64>| async move {
+        id.take().to_string()
+    });
+
+    let mut api = Router::new();
+    api.get("/items", || async { "items" });
+    app.merge("/api/", api).unwrap();
+
+    app
+}
+
+async fn serve() -> SocketAddr {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(app().run(addr));
+
+    for _ in 0..100 {
+        if TcpStream::connect(addr).await.is_ok() {
+            return addr;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server not listening on {}", addr);
+}
+
+async fn get(sender: &mut SendRequest<Full<Bytes>>, path: &str) {
+    let req = hyper::Request::get(path)
+        .header(hyper::header::HOST, "localhost")
+        .body(Full::default())
+        .unwrap();
+
+    let resp = sender.send_request(req).await.unwrap();
+    assert!(resp.status().is_success(), "{} {}", path, resp.status());
+    resp.into_body().collect().await.unwrap();
+}
+
+async fn bench(addr: SocketAddr, path: &str) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+
+    for _ in 0..WARMUP {
+        get(&mut sender, path).await;
+    }
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for _ in 0..REQUESTS {
+        get(&mut sender, path).await;
+    }
+
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!(
+        "{:<12} {:>10.0} req/s {:>8.1} allocations/req",
+        path,
+        REQUESTS as f64 / elapsed.as_secs_f64(),
+        allocations as f64 / REQUESTS as f64
+    );
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt.block_on(async {
+        let addr = serve().await;
+
+        bench(addr, "/static").await;
+        bench(addr, "/users/42").await;
+        bench(addr, "/api/items").await;
+    });
+}
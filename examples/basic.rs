@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use lieweb::{
-    http, middleware, request::RequestParts, App, AppState, Error, LieRequest, LieResponse,
-    PathParam, RemoteAddr, Request,
+    http, middleware, request::RequestParts, App, AppState, Error, Form, LieResponse, PathParam,
+    RemoteAddr,
 };
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
@@ -35,8 +35,8 @@ async fn not_found(req: RequestParts) -> LieResponse {
     LieResponse::with_status(http::StatusCode::NOT_FOUND)
 }
 
-async fn handle_form_urlencoded(mut req: Request) -> Result<LieResponse, Error> {
-    let form: serde_json::Value = req.read_form().await?;
+async fn handle_form_urlencoded(form: Form<serde_json::Value>) -> Result<LieResponse, Error> {
+    let form = form.take();
 
     println!("form=> {:?}", form);
 
@@ -66,7 +66,7 @@ async fn main() {
     let mut default_headers = middleware::DefaultHeaders::new();
     default_headers.header(http::header::SERVER, lieweb::server_id());
 
-    app.middleware(middleware::RequestId);
+    app.middleware(middleware::RequestId::new());
     app.middleware(middleware::AccessLog);
     app.middleware(default_headers);
 
@@ -0,0 +1,33 @@
+use lieweb::{blocking, App, PathParam};
+
+#[derive(serde::Deserialize)]
+struct FibParam {
+    pub n: u64,
+}
+
+// CPU-bound work, should not run on the async runtime
+fn fibonacci(n: u64) -> u64 {
+    match n {
+        0 => 0,
+        1 => 1,
+        n => fibonacci(n - 1) + fibonacci(n - 2),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt().init();
+
+    let mut app = App::new();
+
+    // GET /fib/30 => 200 OK with body "fib(30) = 832040"
+    app.get(
+        "/fib/:n",
+        blocking(|param: PathParam<FibParam>| {
+            let n = param.value().n;
+            format!("fib({}) = {}", n, fibonacci(n))
+        }),
+    );
+
+    app.run("127.0.0.1:5000").await.unwrap();
+}
@@ -69,7 +69,8 @@ async fn main() {
 
     app.handle_not_found(not_found);
 
-    app.run_with_tls(&addr, "examples/server.crt", "examples/abc.key")
+    // self-signed for localhost, for testing only
+    app.run_with_tls(&addr, "testdata/ec.crt", "testdata/ec-pkcs8.key")
         .await
         .unwrap();
 }
@@ -68,12 +68,16 @@ mod models {
 mod handlers {
     use super::models::*;
     use super::State;
-    use lieweb::request::LieRequest;
     use lieweb::AppState;
+    use lieweb::Json;
     use lieweb::PathParam;
     use lieweb::Query;
-    use lieweb::Request;
-    use lieweb::{http::StatusCode, LieResponse};
+    use lieweb::{
+        http::{StatusCode, Uri},
+        LieResponse, Paginated,
+    };
+
+    const DEFAULT_LIMIT: usize = 20;
 
     #[derive(Debug, serde::Deserialize)]
     pub struct TodoId {
@@ -83,26 +87,24 @@ mod handlers {
     pub async fn list_todos(
         state: AppState<State>,
         opts: Query<ListOptions>,
-    ) -> Result<LieResponse, lieweb::Error> {
+        uri: Uri,
+    ) -> Paginated<Todo> {
         let opts = opts.value();
         let state = state.value().lock().await;
 
-        let todos: Vec<Todo> = state
-            .db
-            .clone()
-            .into_iter()
-            .skip(opts.offset.unwrap_or(0))
-            .take(opts.limit.unwrap_or(std::usize::MAX))
-            .collect();
+        let offset = opts.offset.unwrap_or(0);
+        let limit = opts.limit.unwrap_or(DEFAULT_LIMIT);
+
+        let todos: Vec<Todo> = state.db.iter().skip(offset).take(limit).cloned().collect();
 
-        Ok(LieResponse::with_json(&todos))
+        Paginated::new(&uri, todos, offset, limit, state.db.len())
     }
 
     pub async fn create_todo(
         state: AppState<State>,
-        mut req: Request,
+        create: Json<Todo>,
     ) -> Result<LieResponse, lieweb::Error> {
-        let create: Todo = req.read_json().await?;
+        let create = create.take();
 
         let mut state = state.value().lock().await;
 
@@ -122,11 +124,11 @@ mod handlers {
     pub async fn update_todo(
         params: PathParam<TodoId>,
         state: AppState<State>,
-        mut req: Request,
+        update: Json<Todo>,
     ) -> Result<LieResponse, lieweb::Error> {
         let todo_id: u64 = params.value().id;
 
-        let update: Todo = req.read_json().await?;
+        let update = update.take();
 
         let mut state = state.value().lock().await;
 
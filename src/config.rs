@@ -0,0 +1,675 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::server::conn::http1;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use hyper_util::server::conn::auto;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::error::Error;
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Socket and connection options used by `App::run_with_config`.
+///
+/// The default matches `App::run`.
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    reuse_port: bool,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<Duration>,
+    versions: HttpVersions,
+    header_read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    #[cfg(feature = "tls")]
+    tls: Option<crate::tls::TlsConfig>,
+}
+
+/// HTTP versions served on a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum HttpVersions {
+    #[default]
+    Auto,
+    Http1,
+    Http2,
+}
+
+/// Builder of connections for the HTTP versions of `ServerConfig`.
+#[derive(Clone)]
+pub(crate) enum HttpBuilder {
+    /// HTTP/1 and HTTP/2 detected from the preface, or HTTP/2 only.
+    Auto(auto::Builder<TokioExecutor>),
+    /// HTTP/1 only, with upgrades.
+    Http1(http1::Builder),
+}
+
+impl ServerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `SO_REUSEPORT` on the listening socket.
+    ///
+    /// Multiple processes can then bind the same address, and the kernel load-balances
+    /// incoming connections between them.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+    ))]
+    pub fn reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = reuse;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on accepted connections, sending small responses right away
+    /// instead of waiting to coalesce them with later writes. Off by default.
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Enable TCP keep-alive on accepted connections, probing idle peers after `idle`,
+    /// so dead peers are detected and their connections closed. Off by default.
+    pub fn tcp_keepalive(mut self, idle: Duration) -> Self {
+        self.tcp_keepalive = Some(idle);
+        self
+    }
+
+    /// Serve HTTP/1 only, by default the version is detected from the connection preface.
+    pub fn http1_only(mut self) -> Self {
+        self.versions = HttpVersions::Http1;
+        self
+    }
+
+    /// Serve HTTP/2 only, e.g. prior knowledge h2c behind a proxy.
+    pub fn http2_only(mut self) -> Self {
+        self.versions = HttpVersions::Http2;
+        self
+    }
+
+    /// Limit the time from accepting a connection, or finishing the previous request,
+    /// to receiving the full request line and headers.
+    ///
+    /// Mitigates slow-header attacks, the connection is closed when exceeded.
+    /// Only applies to HTTP/1.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections whose client accepts no response data within `timeout`, e.g. a
+    /// client reading a streamed body too slowly. Complements `BodyReadTimeout`.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Serve TLS connections with `tls`, the handshake is done after the socket options
+    /// are applied. See `App::run_with_tls_config`.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: crate::tls::TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub(crate) fn write_timeout_limit(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls_acceptor(&self) -> Option<tokio_rustls::TlsAcceptor> {
+        self.tls.as_ref().map(|tls| tls.acceptor())
+    }
+
+    pub(crate) fn http_builder(&self, http: &HttpConfig) -> HttpBuilder {
+        if self.versions == HttpVersions::Http1 {
+            let mut builder = http1::Builder::new();
+            http.apply_http1(&mut builder);
+
+            if let Some(timeout) = self.header_read_timeout {
+                builder
+                    .timer(TokioTimer::new())
+                    .header_read_timeout(timeout);
+            }
+
+            return HttpBuilder::Http1(builder);
+        }
+
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        if self.versions == HttpVersions::Http2 {
+            builder = builder.http2_only();
+        }
+
+        http.apply(&mut builder);
+
+        if let Some(timeout) = self.header_read_timeout {
+            builder
+                .http1()
+                .timer(TokioTimer::new())
+                .header_read_timeout(timeout);
+        }
+
+        HttpBuilder::Auto(builder)
+    }
+
+    /// Apply socket options to an accepted connection.
+    pub(crate) fn apply_socket(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.tcp_nodelay {
+            stream.set_nodelay(true)?;
+        }
+
+        if let Some(idle) = self.tcp_keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn bind(&self, addr: impl ToSocketAddrs) -> Result<TcpListener, Error> {
+        let mut last_err = None;
+
+        for addr in tokio::net::lookup_host(addr).await? {
+            match self.bind_addr(addr) {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        let err = last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+        });
+
+        Err(err.into())
+    }
+
+    fn bind_addr(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+        // same as `TcpListener::bind`
+        #[cfg(not(windows))]
+        socket.set_reuse_address(true)?;
+
+        #[cfg(all(
+            unix,
+            not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+        ))]
+        if self.reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+
+        TcpListener::from_std(socket.into())
+    }
+}
+
+const DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS: u32 = 100;
+
+/// HTTP protocol options, set by `App::with_http_config`.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    http2_max_concurrent_streams: u32,
+    http2_initial_stream_window_size: Option<u32>,
+    http2_initial_connection_window_size: Option<u32>,
+    http2_keep_alive: Option<(Duration, Duration)>,
+    http1_keep_alive: bool,
+    max_uri_length: Option<usize>,
+    max_headers: Option<usize>,
+}
+
+impl HttpConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit concurrent streams of a single HTTP/2 connection, default is 100.
+    ///
+    /// Streams over the limit are refused by the client until others finish. It only
+    /// bounds one connection, requests in flight across all connections are not limited.
+    pub fn http2_max_concurrent_streams(mut self, max: u32) -> Self {
+        self.http2_max_concurrent_streams = max;
+        self
+    }
+
+    /// Set the initial HTTP/2 flow control window of each stream, in bytes, default is
+    /// the protocol default of 64KiB.
+    pub fn http2_initial_stream_window_size(mut self, size: u32) -> Self {
+        self.http2_initial_stream_window_size = Some(size);
+        self
+    }
+
+    /// Set the initial HTTP/2 flow control window of the connection, in bytes.
+    pub fn http2_initial_connection_window_size(mut self, size: u32) -> Self {
+        self.http2_initial_connection_window_size = Some(size);
+        self
+    }
+
+    /// Ping HTTP/2 connections every `interval`, closing them when a ping is not
+    /// acknowledged within `timeout`. Off by default.
+    pub fn http2_keep_alive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.http2_keep_alive = Some((interval, timeout));
+        self
+    }
+
+    /// Keep HTTP/1 connections alive between requests, default is `true`. Disabled, each
+    /// response is sent with `Connection: close`.
+    ///
+    /// See `ServerConfig::header_read_timeout` to limit the time to receive headers.
+    pub fn http1_keep_alive(mut self, enabled: bool) -> Self {
+        self.http1_keep_alive = enabled;
+        self
+    }
+
+    /// Reject requests whose target, path and query, is longer than `max` bytes with
+    /// `414 URI Too Long`, before routing and middlewares, and close the connection. Not
+    /// limited by default.
+    ///
+    /// HTTP/1 request lines are checked as they are read, so the headers of a rejected
+    /// request are neither waited for nor parsed. HTTP/2 requests are checked once their
+    /// headers are decoded.
+    pub fn max_uri_length(mut self, max: usize) -> Self {
+        self.max_uri_length = Some(max);
+        self
+    }
+
+    /// Limit the number of HTTP/1 request headers, requests with more headers are
+    /// rejected by the parser with `431 Request Header Fields Too Large`.
+    pub fn max_headers(mut self, max: usize) -> Self {
+        self.max_headers = Some(max);
+        self
+    }
+
+    pub(crate) fn uri_length_limit(&self) -> Option<usize> {
+        self.max_uri_length
+    }
+
+    pub(crate) fn apply(&self, builder: &mut auto::Builder<TokioExecutor>) {
+        builder
+            .http2()
+            .max_concurrent_streams(self.http2_max_concurrent_streams)
+            .initial_stream_window_size(self.http2_initial_stream_window_size)
+            .initial_connection_window_size(self.http2_initial_connection_window_size);
+
+        if let Some((interval, timeout)) = self.http2_keep_alive {
+            builder
+                .http2()
+                .timer(TokioTimer::new())
+                .keep_alive_interval(interval)
+                .keep_alive_timeout(timeout);
+        }
+
+        builder.http1().keep_alive(self.http1_keep_alive);
+
+        if let Some(max) = self.max_headers {
+            builder.http1().max_headers(max);
+        }
+    }
+
+    /// Like `apply`, for HTTP/1 only connections.
+    pub(crate) fn apply_http1(&self, builder: &mut http1::Builder) {
+        builder.keep_alive(self.http1_keep_alive);
+
+        if let Some(max) = self.max_headers {
+            builder.max_headers(max);
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            http2_max_concurrent_streams: DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS,
+            http2_initial_stream_window_size: None,
+            http2_initial_connection_window_size: None,
+            http2_keep_alive: None,
+            http1_keep_alive: true,
+            max_uri_length: None,
+            max_headers: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::{HttpConfig, ServerConfig};
+    use crate::App;
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+    ))]
+    #[tokio::test]
+    async fn reuse_port() {
+        let config = ServerConfig::new().reuse_port(true);
+
+        let first = config.bind("127.0.0.1:0").await.unwrap();
+        let addr = first.local_addr().unwrap();
+
+        let second = config.bind(addr).await.unwrap();
+        assert_eq!(second.local_addr().unwrap(), addr);
+
+        // without reuse_port, binding the same address fails
+        assert!(ServerConfig::new().bind(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn socket_options() {
+        let config = ServerConfig::new()
+            .tcp_nodelay(true)
+            .tcp_keepalive(Duration::from_secs(30));
+
+        let listener = config.bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        assert!(!stream.nodelay().unwrap());
+
+        config.apply_socket(&stream).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert!(socket2::SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn http2_only() {
+        let config = ServerConfig::new().http2_only();
+
+        let listener = config.bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.get("/", || async { "ok" });
+        tokio::spawn(app.serve(listener, config));
+
+        // an HTTP/1 request is not a valid HTTP/2 preface, no HTTP/1 response is sent
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut buf = Vec::new();
+        let _ =
+            tokio::time::timeout(Duration::from_millis(500), stream.read_to_end(&mut buf)).await;
+        assert!(!buf.starts_with(b"HTTP/1.1"));
+
+        // prior knowledge HTTP/2 is served
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(
+            hyper_util::rt::TokioExecutor::new(),
+            hyper_util::rt::TokioIo::new(stream),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(conn);
+
+        let req = crate::http::Request::get("http://localhost/")
+            .body(http_body_util::Empty::<bytes::Bytes>::new())
+            .unwrap();
+        let resp = sender.send_request(req).await.unwrap();
+        assert_eq!(resp.status(), crate::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn http1_only_upgrades() {
+        use tokio::net::TcpListener;
+
+        use crate::proxy::ConnectTunnel;
+
+        // echo server
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = echo.accept().await.unwrap();
+            let (mut reader, mut writer) = socket.split();
+            tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        });
+
+        let config = ServerConfig::new().http1_only();
+        let listener = config.bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut tunnel = ConnectTunnel::new();
+        tunnel.allow(echo_addr.to_string());
+        let mut app = App::new();
+        app.middleware(tunnel);
+        tokio::spawn(app.serve(listener, config));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let connect = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", echo_addr);
+        stream.write_all(connect.as_bytes()).await.unwrap();
+
+        let mut buf = Vec::new();
+        while !buf.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            buf.push(byte[0]);
+        }
+        assert!(buf.starts_with(b"HTTP/1.1 200 OK"));
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut pong = [0; 4];
+        stream.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"ping");
+    }
+
+    #[tokio::test]
+    async fn header_read_timeout() {
+        let config = ServerConfig::new().header_read_timeout(Duration::from_millis(200));
+
+        let listener = config.bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.get("/", || async { "ok" });
+        tokio::spawn(app.serve(listener, config));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // send a header line every 100ms, never finishing the headers
+        let slow_headers = async {
+            stream.write_all(b"GET / HTTP/1.1\r\n").await?;
+            for i in 0.. {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                stream
+                    .write_all(format!("x-slow-{}: 1\r\n", i).as_bytes())
+                    .await?;
+            }
+            Ok::<_, std::io::Error>(())
+        };
+
+        tokio::time::timeout(Duration::from_secs(5), slow_headers)
+            .await
+            .expect("connection should be closed")
+            .ok();
+
+        let mut buf = Vec::new();
+        let _ = stream.read_to_end(&mut buf).await;
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn write_timeout() {
+        use futures::StreamExt;
+
+        use crate::test_helpers::LogCapture;
+        use crate::{Error, Streaming};
+
+        const CHUNK: usize = 64 * 1024;
+        const CHUNKS: usize = 1024;
+
+        let capture = LogCapture::new();
+
+        let config = ServerConfig::new().write_timeout(Duration::from_millis(200));
+        let listener = config.bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.get("/", || async {
+            let s = futures::stream::iter(0..CHUNKS).map(|_| Ok::<_, Error>(vec![0u8; CHUNK]));
+            Streaming::new(s, mime::APPLICATION_OCTET_STREAM)
+        });
+        tokio::spawn(app.serve(listener, config));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        // stall reading, socket buffers fill up and writes stop making progress
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let mut read = 0;
+        let mut buf = vec![0; CHUNK];
+        let drained = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Ok(n) = stream.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+                read += n;
+            }
+        })
+        .await;
+
+        assert!(drained.is_ok(), "connection should be closed");
+        assert!(read < CHUNK * CHUNKS);
+        capture.assert_logged(tracing::Level::DEBUG, "write timed out");
+    }
+
+    #[tokio::test]
+    async fn http1_keep_alive_disabled() {
+        let listener = ServerConfig::new().bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.http_config(HttpConfig::new().http1_keep_alive(false));
+        app.get("/", || async { "ok" });
+        tokio::spawn(app.serve(listener, ServerConfig::new()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        // closed after the response, without waiting for another request
+        let mut buf = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+            .await
+            .expect("connection should be closed")
+            .unwrap();
+
+        let resp = String::from_utf8_lossy(&buf).to_lowercase();
+        assert!(resp.starts_with("http/1.1 200 ok"), "{}", resp);
+        assert!(resp.contains("connection: close"), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn http2_max_concurrent_streams() {
+        use std::time::Instant;
+
+        use bytes::Bytes;
+        use http_body_util::Full;
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let mut app = App::with_http_config(HttpConfig::new().http2_max_concurrent_streams(1));
+        app.get("/", || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "ok"
+        });
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(app.serve_io(server, None));
+
+        let (sender, conn) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(client))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+
+        let request = |mut sender: hyper::client::conn::http2::SendRequest<Full<Bytes>>| async move {
+            let req = crate::http::Request::get("http://localhost/")
+                .body(Full::default())
+                .unwrap();
+            sender.send_request(req).await.unwrap().status()
+        };
+
+        let start = Instant::now();
+        let (first, second) = tokio::join!(request(sender.clone()), request(sender.clone()));
+
+        assert!(first.is_success() && second.is_success());
+        // the second stream waits for the first one
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn max_uri_length() {
+        use crate::test_helpers::TestClient;
+
+        let mut app = App::with_http_config(HttpConfig::new().max_uri_length(64));
+        app.get("/*path", || async { "ok" });
+        let client = TestClient::new(app);
+
+        let raw = format!(
+            "GET /{} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+            "a".repeat(64)
+        );
+        let resp = client.send_bytes(raw.as_bytes()).await;
+        assert!(resp.starts_with("HTTP/1.1 414 URI Too Long"), "{}", resp);
+
+        // answered before the rest of the head is sent, even before the line ends
+        for raw in [
+            format!("GET /{} HTTP/1.1\r\nhost: local", "a".repeat(64)),
+            format!("GET /{}", "a".repeat(128)),
+            format!("GET http://localhost/{} HTTP/1.1\r\n", "a".repeat(64)),
+        ] {
+            let resp =
+                tokio::time::timeout(Duration::from_secs(1), client.send_bytes(raw.as_bytes()))
+                    .await
+                    .expect("414 before the end of the head");
+            assert!(resp.starts_with("HTTP/1.1 414 URI Too Long"), "{}", resp);
+        }
+
+        // pipelined requests before it are answered first
+        let raw = format!(
+            "GET /first HTTP/1.1\r\nhost: localhost\r\n\r\nGET /{} HTTP/1.1\r\n",
+            "a".repeat(64)
+        );
+        let resp = client.send_bytes(raw.as_bytes()).await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK"), "{}", resp);
+        assert!(resp.contains("HTTP/1.1 414 URI Too Long"), "{}", resp);
+
+        let raw = format!(
+            "GET /{} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+            "a".repeat(32)
+        );
+        let resp = client.send_bytes(raw.as_bytes()).await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK"), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn max_headers() {
+        use crate::test_helpers::send_bytes;
+
+        let mut app = App::with_http_config(HttpConfig::new().max_headers(4));
+        app.get("/", || async { "ok" });
+
+        let headers: String = (0..8).map(|i| format!("x-h-{}: 1\r\n", i)).collect();
+        let raw = format!("GET / HTTP/1.1\r\nhost: localhost\r\n{}\r\n", headers);
+        let resp = send_bytes(app, raw.as_bytes()).await;
+        assert!(resp.starts_with("HTTP/1.1 431"), "{}", resp);
+    }
+}
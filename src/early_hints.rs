@@ -0,0 +1,419 @@
+//! Informational (1xx) interim responses, like `103 Early Hints`, for HTTP/1.1
+//! connections.
+//!
+//! hyper has no API to send informational responses, so the connection io is shared
+//! with the handler, which writes the interim response before hyper writes the final
+//! one. Interim responses are only written while the handler of the request runs and
+//! hyper has written nothing for it, so they can't corrupt the response.
+
+use std::convert::Infallible;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::http::{self, header, HeaderMap, HeaderValue};
+use crate::request::{FromRequest, RequestParts};
+use crate::Error;
+
+/// Connection io shared between hyper and `EarlyHints`.
+pub(crate) struct InterimIo<I> {
+    inner: Arc<Mutex<I>>,
+    open: Arc<OpenRequest>,
+}
+
+/// The request of a connection allowed to send interim responses.
+#[derive(Default)]
+struct OpenRequest {
+    /// Id of the open request, `0` for none, only changed with the io locked.
+    current: AtomicU64,
+    last: AtomicU64,
+}
+
+impl<I> InterimIo<I> {
+    pub(crate) fn new(io: I) -> Self {
+        InterimIo {
+            inner: Arc::new(Mutex::new(io)),
+            open: Arc::default(),
+        }
+    }
+
+    pub(crate) fn sender(&self) -> InterimSender
+    where
+        I: AsyncWrite + Unpin + Send + 'static,
+    {
+        InterimSender {
+            io: self.inner.clone(),
+            open: self.open.clone(),
+            request: 0,
+        }
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for InterimIo<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut io = self.inner.lock().unwrap();
+        Pin::new(&mut *io).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for InterimIo<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut io = self.inner.lock().unwrap();
+        // hyper is writing, too late for interim responses
+        self.open.current.store(0, Ordering::Release);
+        Pin::new(&mut *io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut io = self.inner.lock().unwrap();
+        Pin::new(&mut *io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut io = self.inner.lock().unwrap();
+        Pin::new(&mut *io).poll_shutdown(cx)
+    }
+}
+
+trait InterimWrite: Send {
+    fn poll_write_interim(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
+    fn poll_flush_interim(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+impl<I: AsyncWrite + Unpin + Send> InterimWrite for I {
+    fn poll_write_interim(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(self).poll_write(cx, buf)
+    }
+
+    fn poll_flush_interim(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(self).poll_flush(cx)
+    }
+}
+
+/// Handle to write interim responses on the connection, stored in request extensions.
+#[derive(Clone)]
+pub(crate) struct InterimSender {
+    io: Arc<Mutex<dyn InterimWrite>>,
+    open: Arc<OpenRequest>,
+    request: u64,
+}
+
+impl InterimSender {
+    /// Sender of a new request, open until `close` or hyper writes to the connection.
+    pub(crate) fn open(&self) -> InterimSender {
+        let _io = self.io.lock().unwrap();
+        let request = self.open.last.fetch_add(1, Ordering::Relaxed) + 1;
+        self.open.current.store(request, Ordering::Release);
+
+        InterimSender {
+            io: self.io.clone(),
+            open: self.open.clone(),
+            request,
+        }
+    }
+
+    /// Close the request once its handler returned.
+    pub(crate) fn close(&self) {
+        let _io = self.io.lock().unwrap();
+        let _ = self.open.current.compare_exchange(
+            self.request,
+            0,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+    }
+
+    fn is_open(&self) -> bool {
+        self.request != 0 && self.open.current.load(Ordering::Acquire) == self.request
+    }
+
+    async fn write_all(&self, buf: &[u8]) -> io::Result<()> {
+        let mut written = 0;
+        while written < buf.len() {
+            let n = std::future::poll_fn(|cx| {
+                let mut io = self.io.lock().unwrap();
+                if !self.is_open() {
+                    return Poll::Ready(Err(io::Error::other(
+                        "interim response after the final response started",
+                    )));
+                }
+                io.poll_write_interim(cx, &buf[written..])
+            })
+            .await?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            written += n;
+        }
+
+        std::future::poll_fn(|cx| self.io.lock().unwrap().poll_flush_interim(cx)).await
+    }
+}
+
+/// Send informational (1xx) responses before the final response, e.g. `100 Continue`
+/// once the handler decided to accept a request sent with `Expect: 100-continue`.
+///
+/// Interim responses are sent on HTTP/1.1 connections only, while the handler of the
+/// request runs and before the final response is written, elsewhere `send` fails.
+/// Middlewares get one with `Interim::of`.
+#[derive(Clone)]
+pub struct Interim {
+    sender: Option<InterimSender>,
+}
+
+impl Interim {
+    /// Interim response sender of the request.
+    pub fn of<B>(req: &http::Request<B>) -> Self {
+        let sender = if req.version() == http::Version::HTTP_11 {
+            req.extensions().get::<InterimSender>().cloned()
+        } else {
+            None
+        };
+
+        Interim { sender }
+    }
+
+    /// Whether interim responses can be sent for this request.
+    pub fn is_supported(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Send an interim response, `status` must be informational and not
+    /// `101 Switching Protocols`, which belongs to upgrades.
+    ///
+    /// hyper sends `100 Continue` by itself when a request expecting it has its body
+    /// read, so a handler sending one too should not mind the client seeing it twice.
+    pub async fn send(&self, status: http::StatusCode, headers: &HeaderMap) -> Result<(), Error> {
+        if !status.is_informational() || status == http::StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Error::Message(format!(
+                "invalid interim response status {}",
+                status
+            )));
+        }
+
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => {
+                return Err(Error::Message(
+                    "interim responses are not supported, e.g. on HTTP/2".to_string(),
+                ))
+            }
+        };
+
+        let mut buf = format!(
+            "HTTP/1.1 {} {}\r\n",
+            status.as_str(),
+            status.canonical_reason().unwrap_or("")
+        )
+        .into_bytes();
+        for (name, value) in headers {
+            buf.extend_from_slice(name.as_str().as_bytes());
+            buf.extend_from_slice(b": ");
+            buf.extend_from_slice(value.as_bytes());
+            buf.extend_from_slice(b"\r\n");
+        }
+        buf.extend_from_slice(b"\r\n");
+
+        sender.write_all(&buf).await?;
+
+        Ok(())
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for Interim {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(Interim::of(req))
+    }
+}
+
+/// Send `103 Early Hints` with `Link` headers before the final response, e.g.
+/// `</style.css>; rel=preload; as=style`, so browsers start preloading while the
+/// handler is still working.
+///
+/// Hints are sent on HTTP/1.1 connections only, elsewhere `send` does nothing, as
+/// hints are optional. Like `Interim::send`, it fails once the final response started.
+pub struct EarlyHints {
+    interim: Interim,
+}
+
+impl EarlyHints {
+    /// Whether hints can be sent for this request.
+    pub fn is_supported(&self) -> bool {
+        self.interim.is_supported()
+    }
+
+    pub async fn send<'a>(&self, links: impl IntoIterator<Item = &'a str>) -> Result<(), Error> {
+        let mut headers = HeaderMap::new();
+        for link in links {
+            let link = HeaderValue::from_str(link).map_err(http::Error::from)?;
+            headers.append(header::LINK, link);
+        }
+
+        if !self.is_supported() {
+            return Ok(());
+        }
+
+        self.interim
+            .send(http::StatusCode::EARLY_HINTS, &headers)
+            .await
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for EarlyHints {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(EarlyHints {
+            interim: Interim::of(req),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::{header, HeaderMap, StatusCode};
+    use crate::test_helpers::send_bytes;
+    use crate::App;
+
+    use super::{EarlyHints, Interim};
+
+    #[tokio::test]
+    async fn early_hints_before_response() {
+        let mut app = App::new();
+        app.get("/", |hints: EarlyHints| async move {
+            hints
+                .send(["</style.css>; rel=preload; as=style"])
+                .await
+                .unwrap();
+            "page"
+        });
+
+        let resp = send_bytes(
+            app,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert!(resp.starts_with(
+            "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style\r\n\r\nHTTP/1.1 200 OK\r\n"
+        ));
+        assert!(resp.ends_with("page"));
+    }
+
+    #[tokio::test]
+    async fn early_hints_http10_ignored() {
+        let mut app = App::new();
+        app.get("/", |hints: EarlyHints| async move {
+            assert!(!hints.is_supported());
+            hints.send(["</style.css>; rel=preload"]).await.unwrap();
+            "page"
+        });
+
+        let resp = send_bytes(app, b"GET / HTTP/1.0\r\nHost: localhost\r\n\r\n").await;
+
+        assert!(resp.starts_with("HTTP/1.0 200 OK\r\n"));
+    }
+
+    #[tokio::test]
+    async fn interim_before_response() {
+        let mut app = App::new();
+        app.get("/", |interim: Interim| async move {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::LINK, "</app.js>; rel=preload".parse().unwrap());
+            interim
+                .send(StatusCode::EARLY_HINTS, &headers)
+                .await
+                .unwrap();
+            assert!(interim.send(StatusCode::OK, &headers).await.is_err());
+            "page"
+        });
+
+        let resp = send_bytes(
+            app,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let hints = resp.find("HTTP/1.1 103 Early Hints\r\n").unwrap();
+        let ok = resp.find("HTTP/1.1 200 OK\r\n").unwrap();
+        assert!(hints < ok);
+        assert!(resp.contains("link: </app.js>; rel=preload\r\n\r\nHTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn interim_after_handler_rejected() {
+        use crate::{Error, Streaming};
+
+        let mut app = App::new();
+        app.get("/", |interim: Interim| async move {
+            // sent from the body, after the handler returned
+            let body = futures::stream::once(async move {
+                let ret = interim
+                    .send(StatusCode::EARLY_HINTS, &HeaderMap::new())
+                    .await;
+                Ok::<_, Error>(format!("rejected={}", ret.is_err()).into_bytes())
+            });
+            Streaming::new(body, mime::TEXT_PLAIN)
+        });
+
+        let resp = send_bytes(
+            app,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(!resp.contains("103 Early Hints"));
+        assert!(resp.contains("rejected=true"));
+    }
+
+    #[tokio::test]
+    async fn interim_http2_rejected() {
+        use http_body_util::{BodyExt, Empty};
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let mut app = App::new();
+        app.get("/", |interim: Interim| async move {
+            let ret = interim
+                .send(StatusCode::EARLY_HINTS, &HeaderMap::new())
+                .await;
+            format!(
+                "supported={} rejected={}",
+                interim.is_supported(),
+                ret.is_err()
+            )
+        });
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(app.serve_io(server, None));
+
+        let (mut sender, conn) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(client))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+
+        let req = crate::http::Request::get("http://localhost/")
+            .body(Empty::<bytes::Bytes>::new())
+            .unwrap();
+        let resp = sender.send_request(req).await.unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "supported=false rejected=true");
+    }
+}
@@ -2,6 +2,7 @@ use std::future::Future;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
+use crate::extracts::render_rejection;
 use crate::request::FromRequest;
 use crate::response::IntoResponse;
 use crate::router::Router;
@@ -113,7 +114,7 @@ macro_rules! impl_handler {
                 $(
                     let $ty = match $ty::from_request(&mut req).await {
                         Ok(value) => value,
-                        Err(rejection) => return rejection.into_response(),
+                        Err(rejection) => return render_rejection(&req, rejection.into_response()),
                     };
                 )*
 
@@ -142,6 +143,93 @@ impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
 impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15);
 impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16);
 
+/// Sync handler running on the blocking thread pool, created by [`blocking`].
+#[derive(Clone)]
+pub struct Blocking<F> {
+    f: F,
+}
+
+/// Wrap a sync handler, to run it by `tokio::task::spawn_blocking`.
+///
+/// Use it for CPU-bound or blocking work, so the async runtime is not stalled.
+/// Extractors run before entering the blocking section, so the body must be taken by
+/// extractors like `BytesBody` or `Json`, it can not be read inside the handler.
+pub fn blocking<F>(f: F) -> Blocking<F> {
+    Blocking { f }
+}
+
+async fn run_blocking<F, Res>(f: F) -> Response
+where
+    F: FnOnce() -> Res + Send + 'static,
+    Res: IntoResponse + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res.into_response(),
+        Err(e) => {
+            tracing::error!("blocking handler failed, {:?}", e);
+            hyper::StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[crate::async_trait]
+impl<F, Res> Handler<()> for Blocking<F>
+where
+    F: FnOnce() -> Res + Clone + Send + 'static,
+    Res: IntoResponse + Send + 'static,
+{
+    async fn call(self, _req: Request) -> Response {
+        run_blocking(self.f).await
+    }
+}
+
+macro_rules! impl_blocking_handler {
+    ($($ty: ident),+) => {
+        #[crate::async_trait]
+        #[allow(non_snake_case)]
+        impl<F, Res, $($ty,)*> Handler<($($ty,)*)> for Blocking<F>
+        where
+            F: FnOnce($($ty,)*) -> Res + Clone + Send + 'static,
+            Res: IntoResponse + Send + 'static,
+            $( $ty: FromRequest + Send + 'static,)*
+        {
+            async fn call(self, req: Request) -> Response {
+                let (parts, body) = req.into_parts();
+                let mut req = hyper::Request::from_parts(parts, Some(body));
+
+                $(
+                    let $ty = match $ty::from_request(&mut req).await {
+                        Ok(value) => value,
+                        Err(rejection) => return render_rejection(&req, rejection.into_response()),
+                    };
+                )*
+
+                let f = self.f;
+
+                run_blocking(move || f($($ty,)*)).await
+            }
+        }
+    };
+}
+
+impl_blocking_handler!(T1);
+impl_blocking_handler!(T1, T2);
+impl_blocking_handler!(T1, T2, T3);
+impl_blocking_handler!(T1, T2, T3, T4);
+impl_blocking_handler!(T1, T2, T3, T4, T5);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15);
+impl_blocking_handler!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16);
+
+#[derive(Clone)]
 pub(crate) struct RouterEndpoint {
     router: Arc<Router>,
 }
@@ -164,3 +252,31 @@ impl std::fmt::Debug for RouterEndpoint {
         write!(f, "RouterEndpoint{{ router: {:?} }}", self.router)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::http::StatusCode;
+    use crate::test_helpers::{post, send};
+    use crate::{blocking, App, BytesBody};
+
+    #[tokio::test]
+    async fn blocking_handler() {
+        let mut app = App::new();
+        app.post(
+            "/",
+            blocking(|body: BytesBody| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                body.value()
+                    .iter()
+                    .map(|b| *b as u64)
+                    .sum::<u64>()
+                    .to_string()
+            }),
+        );
+
+        let resp = send(app, post("/", "text/plain", vec![1, 2, 3])).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "6");
+    }
+}
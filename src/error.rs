@@ -1,3 +1,5 @@
+use hyper::http;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("hyper error")]
@@ -34,9 +36,134 @@ pub enum Error {
     MissingCookie { name: String },
     #[error("missing header {name:?}")]
     MissingHeader { name: String },
+    #[error("request body has been taken")]
+    BodyBeenTaken,
+    #[error("read request body timeout")]
+    BodyReadTimeout,
+    #[error("request body too large")]
+    BodyTooLarge,
     #[cfg(feature = "tls")]
     #[error("tls error")]
     TlsError(#[from] tokio_rustls::rustls::Error),
+    #[error("{0}")]
+    Web(Box<dyn WebError>),
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Annotate the error with context, keeping it as the source.
+    pub fn context(self, context: impl ToString) -> Error {
+        Error::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error as `E`, looking through context and into application errors.
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + 'static,
+    {
+        match self {
+            Error::Context { source, .. } => source.downcast_ref(),
+            Error::Web(e) => {
+                let e: &(dyn std::error::Error + 'static) = e.as_ref();
+                e.downcast_ref()
+            }
+            _ => {
+                let e: &(dyn std::error::Error + 'static) = self;
+                e.downcast_ref()
+            }
+        }
+    }
+
+    /// Format the error with its whole source chain, e.g. `load config: io error: not found`.
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+
+        let mut source = std::error::Error::source(self);
+        while let Some(e) = source {
+            report.push_str(": ");
+            report.push_str(&e.to_string());
+            source = e.source();
+        }
+
+        report
+    }
+}
+
+/// Application error deciding its own response, e.g. `409 Conflict` with a JSON body.
+///
+/// Handlers can return it directly, or as `Error` by `?`. Use `App::error_handler` to
+/// render it differently in one place.
+pub trait WebError: std::error::Error + Send + Sync + 'static {
+    fn status_code(&self) -> http::StatusCode {
+        http::StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    /// JSON body of the response, default is `{"error": "<message>"}`.
+    fn error_body(&self) -> serde_json::Value {
+        serde_json::json!({ "error": self.to_string() })
+    }
+}
+
+impl<E> From<E> for Error
+where
+    E: WebError,
+{
+    fn from(e: E) -> Self {
+        Error::Web(Box::new(e))
+    }
+}
+
+/// Add context to the error of a `Result`, see `Error::context`.
+pub trait ErrorContext<T> {
+    fn context(self, context: impl ToString) -> Result<T, Error>;
+
+    fn with_context<C, F>(self, f: F) -> Result<T, Error>
+    where
+        C: ToString,
+        F: FnOnce() -> C;
+}
+
+impl<T, E> ErrorContext<T> for Result<T, E>
+where
+    E: Into<Error>,
+{
+    fn context(self, context: impl ToString) -> Result<T, Error> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C, F>(self, f: F) -> Result<T, Error>
+    where
+        C: ToString,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+impl From<crate::extracts::BodyBeenTaken> for Error {
+    fn from(_: crate::extracts::BodyBeenTaken) -> Self {
+        Error::BodyBeenTaken
+    }
+}
+
+impl From<crate::extracts::ReadBodyRejection> for Error {
+    fn from(e: crate::extracts::ReadBodyRejection) -> Self {
+        match e {
+            crate::extracts::ReadBodyRejection::BodyBeenTaken(e) => e.into(),
+            crate::extracts::ReadBodyRejection::ReadFailed(e) => e.into(),
+            crate::extracts::ReadBodyRejection::DecodeFailed(e) => e.context("decode body"),
+            crate::extracts::ReadBodyRejection::Timeout => Error::BodyReadTimeout,
+            crate::extracts::ReadBodyRejection::TooLarge => Error::BodyTooLarge,
+        }
+    }
 }
 
 impl<'a> From<&'a str> for Error {
@@ -1,31 +1,112 @@
 use std::{
+    collections::HashMap,
     convert::Infallible,
     net::SocketAddr,
     ops::{Deref, DerefMut},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use bytes::Bytes;
-use http_body_util::BodyExt;
-use hyper::StatusCode;
+use hyper::{Method, StatusCode};
 use mime::Mime;
 use serde::de::DeserializeOwned;
 
 use crate::{
-    middleware::WithState,
-    request::{FromRequest, RequestCtx, RequestParts},
+    middleware::{Timings, WithState},
+    request::{
+        body_taken, read_body, BodyLimit, BodyStream, ContentSniffing, FromRequest, RequestCtx,
+        RequestParts,
+    },
     response::IntoResponse,
-    BytesBody, Form, Json, LieResponse, Response,
+    BytesBody, Form, Json, JsonOrForm, LieResponse, Response,
 };
 
+/// Well-known rejection categories, whose responses can be customized by `App::rejection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectionKind {
+    /// Request body exceeds the limit.
+    BodyTooLarge,
+    /// Unexpected `Content-Type` of request.
+    BadContentType,
+    /// Path params, query string or body decode failed.
+    DecodeFailed,
+}
+
+/// Detail of a rejection, attached to the extensions of the default rejection response.
+#[derive(Debug, Clone)]
+pub struct Rejected {
+    kind: RejectionKind,
+    message: String,
+}
+
+impl Rejected {
+    pub(crate) fn new(kind: RejectionKind, message: impl ToString) -> Self {
+        Rejected {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> RejectionKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn into_response(self, resp: impl Into<Response>) -> Response {
+        let mut resp = resp.into();
+        resp.extensions_mut().insert(self);
+        resp
+    }
+}
+
+type RejectionBuilder = Arc<dyn Fn(&Rejected) -> Response + Send + Sync>;
+
+/// Registry of custom rejection responses, set by `App::rejection`.
+///
+/// Shared behind an `Arc`, as it is cloned into every request with the router data.
+#[derive(Clone, Default)]
+pub(crate) struct Rejections {
+    builders: Arc<HashMap<RejectionKind, RejectionBuilder>>,
+}
+
+impl Rejections {
+    pub(crate) fn insert<F, R>(&mut self, kind: RejectionKind, builder: F)
+    where
+        F: Fn(&Rejected) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Arc::make_mut(&mut self.builders).insert(
+            kind,
+            Arc::new(move |rejected| builder(rejected).into_response()),
+        );
+    }
+}
+
+/// Replace the rejection response with the custom one when registered.
+pub(crate) fn render_rejection(req: &RequestParts, resp: Response) -> Response {
+    let builder = resp.extensions().get::<Rejected>().and_then(|rejected| {
+        req.extensions()
+            .get::<Rejections>()
+            .and_then(|rejections| rejections.builders.get(&rejected.kind))
+            .map(|builder| (builder, rejected))
+    });
+
+    match builder {
+        Some((builder, rejected)) => builder(rejected),
+        None => resp,
+    }
+}
+
 pub struct ParamsRejection(params_de::Error);
 
 impl IntoResponse for ParamsRejection {
     fn into_response(self) -> Response {
-        LieResponse::new(
-            StatusCode::BAD_REQUEST,
-            format!("path param parse error, {}", self.0),
-        )
-        .into()
+        let message = format!("path param parse error, {}", self.0);
+        Rejected::new(RejectionKind::DecodeFailed, &message)
+            .into_response(LieResponse::new(StatusCode::BAD_REQUEST, message))
     }
 }
 
@@ -37,7 +118,10 @@ impl<T> PathParam<T>
 where
     T: DeserializeOwned,
 {
-    pub(crate) fn from_params(params: &pathrouter::Params) -> Result<Self, ParamsRejection> {
+    /// Deserialize `params`, given in route order.
+    pub(crate) fn from_params<'a>(
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, ParamsRejection> {
         params_de::from_params::<T>(params)
             .map(|value| PathParam { value })
             .map_err(ParamsRejection)
@@ -60,10 +144,39 @@ where
     type Rejection = ParamsRejection;
 
     async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
-        let empty = pathrouter::Params::new();
-        let params = RequestCtx::extract_params(req).unwrap_or(&empty);
+        PathParam::from_params(RequestCtx::ordered_params(req))
+    }
+}
+
+/// Typed path params, a single param as a scalar, e.g. `Path<u64>` for `/users/:id`, or
+/// several params as a tuple in route order, e.g. `Path<(String, u32)>`.
+///
+/// Use `PathParam` to deserialize params by name into a struct.
+pub struct Path<T> {
+    value: T,
+}
+
+impl<T> Path<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+#[crate::async_trait]
+impl<T> FromRequest for Path<T>
+where
+    T: DeserializeOwned,
+{
+    type Rejection = ParamsRejection;
 
-        PathParam::from_params(params)
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        PathParam::<T>::from_request(req).await.map(|param| Path {
+            value: param.take(),
+        })
     }
 }
 
@@ -121,6 +234,585 @@ impl IntoResponse for StateRejection {
     }
 }
 
+/// A value cloned from the request extensions, inserted by a middleware or by
+/// `App::data`.
+pub struct Extension<T> {
+    value: T,
+}
+
+impl<T> Extension<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for Extension<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+#[crate::async_trait]
+impl<T> FromRequest for Extension<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Rejection = ExtensionRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<T>()
+            .cloned()
+            .map(|value| Extension { value })
+            .ok_or(ExtensionRejection {
+                name: std::any::type_name::<T>(),
+            })
+    }
+}
+
+/// The extension is absent, answered with `500 Internal Server Error`.
+#[derive(Debug, Clone)]
+pub struct ExtensionRejection {
+    name: &'static str,
+}
+
+impl IntoResponse for ExtensionRejection {
+    fn into_response(self) -> Response {
+        tracing::error!("missing request extension {}", self.name);
+        LieResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "can not extract Extension",
+        )
+        .into()
+    }
+}
+
+/// Subdomain captured by a `*.example.com` pattern of `middleware::HostRouter`, e.g.
+/// `acme` of `acme.example.com`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subdomain {
+    value: String,
+}
+
+impl Subdomain {
+    pub(crate) fn new(value: impl Into<String>) -> Self {
+        Subdomain {
+            value: value.into(),
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn take(self) -> String {
+        self.value
+    }
+}
+
+impl Deref for Subdomain {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for Subdomain {
+    type Rejection = ExtensionRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<Subdomain>()
+            .cloned()
+            .ok_or(ExtensionRejection {
+                name: std::any::type_name::<Subdomain>(),
+            })
+    }
+}
+
+/// Authenticate a request into a user, registered by `App::authenticator`.
+#[crate::async_trait]
+pub trait Authenticator: Send + Sync + 'static {
+    type User: Send + 'static;
+
+    /// Inspect the request, e.g. cookie, bearer or basic credentials. The error
+    /// response, e.g. `401` or a redirect to login, is returned as is.
+    async fn authenticate(&self, req: &RequestParts) -> Result<Self::User, Response>;
+}
+
+pub(crate) struct AuthenticatorRef<U> {
+    inner: Arc<dyn Authenticator<User = U>>,
+}
+
+impl<U> AuthenticatorRef<U> {
+    pub(crate) fn new<A>(authenticator: A) -> Self
+    where
+        A: Authenticator<User = U>,
+    {
+        AuthenticatorRef {
+            inner: Arc::new(authenticator),
+        }
+    }
+}
+
+impl<U> Clone for AuthenticatorRef<U> {
+    fn clone(&self) -> Self {
+        AuthenticatorRef {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// The authenticated user, produced by the `Authenticator` registered for `U`.
+pub struct Auth<U> {
+    user: U,
+}
+
+impl<U> Auth<U> {
+    pub fn value(&self) -> &U {
+        &self.user
+    }
+
+    pub fn take(self) -> U {
+        self.user
+    }
+}
+
+impl<U> Deref for Auth<U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+#[crate::async_trait]
+impl<U> FromRequest for Auth<U>
+where
+    U: Send + 'static,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let authenticator = req
+            .extensions()
+            .get::<AuthenticatorRef<U>>()
+            .cloned()
+            .ok_or_else(|| {
+                LieResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "can not extract Auth, no authenticator",
+                )
+                .into_hyper_response()
+            })?;
+
+        let user = authenticator.inner.authenticate(req).await?;
+
+        Ok(Auth { user })
+    }
+}
+
+/// A typed header decoded by the `headers` crate, e.g. `TypedHeader<headers::ContentType>`.
+pub struct TypedHeader<T> {
+    value: T,
+}
+
+impl<T> TypedHeader<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for TypedHeader<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+#[crate::async_trait]
+impl<T> FromRequest for TypedHeader<T>
+where
+    T: headers::Header,
+{
+    type Rejection = TypedHeaderRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let name = T::name();
+        let mut values = req.headers().get_all(name).iter().peekable();
+        if values.peek().is_none() {
+            return Err(TypedHeaderRejection::Missing(name));
+        }
+
+        T::decode(&mut values)
+            .map(|value| TypedHeader { value })
+            .map_err(|_| TypedHeaderRejection::Invalid(name))
+    }
+}
+
+/// Missing or malformed typed header, answered with `400 Bad Request`.
+#[derive(Debug, Clone)]
+pub enum TypedHeaderRejection {
+    Missing(&'static hyper::header::HeaderName),
+    Invalid(&'static hyper::header::HeaderName),
+}
+
+impl IntoResponse for TypedHeaderRejection {
+    fn into_response(self) -> Response {
+        let message = match self {
+            TypedHeaderRejection::Missing(name) => format!("missing header {}", name),
+            TypedHeaderRejection::Invalid(name) => format!("invalid header {}", name),
+        };
+
+        Rejected::new(RejectionKind::DecodeFailed, &message)
+            .into_response(LieResponse::new(StatusCode::BAD_REQUEST, message))
+    }
+}
+
+/// Every occurrence of a repeated typed header, each decoded on its own, e.g.
+/// `AllOf<headers::Via>`. An absent header gives no values.
+pub struct AllOf<T> {
+    values: Vec<T>,
+}
+
+impl<T> AllOf<T> {
+    pub fn value(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn take(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T> Deref for AllOf<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+#[crate::async_trait]
+impl<T> FromRequest for AllOf<T>
+where
+    T: headers::Header,
+{
+    type Rejection = TypedHeaderRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let name = T::name();
+
+        let values = req
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| {
+                T::decode(&mut std::iter::once(value))
+                    .map_err(|_| TypedHeaderRejection::Invalid(name))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(AllOf { values })
+    }
+}
+
+/// Name of a header extracted by `HeaderValues`.
+///
+/// ```
+/// struct XForwardedFor;
+///
+/// impl lieweb::extracts::HeaderKey for XForwardedFor {
+///     const NAME: &'static str = "x-forwarded-for";
+/// }
+/// ```
+pub trait HeaderKey {
+    /// Lowercase header name.
+    const NAME: &'static str;
+}
+
+/// Raw values of every occurrence of the header named by `K`, in order. An absent header
+/// gives no values.
+pub struct HeaderValues<K> {
+    values: Vec<hyper::header::HeaderValue>,
+    _key: std::marker::PhantomData<fn() -> K>,
+}
+
+impl<K> HeaderValues<K> {
+    pub fn value(&self) -> &[hyper::header::HeaderValue] {
+        &self.values
+    }
+
+    pub fn take(self) -> Vec<hyper::header::HeaderValue> {
+        self.values
+    }
+
+    /// Values which are visible ASCII, others are skipped.
+    pub fn to_strs(&self) -> Vec<&str> {
+        self.values.iter().filter_map(|v| v.to_str().ok()).collect()
+    }
+}
+
+#[crate::async_trait]
+impl<K> FromRequest for HeaderValues<K>
+where
+    K: HeaderKey,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let values = req.headers().get_all(K::NAME).iter().cloned().collect();
+
+        Ok(HeaderValues {
+            values,
+            _key: std::marker::PhantomData,
+        })
+    }
+}
+
+/// Scheme and credentials of the `Authorization` header, e.g. `Signature` and
+/// `keyId="k1",signature="..."`, for custom authentication schemes.
+///
+/// A missing or malformed header is rejected with `401 Unauthorized`, challenging with the
+/// `AuthChallenge` of the route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authorization {
+    scheme: String,
+    credentials: String,
+}
+
+impl Authorization {
+    pub(crate) fn from_parts(req: &RequestParts) -> Result<Self, AuthorizationRejection> {
+        let rejection = |missing| AuthorizationRejection {
+            missing,
+            challenge: req
+                .extensions()
+                .get::<AuthChallenge>()
+                .map(|challenge| challenge.0.clone()),
+        };
+
+        let value = req
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .ok_or_else(|| rejection(true))?;
+        let value = value.to_str().map_err(|_| rejection(false))?;
+
+        let (scheme, credentials) = value
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| rejection(false))?;
+        let credentials = credentials.trim();
+        if scheme.is_empty() || credentials.is_empty() {
+            return Err(rejection(false));
+        }
+
+        Ok(Authorization {
+            scheme: scheme.to_string(),
+            credentials: credentials.to_string(),
+        })
+    }
+
+    /// The scheme as sent, compare it case insensitively, see `is_scheme`.
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn credentials(&self) -> &str {
+        &self.credentials
+    }
+
+    /// Whether the scheme is `scheme`, ignoring ASCII case.
+    pub fn is_scheme(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+
+    /// Decoded user id and password of the `Basic` scheme.
+    pub fn basic(&self) -> Option<(String, String)> {
+        use headers::authorization::{Basic, Credentials};
+
+        if !self.is_scheme("basic") {
+            return None;
+        }
+
+        let value =
+            hyper::header::HeaderValue::from_str(&format!("Basic {}", self.credentials)).ok()?;
+        let basic = Basic::decode(&value)?;
+
+        Some((basic.username().to_string(), basic.password().to_string()))
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for Authorization {
+    type Rejection = AuthorizationRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Authorization::from_parts(req)
+    }
+}
+
+/// Challenge sent in `WWW-Authenticate` when `Authorization` rejects a request, e.g.
+/// `Signature realm="api"`. `Authorization` accepts any scheme, so none is sent without it.
+///
+/// Set it for all routes with `App::data`, or for a single route with `RouteHandle::data`.
+#[derive(Debug, Clone)]
+pub struct AuthChallenge(pub hyper::header::HeaderValue);
+
+/// Missing or malformed `Authorization` header, answered with `401 Unauthorized` and
+/// the `AuthChallenge` of the route in `WWW-Authenticate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizationRejection {
+    missing: bool,
+    challenge: Option<hyper::header::HeaderValue>,
+}
+
+impl AuthorizationRejection {
+    /// Whether the header is missing, rather than malformed.
+    pub fn is_missing(&self) -> bool {
+        self.missing
+    }
+}
+
+impl IntoResponse for AuthorizationRejection {
+    fn into_response(self) -> Response {
+        let message = match self.missing {
+            true => "Missing authorization",
+            false => "Malformed authorization",
+        };
+
+        let mut resp = LieResponse::new(StatusCode::UNAUTHORIZED, message);
+        if let Some(challenge) = self.challenge {
+            resp = resp.insert_header(hyper::header::WWW_AUTHENTICATE, challenge);
+        }
+        resp.into()
+    }
+}
+
+/// Token of the `Authorization: Bearer <token>` header.
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    token: String,
+}
+
+impl BearerToken {
+    pub(crate) fn from_parts(req: &RequestParts) -> Result<Self, BearerRejection> {
+        let authorization = Authorization::from_parts(req).map_err(|e| match e.is_missing() {
+            true => BearerRejection::Missing,
+            false => BearerRejection::Malformed,
+        })?;
+        if !authorization.is_scheme("bearer") {
+            return Err(BearerRejection::Malformed);
+        }
+
+        Ok(BearerToken {
+            token: authorization.credentials,
+        })
+    }
+
+    pub fn value(&self) -> &str {
+        &self.token
+    }
+
+    pub fn take(self) -> String {
+        self.token
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for BearerToken {
+    type Rejection = BearerRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        BearerToken::from_parts(req)
+    }
+}
+
+/// Missing or malformed bearer token, answered with `401 Unauthorized`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BearerRejection {
+    Missing,
+    Malformed,
+}
+
+impl IntoResponse for BearerRejection {
+    fn into_response(self) -> Response {
+        let message = match self {
+            BearerRejection::Missing => "Missing bearer token",
+            BearerRejection::Malformed => "Malformed bearer token",
+        };
+
+        LieResponse::new(StatusCode::UNAUTHORIZED, message)
+            .insert_header(hyper::header::WWW_AUTHENTICATE, "Bearer")
+            .into()
+    }
+}
+
+/// Cookies of all `Cookie` headers, in order. Never fails, malformed pairs are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct Cookies {
+    cookies: Vec<cookie::Cookie<'static>>,
+}
+
+impl Cookies {
+    pub(crate) fn from_headers(headers: &hyper::HeaderMap) -> Self {
+        let cookies = headers
+            .get_all(hyper::header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(cookie::Cookie::split_parse)
+            .flatten()
+            .map(|cookie| cookie.into_owned())
+            .collect();
+
+        Cookies { cookies }
+    }
+
+    /// Value of the first cookie named `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value())
+    }
+
+    /// Name and value of each cookie.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies
+            .iter()
+            .map(|cookie| (cookie.name(), cookie.value()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for Cookies {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(Cookies::from_headers(req.headers()))
+    }
+}
+
 pub struct RemoteAddr {
     addr: Option<SocketAddr>,
 }
@@ -131,12 +823,114 @@ impl RemoteAddr {
     }
 }
 
+/// Methods registered for the matched path.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedMethods {
+    methods: Arc<[Method]>,
+}
+
+impl AllowedMethods {
+    pub(crate) fn new(methods: impl Iterator<Item = Method>) -> Self {
+        let mut methods: Vec<Method> = methods.collect();
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        AllowedMethods {
+            methods: methods.into(),
+        }
+    }
+
+    pub fn value(&self) -> &[Method] {
+        &self.methods
+    }
+
+    pub fn contains(&self, method: &Method) -> bool {
+        self.methods.contains(method)
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for AllowedMethods {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(RequestCtx::allowed_methods(req)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Start time of the request, stamped by the `RequestTimer` middleware.
+#[derive(Debug, Clone, Copy)]
+pub struct StartTime {
+    instant: Instant,
+    system_time: SystemTime,
+}
+
+impl StartTime {
+    pub(crate) fn new(instant: Instant, system_time: SystemTime) -> Self {
+        StartTime {
+            instant,
+            system_time,
+        }
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+
+    pub fn system_time(&self) -> SystemTime {
+        self.system_time
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.instant.elapsed()
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for StartTime {
+    type Rejection = StartTimeRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .get::<StartTime>()
+            .copied()
+            .ok_or(StartTimeRejection)
+    }
+}
+
+pub struct StartTimeRejection;
+
+impl IntoResponse for StartTimeRejection {
+    fn into_response(self) -> Response {
+        LieResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "can not extract StartTime",
+        )
+        .into()
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for Timings {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(Timings::get(req))
+    }
+}
+
+/// Query string deserialized into `T`.
+///
+/// An absent query is deserialized as an empty one, so `Option` fields and
+/// `#[serde(default)]` decide what is required, and missing required fields are rejected
+/// with `400 Bad Request`.
 #[derive(Default)]
-pub struct Query<T: Default> {
+pub struct Query<T> {
     value: T,
 }
 
-impl<T: Default> Query<T> {
+impl<T> Query<T> {
     pub fn value(&self) -> &T {
         &self.value
     }
@@ -149,45 +943,194 @@ impl<T: Default> Query<T> {
 #[crate::async_trait]
 impl<T> FromRequest for Query<T>
 where
-    T: DeserializeOwned + Default,
+    T: DeserializeOwned,
 {
     type Rejection = QueryRejection;
 
-    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
-        match req.uri().query() {
-            Some(query) => serde_urlencoded::from_str::<T>(query)
-                .map(|value| Query { value })
-                .map_err(QueryRejection::from),
-            None => Ok(Default::default()),
-        }
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        serde_urlencoded::from_str::<T>(req.uri().query().unwrap_or_default())
+            .map(|value| Query { value })
+            .map_err(QueryRejection::from)
+    }
+}
+
+/// Percent-decoded query pairs in their original order, keeping duplicates, e.g. for
+/// signature base strings. An absent query gives no pairs.
+pub struct QueryPairs {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryPairs {
+    pub fn value(&self) -> &[(String, String)] {
+        &self.pairs
+    }
+
+    pub fn take(self) -> Vec<(String, String)> {
+        self.pairs
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for QueryPairs {
+    type Rejection = QueryRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let pairs = serde_urlencoded::from_str(req.uri().query().unwrap_or_default())?;
+
+        Ok(QueryPairs { pairs })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum QueryRejection {
+    #[error("decode query string error")]
+    DecodeFailed(#[from] serde_urlencoded::de::Error),
+}
+
+impl IntoResponse for QueryRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::DecodeFailed(e) => {
+                tracing::error!("QueryRejection::DecodeFailed: {:?}", e);
+                Rejected::new(RejectionKind::DecodeFailed, e)
+                    .into_response(LieResponse::with_status(StatusCode::BAD_REQUEST))
+            }
+        }
+    }
+}
+
+/// Path params and query string deserialized into one struct, e.g. `id` and `include`
+/// of `/users/:id?include=posts`.
+///
+/// Path params take precedence, query pairs with the same name as a path param are
+/// ignored.
+pub struct PathAndQuery<T> {
+    value: T,
+}
+
+impl<T> PathAndQuery<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+#[crate::async_trait]
+impl<T> FromRequest for PathAndQuery<T>
+where
+    T: DeserializeOwned,
+{
+    type Rejection = QueryRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let mut pairs: Vec<(String, String)> = Vec::new();
+
+        let params = RequestCtx::extract_params(req);
+        if let Some(params) = params {
+            pairs.extend(
+                params
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string())),
+            );
+        }
+
+        if let Some(query) = req.uri().query() {
+            let query: Vec<(String, String)> = serde_urlencoded::from_str(query)?;
+            pairs.extend(
+                query
+                    .into_iter()
+                    .filter(|(k, _)| params.and_then(|p| p.find(k)).is_none()),
+            );
+        }
+
+        // re-encode so values are parsed the same way as `Query`
+        let merged = serde_urlencoded::to_string(&pairs).expect("encode string pairs");
+
+        serde_urlencoded::from_str::<T>(&merged)
+            .map(|value| PathAndQuery { value })
+            .map_err(QueryRejection::from)
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for RemoteAddr {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let addr = RequestCtx::extract_remote_addr(req);
+
+        Ok(RemoteAddr { addr })
+    }
+}
+
+/// Conditional request headers, absent or malformed headers are left `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Conditionals {
+    if_match: Option<headers::IfMatch>,
+    if_none_match: Option<headers::IfNoneMatch>,
+    if_modified_since: Option<headers::IfModifiedSince>,
+    if_unmodified_since: Option<headers::IfUnmodifiedSince>,
+    if_range: Option<headers::IfRange>,
+    range: Option<headers::Range>,
+}
+
+impl Conditionals {
+    pub fn from_headers(headers: &hyper::HeaderMap) -> Self {
+        use headers::HeaderMapExt;
+
+        Conditionals {
+            if_match: headers.typed_get(),
+            if_none_match: headers.typed_get(),
+            if_modified_since: headers.typed_get(),
+            if_unmodified_since: headers.typed_get(),
+            if_range: headers.typed_get(),
+            range: headers.typed_get(),
+        }
+    }
+
+    pub fn if_match(&self) -> Option<&headers::IfMatch> {
+        self.if_match.as_ref()
+    }
+
+    pub fn if_none_match(&self) -> Option<&headers::IfNoneMatch> {
+        self.if_none_match.as_ref()
+    }
+
+    pub fn if_modified_since(&self) -> Option<&headers::IfModifiedSince> {
+        self.if_modified_since.as_ref()
+    }
+
+    pub fn if_unmodified_since(&self) -> Option<&headers::IfUnmodifiedSince> {
+        self.if_unmodified_since.as_ref()
+    }
+
+    pub fn if_range(&self) -> Option<&headers::IfRange> {
+        self.if_range.as_ref()
+    }
+
+    pub fn range(&self) -> Option<&headers::Range> {
+        self.range.as_ref()
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum QueryRejection {
-    #[error("decode query string error")]
-    DecodeFailed(#[from] serde_urlencoded::de::Error),
-}
+#[crate::async_trait]
+impl FromRequest for Conditionals {
+    type Rejection = Infallible;
 
-impl IntoResponse for QueryRejection {
-    fn into_response(self) -> Response {
-        match self {
-            Self::DecodeFailed(e) => {
-                tracing::error!("QueryRejection::DecodeFailed: {:?}", e);
-                LieResponse::with_status(StatusCode::BAD_REQUEST).into()
-            }
-        }
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(Conditionals::from_headers(req.headers()))
     }
 }
 
 #[crate::async_trait]
-impl FromRequest for RemoteAddr {
+impl FromRequest for hyper::http::Uri {
     type Rejection = Infallible;
 
     async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
-        let addr = RequestCtx::extract_remote_addr(req);
-
-        Ok(RemoteAddr { addr })
+        Ok(req.uri().clone())
     }
 }
 
@@ -207,6 +1150,10 @@ impl FromRequest for crate::Request {
     type Rejection = BodyBeenTaken;
 
     async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        if body_taken(req) {
+            return Err(BodyBeenTaken);
+        }
+
         let empty = hyper::Request::default();
         let req = std::mem::replace(req, empty);
 
@@ -223,6 +1170,22 @@ impl FromRequest for crate::Request {
 pub enum ReadBodyRejection {
     BodyBeenTaken(BodyBeenTaken),
     ReadFailed(hyper::Error),
+    /// Decoding a `Content-Encoding` body failed.
+    DecodeFailed(crate::Error),
+    Timeout,
+    TooLarge,
+}
+
+impl From<hyper::Error> for ReadBodyRejection {
+    fn from(e: hyper::Error) -> Self {
+        ReadBodyRejection::ReadFailed(e)
+    }
+}
+
+impl From<BodyBeenTaken> for ReadBodyRejection {
+    fn from(e: BodyBeenTaken) -> Self {
+        ReadBodyRejection::BodyBeenTaken(e)
+    }
 }
 
 impl IntoResponse for ReadBodyRejection {
@@ -233,6 +1196,22 @@ impl IntoResponse for ReadBodyRejection {
                 tracing::error!("ReadBodyRejection failed {:?}", e);
                 LieResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Read body failed").into()
             }
+            ReadBodyRejection::DecodeFailed(e) => {
+                let report = e.report();
+                tracing::debug!("ReadBodyRejection decode failed: {}", report);
+                Rejected::new(RejectionKind::DecodeFailed, report).into_response(LieResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "Decode body failed",
+                ))
+            }
+            ReadBodyRejection::Timeout => {
+                LieResponse::new(StatusCode::REQUEST_TIMEOUT, "Read body timeout").into()
+            }
+            ReadBodyRejection::TooLarge => {
+                Rejected::new(RejectionKind::BodyTooLarge, "body too large").into_response(
+                    LieResponse::new(StatusCode::PAYLOAD_TOO_LARGE, "Payload Too Large"),
+                )
+            }
         }
     }
 }
@@ -274,11 +1253,13 @@ impl IntoResponse for FormRejection {
             FormRejection::ReadBody(e) => e.into_response(),
             FormRejection::UnexpectedContentType(t) => {
                 tracing::error!("FormRejection::UnexpectedContentType: {:?}", t);
-                LieResponse::with_status(StatusCode::BAD_REQUEST).into()
+                Rejected::new(RejectionKind::BadContentType, t)
+                    .into_response(LieResponse::with_status(StatusCode::BAD_REQUEST))
             }
             FormRejection::DecodeFailed(e) => {
                 tracing::error!("FormRejection::DecodeFailed: {:?}", e);
-                LieResponse::with_status(StatusCode::BAD_REQUEST).into()
+                Rejected::new(RejectionKind::DecodeFailed, e)
+                    .into_response(LieResponse::with_status(StatusCode::BAD_REQUEST))
             }
         }
     }
@@ -321,11 +1302,13 @@ impl IntoResponse for JsonRejection {
             JsonRejection::ReadBody(e) => e.into_response(),
             JsonRejection::UnexpectedContentType(t) => {
                 tracing::error!("JsonRejection::UnexpectedContentType: {:?}", t);
-                LieResponse::with_status(StatusCode::BAD_REQUEST).into()
+                Rejected::new(RejectionKind::BadContentType, t)
+                    .into_response(LieResponse::with_status(StatusCode::BAD_REQUEST))
             }
             JsonRejection::DecodeFailed(e) => {
                 tracing::error!("JsonRejection::DecodeFailed: {:?}", e);
-                LieResponse::with_status(StatusCode::BAD_REQUEST).into()
+                Rejected::new(RejectionKind::DecodeFailed, e)
+                    .into_response(LieResponse::with_status(StatusCode::BAD_REQUEST))
             }
         }
     }
@@ -340,18 +1323,74 @@ where
 
     async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
         let content_type = get_content_type(req);
-        if content_type.subtype() != mime::JSON {
+        let sniffing = content_type == mime::APPLICATION_OCTET_STREAM
+            && req.extensions().get::<ContentSniffing>().is_some();
+        if content_type.subtype() != mime::JSON && !sniffing {
             return Err(JsonRejection::UnexpectedContentType(content_type));
         }
 
         let body = read_body(req).await.map_err(JsonRejection::ReadBody)?;
 
+        if sniffing && !looks_like_json(&body) {
+            return Err(JsonRejection::UnexpectedContentType(content_type));
+        }
+
         let value: T = serde_json::from_slice(&body)?;
 
         Ok(Json::new(value))
     }
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum JsonOrFormRejection {
+    #[error("json body rejected")]
+    Json(#[from] JsonRejection),
+    #[error("form body rejected")]
+    Form(#[from] FormRejection),
+    #[error("unexecpted content type")]
+    UnexpectedContentType(Mime),
+}
+
+impl IntoResponse for JsonOrFormRejection {
+    fn into_response(self) -> Response {
+        match self {
+            JsonOrFormRejection::Json(e) => e.into_response(),
+            JsonOrFormRejection::Form(e) => e.into_response(),
+            JsonOrFormRejection::UnexpectedContentType(t) => {
+                tracing::error!("JsonOrFormRejection::UnexpectedContentType: {:?}", t);
+                Rejected::new(RejectionKind::BadContentType, t)
+                    .into_response(LieResponse::with_status(StatusCode::BAD_REQUEST))
+            }
+        }
+    }
+}
+
+#[crate::async_trait]
+impl<T> FromRequest for JsonOrForm<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    type Rejection = JsonOrFormRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let content_type = get_content_type(req);
+
+        if content_type.subtype() == mime::WWW_FORM_URLENCODED {
+            let form = Form::<T>::from_request(req).await?;
+            return Ok(JsonOrForm::new(form.take()));
+        }
+
+        let sniffing = content_type == mime::APPLICATION_OCTET_STREAM
+            && req.extensions().get::<ContentSniffing>().is_some();
+        if content_type.subtype() == mime::JSON || sniffing {
+            let json = Json::<T>::from_request(req).await?;
+            return Ok(JsonOrForm::new(json.take()));
+        }
+
+        Err(JsonOrFormRejection::UnexpectedContentType(content_type))
+    }
+}
+
 #[crate::async_trait]
 impl FromRequest for BytesBody {
     type Rejection = ReadBodyRejection;
@@ -364,23 +1403,71 @@ impl FromRequest for BytesBody {
     }
 }
 
+/// Body extractor `T`, like `Json`, `BytesBody` or `BodyStream`, with the body limited to
+/// `N` bytes.
+///
+/// Larger bodies are rejected with `413 Payload Too Large` while reading, without
+/// buffering them first. A stricter `BodyLimit` still applies.
+pub struct ContentLengthLimit<T, const N: usize> {
+    value: T,
+}
+
+impl<T, const N: usize> ContentLengthLimit<T, N> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+impl<T, const N: usize> Deref for ContentLengthLimit<T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
 #[crate::async_trait]
-impl FromRequest for hyper::body::Incoming {
-    type Rejection = BodyBeenTaken;
+impl<T, const N: usize> FromRequest for ContentLengthLimit<T, N>
+where
+    T: FromRequest,
+{
+    type Rejection = T::Rejection;
 
     async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
-        let empty = hyper::Request::default();
-        let req = std::mem::replace(req, empty);
+        let prev = req.extensions().get::<BodyLimit>().copied();
+        let limit = prev.map_or(N, |limit| limit.0.min(N));
+
+        req.extensions_mut().insert(BodyLimit(limit));
+        let ret = T::from_request(req).await;
+        match prev {
+            Some(prev) => req.extensions_mut().insert(prev),
+            None => req.extensions_mut().remove::<BodyLimit>(),
+        };
+
+        ret.map(|value| ContentLengthLimit { value })
+    }
+}
 
-        let (_parts, body) = req.into_parts();
+#[crate::async_trait]
+impl FromRequest for BodyStream {
+    type Rejection = ReadBodyRejection;
 
-        match body {
-            Some(body) => Ok(body),
-            None => Err(BodyBeenTaken),
-        }
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        BodyStream::from_request(req)
     }
 }
 
+fn looks_like_json(body: &[u8]) -> bool {
+    matches!(
+        body.iter().find(|b| !b.is_ascii_whitespace()),
+        Some(b'{') | Some(b'[')
+    )
+}
+
 fn get_content_type(req: &mut RequestParts) -> mime::Mime {
     req.headers()
         .get(hyper::header::CONTENT_TYPE)
@@ -392,24 +1479,11 @@ fn get_content_type(req: &mut RequestParts) -> mime::Mime {
         .unwrap_or(mime::APPLICATION_OCTET_STREAM)
 }
 
-async fn read_body(req: &mut RequestParts) -> Result<Bytes, ReadBodyRejection> {
-    let body = req
-        .body_mut()
-        .take()
-        .ok_or(ReadBodyRejection::BodyBeenTaken(BodyBeenTaken))?;
-
-    let body = BodyExt::collect(body)
-        .await
-        .map_err(ReadBodyRejection::ReadFailed)?;
-
-    Ok(body.to_bytes())
-}
-
 mod params_de {
     use std::fmt::{self, Display};
 
     use serde::{
-        de::{self, DeserializeOwned, IntoDeserializer, MapAccess},
+        de::{self, DeserializeOwned, IntoDeserializer, MapAccess, SeqAccess},
         Deserializer,
     };
 
@@ -438,27 +1512,51 @@ mod params_de {
 
     impl std::error::Error for Error {}
 
-    struct PathParamsDeserialzer<'de> {
-        inner: &'de mut pathrouter::ParamIter<'de>,
-    }
+    type ParamIter<'de> = std::vec::IntoIter<(&'de str, &'de str)>;
 
-    impl<'de> PathParamsDeserialzer<'de> {
-        pub fn from_params(inner: &'de mut pathrouter::ParamIter<'de>) -> Self {
-            PathParamsDeserialzer { inner }
-        }
+    /// Params in route order, so tuples are filled by position.
+    struct PathParamsDeserialzer<'de> {
+        inner: ParamIter<'de>,
     }
 
-    pub fn from_params<T>(params: &pathrouter::Params) -> Result<T, Error>
+    pub fn from_params<'de, T>(
+        params: impl IntoIterator<Item = (&'de str, &'de str)>,
+    ) -> Result<T, Error>
     where
         T: DeserializeOwned,
     {
-        let mut iter = params.into_iter();
-        let mut deserializer = PathParamsDeserialzer::from_params(&mut iter);
+        let params: Vec<(&str, &str)> = params.into_iter().collect();
+        let mut deserializer = PathParamsDeserialzer {
+            inner: params.into_iter(),
+        };
         let t = T::deserialize(&mut deserializer)?;
         Ok(t)
     }
 
-    impl<'de, 'a> Deserializer<'de> for &'a mut PathParamsDeserialzer<'de> {
+    impl<'de> PathParamsDeserialzer<'de> {
+        /// The value of the only param, for scalar types.
+        fn single(&mut self) -> Result<PartDeserialzer<'de>, Error> {
+            let (_, value) = self.inner.next().ok_or(Error::Eof)?;
+            if self.inner.next().is_some() {
+                return Err(Error::Message("expected a single path param".to_string()));
+            }
+
+            Ok(PartDeserialzer { inner: value })
+        }
+    }
+
+    macro_rules! deserialize_single {
+        ($trait_fn:ident) => {
+            fn $trait_fn<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: serde::de::Visitor<'de>,
+            {
+                self.single()?.$trait_fn(visitor)
+            }
+        };
+    }
+
+    impl<'de> Deserializer<'de> for &mut PathParamsDeserialzer<'de> {
         type Error = Error;
 
         fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -469,24 +1567,96 @@ mod params_de {
         }
 
         serde::forward_to_deserialize_any! {
-            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            i128 u128 char bytes byte_buf option unit unit_struct
             tuple_struct enum identifier ignored_any
         }
 
+        deserialize_single!(deserialize_bool);
+        deserialize_single!(deserialize_i8);
+        deserialize_single!(deserialize_i16);
+        deserialize_single!(deserialize_i32);
+        deserialize_single!(deserialize_i64);
+        deserialize_single!(deserialize_u8);
+        deserialize_single!(deserialize_u16);
+        deserialize_single!(deserialize_u32);
+        deserialize_single!(deserialize_u64);
+        deserialize_single!(deserialize_f32);
+        deserialize_single!(deserialize_f64);
+        deserialize_single!(deserialize_str);
+        deserialize_single!(deserialize_string);
+
+        fn deserialize_newtype_struct<V>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Self::Error>
+        where
+            V: serde::de::Visitor<'de>,
+        {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: serde::de::Visitor<'de>,
+        {
+            struct Access<'de, 'a> {
+                iter: &'a mut ParamIter<'de>,
+            }
+
+            impl<'de, 'a> SeqAccess<'de> for Access<'de, 'a> {
+                type Error = Error;
+
+                fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+                where
+                    T: de::DeserializeSeed<'de>,
+                {
+                    match self.iter.next() {
+                        Some(entry) => seed
+                            .deserialize(PartDeserialzer { inner: entry.1 })
+                            .map(Some),
+                        None => Ok(None),
+                    }
+                }
+            }
+
+            visitor.visit_seq(Access {
+                iter: &mut self.inner,
+            })
+        }
+
+        fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: serde::de::Visitor<'de>,
+        {
+            let value = (&mut *self).deserialize_seq(visitor)?;
+
+            // a tuple visitor stops after `len` elements
+            let rest = self.inner.len();
+            if rest > 0 {
+                return Err(Error::Message(format!(
+                    "expected {} path params, found {}",
+                    len,
+                    len + rest
+                )));
+            }
+
+            Ok(value)
+        }
+
         fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
         where
             V: serde::de::Visitor<'de>,
         {
             struct Access<'de, 'a> {
-                iter: &'a mut pathrouter::ParamIter<'de>,
+                iter: &'a mut ParamIter<'de>,
                 entry: Option<(&'de str, &'de str)>,
             }
 
             impl<'de, 'a> Access<'de, 'a> {
                 fn new(de: &'a mut PathParamsDeserialzer<'de>) -> Self {
                     Access {
-                        iter: de.inner,
+                        iter: &mut de.inner,
                         entry: None,
                     }
                 }
@@ -661,5 +1831,692 @@ mod params_de {
 
             println!("params: {:?}", &p);
         }
+
+        #[test]
+        fn param_count() {
+            let params = [("owner", "lieweb"), ("repo", "core"), ("stars", "7")];
+
+            let p: (String, String, u32) = from_params(params).unwrap();
+            assert_eq!(p, ("lieweb".to_string(), "core".to_string(), 7));
+
+            assert!(from_params::<(String, String)>(params).is_err());
+            assert!(from_params::<(String, String, u32, u32)>(params).is_err());
+            assert!(from_params::<String>(params).is_err());
+            assert_eq!(
+                from_params::<String>([("owner", "lieweb")]).unwrap(),
+                "lieweb"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    use crate::http::{self, StatusCode};
+    use crate::test_helpers::{post, send, TestClient};
+    use crate::{App, BodyStream, ContentSniffing, Json, JsonOrForm, LieResponse};
+
+    use super::{
+        AllOf, Auth, Authenticator, Authorization, BearerToken, Conditionals, ContentLengthLimit,
+        HeaderKey, HeaderValues, PathAndQuery, Rejected, RejectionKind,
+    };
+
+    #[derive(serde::Deserialize)]
+    struct Login {
+        name: String,
+    }
+
+    struct User {
+        name: String,
+    }
+
+    struct TokenAuth;
+
+    #[crate::async_trait]
+    impl Authenticator for TokenAuth {
+        type User = User;
+
+        async fn authenticate(
+            &self,
+            req: &crate::request::RequestParts,
+        ) -> Result<User, crate::Response> {
+            match req.headers().get(http::header::AUTHORIZATION) {
+                Some(token) if token == "Bearer secret" => Ok(User {
+                    name: "alice".to_string(),
+                }),
+                _ => Err(LieResponse::with_status(StatusCode::UNAUTHORIZED)
+                    .insert_header(http::header::WWW_AUTHENTICATE, "Bearer")
+                    .into_hyper_response()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn auth_user() {
+        let mut app = App::new();
+        app.authenticator(TokenAuth);
+        app.get("/me", |user: Auth<User>| async move {
+            format!("hello, {}", user.name)
+        });
+        let client = TestClient::new(app);
+
+        let req = http::Request::get("/me")
+            .header(http::header::AUTHORIZATION, "Bearer secret")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "hello, alice");
+
+        let req = http::Request::get("/me")
+            .header(http::header::AUTHORIZATION, "Bearer wrong")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers()[http::header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[derive(serde::Deserialize)]
+    struct UserQuery {
+        id: u32,
+        include: Option<String>,
+    }
+
+    #[tokio::test]
+    async fn path_and_query() {
+        let mut app = App::new();
+        app.get("/users/:id", |q: PathAndQuery<UserQuery>| async move {
+            let q = q.value();
+            format!("user {} include {:?}", q.id, q.include)
+        });
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(crate::test_helpers::get("/users/42?include=posts"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), r#"user 42 include Some("posts")"#);
+
+        // path param takes precedence
+        let resp = client
+            .send(crate::test_helpers::get("/users/42?id=7"))
+            .await;
+        assert_eq!(resp.body(), "user 42 include None");
+
+        let resp = client.send(crate::test_helpers::get("/users/abc")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn typed_path() {
+        let mut app = App::new();
+        app.get("/users/:id", |id: super::Path<u64>| async move {
+            format!("user {}", id.value())
+        });
+        app.get(
+            "/repos/:owner/:stars",
+            |p: super::Path<(String, u32)>| async move {
+                let (owner, stars) = p.take();
+                format!("{} {}", owner, stars)
+            },
+        );
+        // more params than the tuple takes
+        app.get(
+            "/forks/:owner/:repo/:stars",
+            |p: super::Path<(String, u32)>| async move {
+                let (owner, stars) = p.take();
+                format!("{} {}", owner, stars)
+            },
+        );
+        app.get("/orgs/:org/:team", |org: super::Path<String>| async move {
+            org.take()
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(crate::test_helpers::get("/users/42")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "user 42");
+
+        let resp = client.send(crate::test_helpers::get("/users/abc")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = client
+            .send(crate::test_helpers::get("/repos/lieweb/7"))
+            .await;
+        assert_eq!(resp.body(), "lieweb 7");
+
+        let resp = client
+            .send(crate::test_helpers::get("/forks/lieweb/core/7"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = client
+            .send(crate::test_helpers::get("/orgs/rust/core"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn typed_path_route_order() {
+        let mut app = App::new();
+        // names sort the other way round
+        app.get(
+            "/users/:user_id/posts/:id",
+            |p: super::Path<(String, u32)>| async move {
+                let (user, post) = p.take();
+                format!("{} {}", user, post)
+            },
+        );
+
+        let mut items = crate::Router::new();
+        items.get("/items/:id", |id: super::Path<u64>| async move {
+            format!("item {}", id.take())
+        });
+        app.merge("/api/", items).unwrap();
+
+        let mut teams = crate::Router::new();
+        teams.get("/teams/:id", |p: super::Path<(String, u32)>| async move {
+            let (org, team) = p.take();
+            format!("{} {}", org, team)
+        });
+        app.merge("/orgs/:org/", teams).unwrap();
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(crate::test_helpers::get("/users/alice/posts/7"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "alice 7");
+
+        let resp = client.send(crate::test_helpers::get("/api/items/5")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "item 5");
+
+        let resp = client
+            .send(crate::test_helpers::get("/orgs/rust/teams/3"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "rust 3");
+    }
+
+    #[tokio::test]
+    async fn query_pairs() {
+        let mut app = App::new();
+        app.get("/", |q: super::QueryPairs| async move {
+            q.value()
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(crate::test_helpers::get("/?b=2&a=1&b=x%20y&c=a+b"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "b=2,a=1,b=x y,c=a b");
+
+        let resp = client.send(crate::test_helpers::get("/")).await;
+        assert_eq!(resp.body(), "");
+    }
+
+    #[tokio::test]
+    async fn cookies() {
+        let mut app = App::new();
+        app.get("/", |cookies: super::Cookies| async move {
+            let all = cookies
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{} {}", cookies.get("b").unwrap_or_default(), all)
+        });
+
+        let req = http::Request::get("/")
+            .header(http::header::COOKIE, "a=1; b=2")
+            .header(http::header::COOKIE, "c=3; b=4")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = send(app, req).await;
+
+        assert_eq!(resp.body(), "2 a=1,b=2,c=3,b=4");
+    }
+
+    #[tokio::test]
+    async fn typed_header() {
+        let mut app = App::new();
+        app.get(
+            "/",
+            |ct: super::TypedHeader<headers::ContentType>| async move { ct.value().to_string() },
+        );
+        let client = TestClient::new(app);
+
+        let with_type = |content_type: &str| {
+            http::Request::get("/")
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Full::<Bytes>::default())
+                .unwrap()
+        };
+
+        let resp = client.send(with_type("application/json")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "application/json");
+
+        let resp = client.send(with_type("not a mime")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.body(), "invalid header content-type");
+
+        let resp = client.send(crate::test_helpers::get("/")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.body(), "missing header content-type");
+    }
+
+    #[tokio::test]
+    async fn extension() {
+        use crate::middleware::{Middleware, Next};
+        use crate::{Request, Response};
+
+        #[derive(Clone)]
+        struct User(&'static str);
+
+        struct Login;
+
+        #[crate::async_trait]
+        impl Middleware for Login {
+            async fn handle<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+                if req.uri().path() == "/me" {
+                    req.extensions_mut().insert(User("lieweb"));
+                }
+                next.run(req).await
+            }
+        }
+
+        let mut app = App::new();
+        app.middleware(Login);
+        app.get("/me", |user: super::Extension<User>| async move { user.0 });
+        app.get("/anonymous", |user: super::Extension<User>| async move {
+            user.0
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(crate::test_helpers::get("/me")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "lieweb");
+
+        let resp = client.send(crate::test_helpers::get("/anonymous")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn query_required_fields() {
+        #[derive(serde::Deserialize)]
+        struct Search {
+            q: String,
+            page: Option<u32>,
+        }
+
+        let mut app = App::new();
+        app.get("/", |search: crate::Query<Search>| async move {
+            let search = search.take();
+            format!("{} {:?}", search.q, search.page)
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(crate::test_helpers::get("/?q=lieweb")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "lieweb None");
+
+        let resp = client.send(crate::test_helpers::get("/?page=2")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = client.send(crate::test_helpers::get("/")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn custom_rejection() {
+        let mut app = App::new();
+        app.rejection(RejectionKind::DecodeFailed, |rejected: &Rejected| {
+            LieResponse::with_json(serde_json::json!({
+                "code": 40001,
+                "kind": format!("{:?}", rejected.kind()),
+            }))
+            .set_status(StatusCode::UNPROCESSABLE_ENTITY)
+        });
+        app.post("/login", |_login: Json<Login>| async move { "ok" });
+
+        let resp = send(app, post("/login", "application/json", "{}")).await;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            resp.body().as_ref(),
+            br#"{"code":40001,"kind":"DecodeFailed"}"#
+        );
+    }
+
+    fn untyped_post(uri: &str, body: &'static str) -> http::Request<Full<Bytes>> {
+        http::Request::post(uri).body(Full::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_content_sniffing() {
+        let mut app = App::new();
+        app.data(ContentSniffing);
+        app.post("/login", |login: Json<Login>| async move {
+            login.value().name.clone()
+        });
+
+        let resp = send(app, untyped_post("/login", r#" {"name":"lie"}"#)).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "lie");
+    }
+
+    #[tokio::test]
+    async fn json_content_sniffing_mismatch() {
+        let mut app = App::new();
+        app.data(ContentSniffing);
+        app.post("/login", |_login: Json<Login>| async move { "ok" });
+
+        let resp = send(app, untyped_post("/login", "name=lie")).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn json_strict_content_type() {
+        let capture = crate::test_util::LogCapture::new();
+
+        let mut app = App::new();
+        app.post("/login", |_login: Json<Login>| async move { "ok" });
+
+        let resp = send(app, untyped_post("/login", r#"{"name":"lie"}"#)).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        capture.assert_logged(
+            tracing::Level::ERROR,
+            "JsonRejection::UnexpectedContentType",
+        );
+    }
+
+    #[tokio::test]
+    async fn conditionals() {
+        use std::ops::Bound;
+
+        async fn handler(conditionals: Conditionals) -> String {
+            let etag: headers::ETag = "\"v1\"".parse().unwrap();
+            let not_modified = conditionals
+                .if_none_match()
+                .map(|m| !m.precondition_passes(&etag))
+                .unwrap_or_default();
+            let ranges: Vec<_> = conditionals
+                .range()
+                .map(|r| r.satisfiable_ranges(1000).collect())
+                .unwrap_or_default();
+
+            assert!(conditionals.if_match().is_none());
+            assert!(conditionals.if_modified_since().is_none());
+
+            format!("{} {:?}", not_modified, ranges)
+        }
+
+        let mut app = App::new();
+        app.get("/", handler);
+
+        let req = http::Request::get("/")
+            .header(http::header::IF_NONE_MATCH, "\"v1\"")
+            .header(http::header::RANGE, "bytes=0-99")
+            .header(http::header::IF_MODIFIED_SINCE, "yesterday")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = send(app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.body(),
+            &format!(
+                "true {:?}",
+                [(Bound::Included(0u64), Bound::Included(99u64))]
+            )
+        );
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Item {
+        name: String,
+        count: u32,
+    }
+
+    #[tokio::test]
+    async fn json_or_form() {
+        let mut app = App::new();
+        app.post("/items", |item: JsonOrForm<Item>| async move {
+            let item = item.take();
+            format!("{} x{}", item.name, item.count)
+        });
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(post(
+                "/items",
+                "application/json",
+                r#"{"name":"apple","count":3}"#,
+            ))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "apple x3");
+
+        let resp = client
+            .send(post(
+                "/items",
+                "application/x-www-form-urlencoded",
+                "name=apple&count=3",
+            ))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "apple x3");
+
+        let resp = client.send(post("/items", "text/plain", "apple")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn default_rejection() {
+        let mut app = App::new();
+        app.rejection(RejectionKind::DecodeFailed, |_: &Rejected| {
+            LieResponse::with_status(StatusCode::UNPROCESSABLE_ENTITY)
+        });
+        app.post("/login", |_login: Json<Login>| async move { "ok" });
+
+        let resp = send(app, post("/login", "text/plain", "{}")).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn content_length_limit() {
+        let mut app = App::new();
+        app.post(
+            "/",
+            |login: ContentLengthLimit<Json<Login>, 16>| async move {
+                format!("hello, {}", login.value().value().name)
+            },
+        );
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(post("/", "application/json", r#"{"name":"lie"}"#))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = client
+            .send(post("/", "application/json", r#"{"name":"lieweb-lieweb"}"#))
+            .await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // chunked, so the limit is checked while streaming
+        let req = http::Request::post("/")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(http::header::TRANSFER_ENCODING, "chunked")
+            .body(Full::new(Bytes::from(r#"{"name":"lieweb-lieweb"}"#)))
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn body_stream_limit() {
+        let mut app = App::new();
+        app.post("/", |body: ContentLengthLimit<BodyStream, 8>| async move {
+            body.take()
+                .collect()
+                .await
+                .map(|body| String::from_utf8_lossy(&body.to_bytes()).into_owned())
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(post("/", "text/plain", "01234567")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "01234567");
+
+        let resp = client
+            .send(post("/", "text/plain", "0123456789abcdef"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // chunked, so the limit is checked while streaming
+        let body =
+            futures::stream::iter(["01234", "56789"].map(|chunk| {
+                Ok::<_, std::io::Error>(hyper::body::Frame::data(Bytes::from(chunk)))
+            }));
+        let req = http::Request::post("/")
+            .body(http_body_util::StreamBody::new(body))
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn authorization_schemes() {
+        let mut app = App::new();
+        app.get("/", |auth: Authorization| async move {
+            match auth.basic() {
+                Some((user, password)) => format!("basic {}:{}", user, password),
+                None => format!("{} {}", auth.scheme(), auth.credentials()),
+            }
+        });
+        app.get("/bearer", |token: BearerToken| async move { token.take() });
+        let client = TestClient::new(app);
+
+        fn get(uri: &str, authorization: Option<&str>) -> http::Request<Full<Bytes>> {
+            let mut req = http::Request::get(uri);
+            if let Some(authorization) = authorization {
+                req = req.header(http::header::AUTHORIZATION, authorization);
+            }
+            req.body(Full::default()).unwrap()
+        }
+
+        // "lieweb:secret"
+        let resp = client
+            .send(get("/", Some("Basic bGlld2ViOnNlY3JldA==")))
+            .await;
+        assert_eq!(resp.body(), "basic lieweb:secret");
+
+        let resp = client.send(get("/", Some("Bearer abc.def"))).await;
+        assert_eq!(resp.body(), "Bearer abc.def");
+        let resp = client.send(get("/bearer", Some("bearer abc.def"))).await;
+        assert_eq!(resp.body(), "abc.def");
+
+        let resp = client
+            .send(get("/", Some(r#"Signature keyId="k1",signature="c2ln""#)))
+            .await;
+        assert_eq!(resp.body(), r#"Signature keyId="k1",signature="c2ln""#);
+
+        let resp = client.send(get("/bearer", Some("Signature abc"))).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        for authorization in [None, Some("Bearer"), Some("Bearer  ")] {
+            let resp = client.send(get("/", authorization)).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            // no scheme is claimed without a configured challenge
+            assert!(resp.headers().get(http::header::WWW_AUTHENTICATE).is_none());
+        }
+
+        let resp = client.send(get("/bearer", None)).await;
+        assert_eq!(resp.headers()[http::header::WWW_AUTHENTICATE], "Bearer");
+
+        let mut app = App::new();
+        app.data(super::AuthChallenge(http::HeaderValue::from_static(
+            r#"Signature realm="api""#,
+        )));
+        app.get("/", |auth: Authorization| async move {
+            auth.credentials().to_string()
+        });
+        let resp = send(app, get("/", None)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers()[http::header::WWW_AUTHENTICATE],
+            r#"Signature realm="api""#
+        );
+    }
+
+    #[tokio::test]
+    async fn repeated_headers() {
+        struct XForwardedFor;
+
+        impl HeaderKey for XForwardedFor {
+            const NAME: &'static str = "x-forwarded-for";
+        }
+
+        let mut app = App::new();
+        app.get("/", |values: HeaderValues<XForwardedFor>| async move {
+            values.to_strs().join(" | ")
+        });
+        app.get("/types", |types: AllOf<headers::ContentType>| async move {
+            types
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(" | ")
+        });
+        let client = TestClient::new(app);
+
+        let req = http::Request::get("/")
+            .header("x-forwarded-for", "203.0.113.1")
+            .header("x-forwarded-for", "198.51.100.2, 10.0.0.1")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.body(), "203.0.113.1 | 198.51.100.2, 10.0.0.1");
+
+        let resp = client
+            .send(
+                http::Request::get("/")
+                    .body(Full::<Bytes>::default())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "");
+
+        let req = http::Request::get("/types")
+            .header("content-type", "text/plain")
+            .header("content-type", "application/json")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.body(), "text/plain | application/json");
+
+        let req = http::Request::get("/types")
+            .header("content-type", "not a mime")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 }
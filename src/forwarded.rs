@@ -0,0 +1,374 @@
+//! Client address behind proxies, from `Forwarded` (RFC 7239) or `X-Forwarded-For`.
+
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::http::{header, HeaderMap, HeaderName};
+use crate::request::{FromRequest, RequestCtx, RequestParts};
+use crate::Error;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// A hop of the forwarding chain, `None` for `unknown` or obfuscated identifiers.
+pub(crate) type Hop = Option<IpAddr>;
+
+/// The header trusted proxies append the client address to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For`, the common choice of proxies and load balancers.
+    #[default]
+    XForwardedFor,
+    /// `Forwarded` of RFC 7239.
+    Forwarded,
+}
+
+/// Hops from the client to the last proxy, from all headers of `source`.
+pub(crate) fn forwarded_for(headers: &HeaderMap, source: ForwardedHeader) -> Vec<Hop> {
+    match source {
+        ForwardedHeader::Forwarded => headers
+            .get_all(header::FORWARDED)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| split_quoted(v, ','))
+            .map(|element| {
+                split_quoted(element, ';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(&unquote(node.trim())))
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter(|v| !v.trim().is_empty())
+            .map(|node| parse_node(node.trim()))
+            .collect(),
+    }
+}
+
+/// Split by `sep` outside of quoted strings.
+fn split_quoted(s: &str, sep: char) -> impl Iterator<Item = &str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts.into_iter().filter(|p| !p.trim().is_empty())
+}
+
+fn unquote(s: &str) -> String {
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) => {
+            let mut out = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => out.extend(chars.next()),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+        None => s.to_string(),
+    }
+}
+
+/// Parse a node, `1.2.3.4`, `1.2.3.4:80`, `[::1]`, `[::1]:80` or a bare IPv6.
+fn parse_node(node: &str) -> Hop {
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']').and_then(|(ip, _)| ip.parse().ok());
+    }
+
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    fn parse(s: &str) -> Option<Cidr> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse().ok()?, Some(prefix.parse().ok()?)),
+            None => (s.parse().ok()?, None),
+        };
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = prefix.unwrap_or(max);
+
+        (prefix <= max).then_some(Cidr { addr, prefix })
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            _ => *ip,
+        };
+
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Proxies trusted to set `X-Forwarded-For`, or `Forwarded` by `TrustedProxies::header`,
+/// by address or CIDR range.
+///
+/// Only the configured header is read, the other one is passed through by the proxies
+/// as sent by the client, so it can not be trusted.
+///
+/// Set it with `App::data`, without it `ClientIp` is the peer address.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    /// e.g. `["10.0.0.0/8", "127.0.0.1", "::1"]`.
+    pub fn new<'a>(proxies: impl IntoIterator<Item = &'a str>) -> Result<Self, Error> {
+        let ranges = proxies
+            .into_iter()
+            .map(|p| {
+                Cidr::parse(p.trim())
+                    .ok_or_else(|| Error::Message(format!("invalid trusted proxy {:?}", p)))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(TrustedProxies {
+            ranges,
+            header: ForwardedHeader::default(),
+        })
+    }
+
+    /// Read the client address from `header`, default is `X-Forwarded-For`.
+    pub fn header(&mut self, header: ForwardedHeader) {
+        self.header = header;
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// Walk the chain from the peer to the left, skipping trusted proxies, the first
+    /// untrusted hop is the client.
+    pub(crate) fn client_ip(&self, peer: Option<IpAddr>, forwarded: &[Hop]) -> Option<IpAddr> {
+        let mut hops = forwarded.iter().rev();
+        let mut current = peer;
+
+        loop {
+            match current {
+                Some(ip) if self.contains(&ip) => match hops.next() {
+                    Some(hop) => current = *hop,
+                    None => return Some(ip),
+                },
+                _ => return current,
+            }
+        }
+    }
+}
+
+/// Address of the client, resolved from the forwarding headers set by
+/// `TrustedProxies`, or the peer address.
+///
+/// `None` when the client is `unknown` or obfuscated, or there is no peer address.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp {
+    ip: Option<IpAddr>,
+}
+
+impl ClientIp {
+    pub fn value(&self) -> Option<IpAddr> {
+        self.ip
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let peer = RequestCtx::extract_remote_addr(req).map(|addr| addr.ip());
+
+        let ip = match req.extensions().get::<TrustedProxies>() {
+            Some(trusted) => trusted.client_ip(peer, &forwarded_for(req.headers(), trusted.header)),
+            None => peer,
+        };
+
+        Ok(ClientIp { ip })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, header, HeaderMap, HeaderValue};
+    use crate::test_helpers::{send, TestClient};
+    use crate::App;
+
+    use super::{forwarded_for, ClientIp, ForwardedHeader, TrustedProxies};
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    fn headers(name: header::HeaderName, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for v in values {
+            headers.append(name.clone(), HeaderValue::from_static(v));
+        }
+        headers
+    }
+
+    #[test]
+    fn parse_forwarded() {
+        let headers = headers(
+            header::FORWARDED,
+            &[
+                r#"for=192.0.2.43;proto=http, for="[2001:db8:cafe::17]:4711""#,
+                r#"for=unknown, for="198.51.100.17:8080";by="a,b";host=example.com"#,
+                "For=_hidden",
+            ],
+        );
+
+        assert_eq!(
+            forwarded_for(&headers, ForwardedHeader::Forwarded),
+            vec![
+                ip("192.0.2.43"),
+                ip("2001:db8:cafe::17"),
+                None,
+                ip("198.51.100.17"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_x_forwarded_for() {
+        let headers = headers(
+            super::X_FORWARDED_FOR,
+            &["203.0.113.1, 2001:db8::1", "10.0.0.1:1234"],
+        );
+
+        assert_eq!(
+            forwarded_for(&headers, ForwardedHeader::XForwardedFor),
+            vec![ip("203.0.113.1"), ip("2001:db8::1"), ip("10.0.0.1")]
+        );
+        assert!(forwarded_for(&headers, ForwardedHeader::Forwarded).is_empty());
+    }
+
+    #[test]
+    fn walk_trusted_hops() {
+        let trusted = TrustedProxies::new(["10.0.0.0/8", "127.0.0.1"]).unwrap();
+        let chain = [ip("1.1.1.1"), ip("203.0.113.7"), ip("10.1.2.3")];
+
+        // 203.0.113.7 is not trusted, so 1.1.1.1 may be spoofed
+        assert_eq!(
+            trusted.client_ip(ip("127.0.0.1"), &chain),
+            ip("203.0.113.7")
+        );
+        // untrusted peer is the client
+        assert_eq!(trusted.client_ip(ip("192.0.2.1"), &chain), ip("192.0.2.1"));
+        // all hops trusted, the leftmost one
+        assert_eq!(
+            trusted.client_ip(ip("127.0.0.1"), &[ip("10.0.0.9")]),
+            ip("10.0.0.9")
+        );
+        assert_eq!(trusted.client_ip(ip("127.0.0.1"), &[None]), None);
+    }
+
+    #[tokio::test]
+    async fn client_ip_chained_proxies() {
+        fn app(trusted: Option<TrustedProxies>) -> App {
+            let mut app = App::new();
+            if let Some(trusted) = trusted {
+                app.data(trusted);
+            }
+            app.get(
+                "/",
+                |ip: ClientIp| async move { format!("{:?}", ip.value()) },
+            );
+            app
+        }
+
+        let req = || {
+            http::Request::get("/")
+                .header(header::FORWARDED, "for=198.51.100.1")
+                .header(header::FORWARDED, r#"for="10.0.0.2:9000""#)
+                .body(Full::<Bytes>::default())
+                .unwrap()
+        };
+
+        // the test peer is 127.0.0.1
+        let mut trusted = TrustedProxies::new(["127.0.0.1", "10.0.0.0/8"]).unwrap();
+        trusted.header(ForwardedHeader::Forwarded);
+        let resp = send(app(Some(trusted)), req()).await;
+        assert_eq!(resp.body(), "Some(198.51.100.1)");
+
+        let resp = send(app(None), req()).await;
+        assert_eq!(resp.body(), "Some(127.0.0.1)");
+    }
+
+    #[tokio::test]
+    async fn spoofed_forwarded_ignored() {
+        let mut app = App::new();
+        app.data(TrustedProxies::new(["127.0.0.1"]).unwrap());
+        app.get(
+            "/",
+            |ip: ClientIp| async move { format!("{:?}", ip.value()) },
+        );
+        let client = TestClient::new(app);
+
+        // the client sends its own `Forwarded`, the proxy only appends `X-Forwarded-For`
+        let req = http::Request::get("/")
+            .header(header::FORWARDED, "for=1.2.3.4")
+            .header(super::X_FORWARDED_FOR, "203.0.113.9")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.body(), "Some(203.0.113.9)");
+
+        let req = http::Request::get("/")
+            .header(header::FORWARDED, "for=1.2.3.4")
+            .body(Full::<Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.body(), "Some(127.0.0.1)");
+    }
+}
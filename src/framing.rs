@@ -0,0 +1,558 @@
+//! Request framing checks of HTTP/1 connections, against request smuggling.
+
+use std::io;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Longest head buffered, hyper's default `max_buf_size`.
+const MAX_HEAD_SIZE: usize = 8192 + 4096 * 100;
+/// Longest line of a chunked body, hyper rejects far shorter chunk extensions.
+const MAX_LINE_SIZE: usize = 64 * 1024;
+
+const READ_BUF_SIZE: usize = 8 * 1024;
+
+/// Head handed to hyper in place of a request whose target is too long.
+const URI_TOO_LONG_HEAD: &[u8] = b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n";
+/// Longest tail of a request line after its target, ` HTTP/1.1\r`.
+const VERSION_LEN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Buffering the next request head.
+    Head,
+    /// Bytes left of a `Content-Length` body.
+    Body(u64),
+    /// Line of the next chunk size.
+    ChunkSize,
+    /// Bytes left of the chunk data.
+    ChunkData(u64),
+    /// Line ending the chunk data.
+    ChunkEnd,
+    /// Trailer lines, up to an empty one.
+    Trailers,
+    /// Waiting for the response to an upgrade or `CONNECT` request.
+    Response,
+    /// No more requests to check, e.g. after an upgrade or an HTTP/2 preface.
+    Passthrough,
+    /// Dropping the rest of a rejected request, the connection is closed.
+    Discard,
+}
+
+/// Connection io checking the framing of HTTP/1 requests before hyper parses them.
+///
+/// hyper drops `Content-Length` when `Transfer-Encoding` is present too and serves the
+/// request, this makes it reject such a request with `400 Bad Request` instead: the
+/// `Transfer-Encoding` value is blanked out, so hyper finds it invalid. Request bodies
+/// are followed to find the next head. Connections are passed through once they leave
+/// HTTP/1, after a `101` or a successful `CONNECT` response or by the HTTP/2 preface, or
+/// carry heads hyper can't parse, which hyper rejects itself.
+///
+/// Request lines with a target longer than `max_uri_length` are rejected as soon as they
+/// are read, before the rest of the head arrives: hyper is handed a short request in
+/// their place, which `UriTooLong` tells apart.
+pub(crate) struct FramingGuard<I> {
+    inner: I,
+    state: State,
+    max_uri_length: Option<usize>,
+    /// Heads handed to hyper so far.
+    requests: u64,
+    uri_too_long: UriTooLong,
+    /// The incomplete head, or the incomplete line of a chunked body.
+    pending: Vec<u8>,
+    /// Bytes of the incomplete head already checked, its request line.
+    released: usize,
+    /// Checked bytes not read yet.
+    checked: BytesMut,
+    read_buf: Box<[u8]>,
+    /// Upgrade or `CONNECT` request waiting for its response.
+    tunnel: Option<Tunnel>,
+    /// The incomplete response head to `tunnel`.
+    response: Vec<u8>,
+    /// Bytes read after `tunnel`, checked once it is answered.
+    held: Vec<u8>,
+    read_waker: Option<Waker>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tunnel {
+    Upgrade,
+    Connect,
+}
+
+/// Number of the request, counted from 1 on the connection, that was handed to hyper in
+/// place of one with a too long target.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UriTooLong(Arc<AtomicU64>);
+
+impl UriTooLong {
+    pub(crate) fn is_rejected(&self, request: u64) -> bool {
+        self.0.load(Ordering::Acquire) == request
+    }
+}
+
+impl<I> FramingGuard<I> {
+    pub(crate) fn new(inner: I, max_uri_length: Option<usize>) -> Self {
+        FramingGuard {
+            inner,
+            state: State::Head,
+            max_uri_length,
+            requests: 0,
+            uri_too_long: UriTooLong::default(),
+            pending: Vec::new(),
+            released: 0,
+            checked: BytesMut::new(),
+            read_buf: vec![0; READ_BUF_SIZE].into_boxed_slice(),
+            tunnel: None,
+            response: Vec::new(),
+            held: Vec::new(),
+            read_waker: None,
+        }
+    }
+
+    /// Tells apart the request handed to hyper in place of one with a too long target.
+    pub(crate) fn uri_too_long(&self) -> UriTooLong {
+        self.uri_too_long.clone()
+    }
+
+    /// State after a request, bytes of a tunnel can't be checked as requests.
+    fn next_request(&self) -> State {
+        match self.tunnel {
+            Some(_) => State::Response,
+            None => State::Head,
+        }
+    }
+
+    /// Check `data`, moving the bytes into `checked` as soon as they may be read.
+    fn feed(&mut self, data: &[u8]) {
+        let mut data = data;
+
+        while !data.is_empty() {
+            let n = match self.state {
+                State::Head => self.feed_head(data),
+                State::Body(left) | State::ChunkData(left) => {
+                    let n = data.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+                    self.checked.extend_from_slice(&data[..n]);
+
+                    let left = left - n as u64;
+                    self.state = match self.state {
+                        State::Body(_) if left == 0 => self.next_request(),
+                        State::Body(_) => State::Body(left),
+                        _ if left == 0 => State::ChunkEnd,
+                        _ => State::ChunkData(left),
+                    };
+                    n
+                }
+                State::ChunkSize | State::ChunkEnd | State::Trailers => self.feed_line(data),
+                State::Response => {
+                    self.held.extend_from_slice(data);
+                    data.len()
+                }
+                State::Passthrough => {
+                    self.checked.extend_from_slice(data);
+                    data.len()
+                }
+                State::Discard => data.len(),
+            };
+
+            data = &data[n..];
+        }
+    }
+
+    /// Buffer the head until it is complete, returning the bytes of `data` taken.
+    fn feed_head(&mut self, data: &[u8]) -> usize {
+        let buffered = self.pending.len();
+        self.pending.extend_from_slice(data);
+
+        let (len, state) = match check_head(&mut self.pending) {
+            Ok(Some((len, state, tunnel))) => {
+                self.tunnel = tunnel;
+                let state = match state {
+                    State::Head => self.next_request(),
+                    state => state,
+                };
+                (len, state)
+            }
+            Ok(None) if self.pending.len() <= MAX_HEAD_SIZE => {
+                if self.released == 0 && self.target_too_long() {
+                    self.reject_uri();
+                    return data.len();
+                }
+
+                // nothing to blank out in the request line, and hyper starts reading the
+                // head, with its timeout, only once it has some bytes
+                if self.released == 0 {
+                    if let Some(end) = request_line_end(&self.pending) {
+                        self.checked.extend_from_slice(&self.pending[..end]);
+                        self.released = end;
+                    }
+                }
+                return data.len();
+            }
+            _ => (self.pending.len(), State::Passthrough),
+        };
+
+        if self.released == 0 && self.target_too_long() {
+            self.reject_uri();
+            return data.len();
+        }
+
+        self.requests += 1;
+        self.release_head(len);
+        self.state = state;
+
+        len - buffered
+    }
+
+    /// Whether the target of the request line in `pending`, complete or not, is longer
+    /// than `max_uri_length`.
+    fn target_too_long(&self) -> bool {
+        let Some(max) = self.max_uri_length else {
+            return false;
+        };
+
+        let line = match request_line_end(&self.pending) {
+            Some(end) => &self.pending[..end],
+            None => &self.pending[..],
+        };
+        let line = line
+            .iter()
+            .position(|b| *b != b'\r' && *b != b'\n')
+            .map_or(line, |start| &line[start..]);
+        let Some(start) = line.iter().position(|b| *b == b' ') else {
+            return false;
+        };
+        let target = &line[start + 1..];
+
+        match target.iter().rposition(|b| *b == b' ') {
+            Some(end) if line.ends_with(b"\n") => target_length(&target[..end]) > max,
+            // the target is not complete yet, or is followed by the version
+            _ => target_length(target) > max + VERSION_LEN,
+        }
+    }
+
+    /// Hand hyper a short request closing the connection in place of the one in
+    /// `pending`, and drop the rest of the connection.
+    fn reject_uri(&mut self) {
+        tracing::debug!("request target too long, rejecting");
+
+        self.requests += 1;
+        self.uri_too_long.0.store(self.requests, Ordering::Release);
+        self.tunnel = None;
+        self.checked.extend_from_slice(URI_TOO_LONG_HEAD);
+        self.pending = Vec::new();
+        self.state = State::Discard;
+    }
+
+    /// Check the first `len` bytes of the head, and drop it from `pending`.
+    fn release_head(&mut self, len: usize) {
+        self.checked
+            .extend_from_slice(&self.pending[self.released..len]);
+        self.pending.clear();
+        self.released = 0;
+    }
+
+    /// Buffer a line of a chunked body, returning the bytes of `data` taken.
+    fn feed_line(&mut self, data: &[u8]) -> usize {
+        let n = match data.iter().position(|b| *b == b'\n') {
+            Some(pos) => pos + 1,
+            None => data.len(),
+        };
+        self.checked.extend_from_slice(&data[..n]);
+        self.pending.extend_from_slice(&data[..n]);
+
+        if !self.pending.ends_with(b"\n") {
+            if self.pending.len() > MAX_LINE_SIZE {
+                self.pending.clear();
+                self.state = State::Passthrough;
+            }
+            return n;
+        }
+
+        let line = self
+            .pending
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or_default();
+
+        self.state = match self.state {
+            State::ChunkSize => match parse_chunk_size(line) {
+                Some(0) => State::Trailers,
+                Some(size) => State::ChunkData(size),
+                None => State::Passthrough,
+            },
+            State::ChunkEnd => State::ChunkSize,
+            _ if line.is_empty() => self.next_request(),
+            _ => State::Trailers,
+        };
+        self.pending.clear();
+
+        n
+    }
+
+    /// Follow the response to `tunnel` in `written`, the connection is passed through once
+    /// it is upgraded, otherwise requests are checked again.
+    fn on_written(&mut self, written: &[u8]) {
+        let Some(tunnel) = self.tunnel else {
+            return;
+        };
+        self.response.extend_from_slice(written);
+
+        let upgraded = loop {
+            let Some(end) = find(&self.response, b"\r\n\r\n") else {
+                if self.response.len() > MAX_HEAD_SIZE {
+                    break false;
+                }
+                return;
+            };
+
+            match response_status(&self.response) {
+                Some(101) => break true,
+                Some(100..=199) => {
+                    self.response.drain(..end + 4);
+                }
+                Some(200..=299) => break tunnel == Tunnel::Connect,
+                _ => break false,
+            }
+        };
+
+        self.tunnel = None;
+        self.response = Vec::new();
+
+        if upgraded {
+            self.state = State::Passthrough;
+            self.checked.extend_from_slice(&self.held);
+            self.held = Vec::new();
+        } else if self.state == State::Response {
+            self.state = State::Head;
+            let held = std::mem::take(&mut self.held);
+            self.feed(&held);
+        }
+
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Status code of a response head, e.g. `101` of `HTTP/1.1 101 Switching Protocols`.
+fn response_status(head: &[u8]) -> Option<u16> {
+    let line = head.split(|b| *b == b'\n').next()?;
+    let status = line.split(|b| *b == b' ').nth(1)?;
+
+    std::str::from_utf8(status).ok()?.parse().ok()
+}
+
+/// Check a request head, blanking out `Transfer-Encoding` when `Content-Length` is present
+/// too. Returns the length of the head, the state of what follows and whether it may open a
+/// tunnel, `None` when the head is incomplete.
+fn check_head(buf: &mut [u8]) -> Result<Option<(usize, State, Option<Tunnel>)>, httparse::Error> {
+    let mut headers = vec![httparse::EMPTY_HEADER; 64];
+
+    let (len, transfer_encodings, state, tunnel) = loop {
+        let mut req = httparse::Request::new(&mut headers);
+        let len = match req.parse(buf) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => return Ok(None),
+            Err(httparse::Error::TooManyHeaders) => {
+                let more = headers.len() * 2;
+                headers = vec![httparse::EMPTY_HEADER; more];
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut transfer_encodings: Vec<Range<usize>> = Vec::new();
+        let mut content_length = None;
+        let mut tunnel = (req.method == Some("CONNECT")).then_some(Tunnel::Connect);
+        let mut chunked = false;
+
+        for header in req.headers.iter() {
+            if header.name.eq_ignore_ascii_case("transfer-encoding") {
+                let start = header.value.as_ptr() as usize - buf.as_ptr() as usize;
+                transfer_encodings.push(start..start + header.value.len());
+                chunked = is_chunked(header.value);
+            } else if header.name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    std::str::from_utf8(header.value)
+                        .ok()
+                        .and_then(|v| v.trim().parse::<u64>().ok()),
+                );
+            } else if header.name.eq_ignore_ascii_case("upgrade") {
+                tunnel = tunnel.or(Some(Tunnel::Upgrade));
+            }
+        }
+
+        let state = match (transfer_encodings.is_empty(), content_length) {
+            (false, None) if chunked => State::ChunkSize,
+            (true, None) => State::Head,
+            (true, Some(Some(0))) => State::Head,
+            (true, Some(Some(len))) => State::Body(len),
+            // hyper rejects the rest, and closes the connection
+            _ => State::Passthrough,
+        };
+        let transfer_encodings = match content_length {
+            Some(_) => transfer_encodings,
+            None => Vec::new(),
+        };
+
+        break (len, transfer_encodings, state, tunnel);
+    };
+
+    if !transfer_encodings.is_empty() {
+        tracing::debug!("request with both Transfer-Encoding and Content-Length, rejecting");
+    }
+    for range in transfer_encodings {
+        buf[range].fill(b'x');
+    }
+
+    Ok(Some((len, state, tunnel)))
+}
+
+/// Length of the path and query of a request target, e.g. of `http://host/path?query`.
+fn target_length(target: &[u8]) -> usize {
+    if target.starts_with(b"/") {
+        return target.len();
+    }
+
+    // absolute-form, the authority is not counted
+    match find(target, b"://") {
+        Some(pos) => {
+            let rest = &target[pos + 3..];
+            rest.iter()
+                .position(|b| *b == b'/' || *b == b'?')
+                .map_or(0, |start| rest.len() - start)
+        }
+        None => 0,
+    }
+}
+
+/// End of the request line, after the empty lines allowed before it.
+fn request_line_end(head: &[u8]) -> Option<usize> {
+    let start = head.iter().position(|b| *b != b'\r' && *b != b'\n')?;
+
+    head[start..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map(|pos| start + pos + 1)
+}
+
+/// Whether `chunked` is the final coding, as hyper decides.
+fn is_chunked(value: &[u8]) -> bool {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.rsplit(',').next())
+        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Parse the hex chunk size, ignoring chunk extensions.
+fn parse_chunk_size(line: &[u8]) -> Option<u64> {
+    let line = std::str::from_utf8(line).ok()?;
+    let size = line.split(';').next()?.trim();
+
+    u64::from_str_radix(size, 16).ok()
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for FramingGuard<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        loop {
+            if !this.checked.is_empty() {
+                let n = this.checked.len().min(buf.remaining());
+                buf.put_slice(&this.checked[..n]);
+                this.checked.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            match this.state {
+                State::Passthrough => return Pin::new(&mut this.inner).poll_read(cx, buf),
+                State::Response => {
+                    this.read_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                _ => {}
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.read_buf);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut read_buf) {
+                Poll::Ready(Ok(())) => {}
+                ret => return ret,
+            }
+
+            let filled = read_buf.filled().len();
+            if filled == 0 {
+                // eof, leave the incomplete head to hyper
+                if this.state == State::Head {
+                    this.release_head(this.pending.len());
+                }
+                this.state = State::Passthrough;
+                continue;
+            }
+
+            let data = std::mem::take(&mut this.read_buf);
+            this.feed(&data[..filled]);
+            this.read_buf = data;
+        }
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for FramingGuard<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let ret = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = ret {
+            self.on_written(&buf[..n]);
+        }
+        ret
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let ret = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(mut n)) = ret {
+            for buf in bufs {
+                if n == 0 || self.tunnel.is_none() {
+                    break;
+                }
+                let len = n.min(buf.len());
+                self.on_written(&buf[..len]);
+                n -= len;
+            }
+        }
+        ret
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
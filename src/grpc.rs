@@ -0,0 +1,364 @@
+//! Minimal gRPC and gRPC-web unary framing.
+//!
+//! Messages are length-prefixed: a compressed flag byte and a big-endian `u32` length.
+//! The status is sent in `grpc-status` and `grpc-message` trailers, as HTTP trailers
+//! for gRPC, or as a trailer frame at the end of the body for gRPC-web.
+
+use std::convert::Infallible;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+
+use crate::extracts::ReadBodyRejection;
+use crate::http::{header, HeaderMap, HeaderName, HeaderValue};
+use crate::request::{read_body, FromRequest, RequestParts};
+use crate::response::IntoResponse;
+use crate::Response;
+
+const GRPC_STATUS: HeaderName = HeaderName::from_static("grpc-status");
+const GRPC_MESSAGE: HeaderName = HeaderName::from_static("grpc-message");
+
+const FLAG_COMPRESSED: u8 = 0x01;
+const FLAG_TRAILERS: u8 = 0x80;
+
+/// gRPC status codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrpcStatus {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+/// Frame a message with the length prefix.
+pub fn encode_message(message: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(5 + message.len());
+    buf.put_u8(0);
+    buf.put_u32(message.len() as u32);
+    buf.put_slice(message);
+    buf.freeze()
+}
+
+/// Decode a single length-prefixed message, the frame must span the whole input.
+pub fn decode_message(frame: &[u8]) -> Result<Bytes, GrpcRejection> {
+    if frame.len() < 5 {
+        return Err(GrpcRejectionKind::Malformed.into());
+    }
+    if frame[0] & FLAG_COMPRESSED != 0 {
+        return Err(GrpcRejectionKind::Compressed.into());
+    }
+
+    let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    if frame.len() - 5 != len {
+        return Err(GrpcRejectionKind::Malformed.into());
+    }
+
+    Ok(Bytes::copy_from_slice(&frame[5..]))
+}
+
+/// The length-prefixed request message of a unary call.
+pub struct GrpcMessage {
+    message: Bytes,
+}
+
+impl GrpcMessage {
+    pub fn value(&self) -> &Bytes {
+        &self.message
+    }
+
+    pub fn take(self) -> Bytes {
+        self.message
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for GrpcMessage {
+    type Rejection = GrpcRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let web = is_grpc_web(req.headers());
+
+        let message = match read_body(req).await {
+            Ok(body) => decode_message(&body),
+            Err(e) => Err(GrpcRejectionKind::ReadBody(e).into()),
+        };
+
+        message
+            .map(|message| GrpcMessage { message })
+            .map_err(|e| GrpcRejection { web, ..e })
+    }
+}
+
+#[derive(Debug)]
+pub enum GrpcRejectionKind {
+    ReadBody(ReadBodyRejection),
+    Compressed,
+    Malformed,
+}
+
+/// Rejection of `GrpcMessage`, responded as a gRPC error status.
+#[derive(Debug)]
+pub struct GrpcRejection {
+    kind: GrpcRejectionKind,
+    web: bool,
+}
+
+impl GrpcRejection {
+    pub fn kind(&self) -> &GrpcRejectionKind {
+        &self.kind
+    }
+}
+
+impl From<GrpcRejectionKind> for GrpcRejection {
+    fn from(kind: GrpcRejectionKind) -> Self {
+        GrpcRejection { kind, web: false }
+    }
+}
+
+impl IntoResponse for GrpcRejection {
+    fn into_response(self) -> Response {
+        let resp = match self.kind {
+            GrpcRejectionKind::ReadBody(ReadBodyRejection::TooLarge) => {
+                GrpcResponse::error(GrpcStatus::ResourceExhausted, "message too large")
+            }
+            GrpcRejectionKind::ReadBody(e) => {
+                tracing::error!("GrpcRejection read body failed {:?}", e);
+                GrpcResponse::error(GrpcStatus::Internal, "read message failed")
+            }
+            GrpcRejectionKind::Compressed => {
+                GrpcResponse::error(GrpcStatus::Unimplemented, "compressed message")
+            }
+            GrpcRejectionKind::Malformed => {
+                GrpcResponse::error(GrpcStatus::InvalidArgument, "malformed message")
+            }
+        };
+
+        if self.web {
+            resp.web().into_response()
+        } else {
+            resp.into_response()
+        }
+    }
+}
+
+/// Unary response, with `200 OK` and the status in trailers.
+pub struct GrpcResponse {
+    message: Option<Bytes>,
+    status: GrpcStatus,
+    status_message: Option<String>,
+    web: bool,
+}
+
+impl GrpcResponse {
+    pub fn ok(message: impl Into<Bytes>) -> Self {
+        GrpcResponse {
+            message: Some(message.into()),
+            status: GrpcStatus::Ok,
+            status_message: None,
+            web: false,
+        }
+    }
+
+    pub fn error(status: GrpcStatus, message: impl Into<String>) -> Self {
+        GrpcResponse {
+            message: None,
+            status,
+            status_message: Some(message.into()),
+            web: false,
+        }
+    }
+
+    /// Use gRPC-web framing, with the trailers at the end of the body.
+    pub fn web(mut self) -> Self {
+        self.web = true;
+        self
+    }
+
+    fn trailers(&self) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert(GRPC_STATUS, HeaderValue::from(self.status as u16));
+        if let Some(message) = &self.status_message {
+            if let Ok(message) = HeaderValue::from_str(&percent_encode(message)) {
+                trailers.insert(GRPC_MESSAGE, message);
+            }
+        }
+        trailers
+    }
+}
+
+impl IntoResponse for GrpcResponse {
+    fn into_response(self) -> Response {
+        let trailers = self.trailers();
+
+        let mut frames = Vec::new();
+        if let Some(message) = &self.message {
+            frames.push(Frame::data(encode_message(message)));
+        }
+
+        let content_type = if self.web {
+            let mut block = BytesMut::new();
+            for (name, value) in &trailers {
+                block.put_slice(name.as_str().as_bytes());
+                block.put_slice(b":");
+                block.put_slice(value.as_bytes());
+                block.put_slice(b"\r\n");
+            }
+
+            let mut frame = BytesMut::with_capacity(5 + block.len());
+            frame.put_u8(FLAG_TRAILERS);
+            frame.put_u32(block.len() as u32);
+            frame.put_slice(&block);
+            frames.push(Frame::data(frame.freeze()));
+
+            "application/grpc-web+proto"
+        } else {
+            frames.push(Frame::trailers(trailers));
+
+            "application/grpc"
+        };
+
+        let body = StreamBody::new(futures::stream::iter(
+            frames.into_iter().map(Ok::<_, Infallible>),
+        ));
+
+        let mut resp = crate::http::Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body.map_err(Into::into).boxed())
+            .unwrap();
+        if !self.web {
+            resp.headers_mut().insert(
+                header::TRAILER,
+                HeaderValue::from_static("grpc-status, grpc-message"),
+            );
+        }
+
+        resp
+    }
+}
+
+fn is_grpc_web(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/grpc-web"))
+}
+
+/// Percent-encode `grpc-message` as the gRPC spec requires.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'%' {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    use crate::http::{self, header, StatusCode};
+    use crate::test_helpers::{send, send_raw};
+    use crate::App;
+
+    use super::{decode_message, encode_message, GrpcMessage, GrpcResponse};
+
+    fn call(path: &str, content_type: &str, body: Bytes) -> http::Request<Full<Bytes>> {
+        http::Request::post(path)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::TE, "trailers")
+            .body(Full::new(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn grpc_unary_trailers() {
+        let mut app = App::new();
+        app.post("/echo.Echo/Reverse", |msg: GrpcMessage| async move {
+            let mut reversed = msg.take().to_vec();
+            reversed.reverse();
+            GrpcResponse::ok(reversed)
+        });
+
+        let req = call(
+            "/echo.Echo/Reverse",
+            "application/grpc",
+            encode_message(b"hello"),
+        );
+        let resp = send_raw(app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/grpc");
+
+        let collected = resp.into_body().collect().await.unwrap();
+        assert_eq!(collected.trailers().unwrap()["grpc-status"], "0");
+        assert_eq!(
+            decode_message(&collected.to_bytes()).unwrap(),
+            Bytes::from("olleh")
+        );
+    }
+
+    #[tokio::test]
+    async fn grpc_web_round_trip() {
+        let mut app = App::new();
+        app.post("/echo.Echo/ReverseWeb", |msg: GrpcMessage| async move {
+            let mut reversed = msg.take().to_vec();
+            reversed.reverse();
+            GrpcResponse::ok(reversed).web()
+        });
+
+        let req = call(
+            "/echo.Echo/ReverseWeb",
+            "application/grpc-web+proto",
+            encode_message(b"hello"),
+        );
+        let resp = send(app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.body();
+        let (message, trailers) = body.split_at(5 + 5);
+        assert_eq!(decode_message(message).unwrap(), Bytes::from("olleh"));
+        assert_eq!(trailers[0], 0x80);
+        assert_eq!(&trailers[5..], b"grpc-status:0\r\n");
+    }
+
+    #[tokio::test]
+    async fn grpc_malformed_message() {
+        let mut app = App::new();
+        app.post("/echo.Echo/ReverseWeb", |msg: GrpcMessage| async move {
+            let mut reversed = msg.take().to_vec();
+            reversed.reverse();
+            GrpcResponse::ok(reversed).web()
+        });
+
+        let req = call(
+            "/echo.Echo/ReverseWeb",
+            "application/grpc-web+proto",
+            Bytes::from_static(b"\0\0\0\0\x09hello"),
+        );
+        let resp = send(app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8_lossy(resp.body()).into_owned();
+        assert!(body.contains("grpc-status:3\r\n"));
+        assert!(body.contains("grpc-message:malformed message\r\n"));
+    }
+}
@@ -0,0 +1,316 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::StatusCode;
+use ring::{hmac, signature};
+use serde::de::DeserializeOwned;
+
+use crate::extracts::{BearerRejection, BearerToken};
+use crate::request::{FromRequest, RequestParts};
+use crate::response::IntoResponse;
+use crate::{LieResponse, Response};
+
+/// Key verifying `Jwt` signatures, registered with `App::data`.
+#[derive(Clone)]
+pub struct JwtKey {
+    inner: Arc<KeyInner>,
+}
+
+enum KeyInner {
+    Hs256(hmac::Key),
+    Rs256(Vec<u8>),
+}
+
+impl JwtKey {
+    /// HMAC SHA-256 with a shared secret.
+    pub fn hs256(secret: impl AsRef<[u8]>) -> Self {
+        JwtKey {
+            inner: Arc::new(KeyInner::Hs256(hmac::Key::new(
+                hmac::HMAC_SHA256,
+                secret.as_ref(),
+            ))),
+        }
+    }
+
+    /// RSA PKCS#1 v1.5 SHA-256 with a DER encoded `RSAPublicKey`.
+    pub fn rs256(public_key_der: impl Into<Vec<u8>>) -> Self {
+        JwtKey {
+            inner: Arc::new(KeyInner::Rs256(public_key_der.into())),
+        }
+    }
+
+    fn alg(&self) -> &'static str {
+        match *self.inner {
+            KeyInner::Hs256(_) => "HS256",
+            KeyInner::Rs256(_) => "RS256",
+        }
+    }
+
+    fn verify(&self, message: &[u8], sig: &[u8]) -> bool {
+        match &*self.inner {
+            KeyInner::Hs256(key) => hmac::verify(key, message, sig).is_ok(),
+            KeyInner::Rs256(der) => {
+                signature::UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, der)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+        }
+    }
+
+    /// Verify the token and decode its claims.
+    fn decode<C: DeserializeOwned>(&self, token: &str) -> Result<C, JwtRejection> {
+        let (signed, sig) = token.rsplit_once('.').ok_or(JwtRejection::Malformed)?;
+        let (header, payload) = signed.split_once('.').ok_or(JwtRejection::Malformed)?;
+        if payload.contains('.') {
+            return Err(JwtRejection::Malformed);
+        }
+
+        let header: serde_json::Value = decode_part(header)?;
+        if header.get("alg").and_then(|alg| alg.as_str()) != Some(self.alg()) {
+            return Err(JwtRejection::InvalidSignature);
+        }
+
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig)
+            .map_err(|_| JwtRejection::Malformed)?;
+        if !self.verify(signed.as_bytes(), &sig) {
+            return Err(JwtRejection::InvalidSignature);
+        }
+
+        let claims: serde_json::Value = decode_part(payload)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        if numeric_date(&claims, "exp")?.is_some_and(|exp| now >= exp) {
+            return Err(JwtRejection::Expired);
+        }
+        if numeric_date(&claims, "nbf")?.is_some_and(|nbf| now < nbf) {
+            return Err(JwtRejection::Expired);
+        }
+
+        serde_json::from_value(claims).map_err(|_| JwtRejection::InvalidClaims)
+    }
+}
+
+impl std::fmt::Debug for JwtKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKey").field("alg", &self.alg()).finish()
+    }
+}
+
+/// A NumericDate claim, seconds since the epoch, possibly fractional (RFC 7519).
+fn numeric_date(claims: &serde_json::Value, name: &str) -> Result<Option<f64>, JwtRejection> {
+    match claims.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .filter(|date| date.is_finite())
+            .map(Some)
+            .ok_or(JwtRejection::InvalidClaims),
+    }
+}
+
+fn decode_part(part: &str) -> Result<serde_json::Value, JwtRejection> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| JwtRejection::Malformed)?;
+
+    serde_json::from_slice(&bytes).map_err(|_| JwtRejection::Malformed)
+}
+
+/// Claims of a bearer JWT, verified with the `JwtKey` registered by `App::data`.
+///
+/// The `alg` of the token must match the key. `exp` and `nbf` are checked when present.
+pub struct Jwt<C> {
+    claims: C,
+}
+
+impl<C> Jwt<C> {
+    pub fn value(&self) -> &C {
+        &self.claims
+    }
+
+    pub fn take(self) -> C {
+        self.claims
+    }
+}
+
+#[crate::async_trait]
+impl<C> FromRequest for Jwt<C>
+where
+    C: DeserializeOwned,
+{
+    type Rejection = JwtRejection;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        let key = req
+            .extensions()
+            .get::<JwtKey>()
+            .cloned()
+            .ok_or(JwtRejection::NoKey)?;
+        let token = BearerToken::from_parts(req).map_err(JwtRejection::Bearer)?;
+
+        let claims = key.decode(token.value())?;
+
+        Ok(Jwt { claims })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwtRejection {
+    Bearer(BearerRejection),
+    Malformed,
+    InvalidSignature,
+    Expired,
+    InvalidClaims,
+    /// No `JwtKey` registered, answered with `500 Internal Server Error`.
+    NoKey,
+}
+
+impl IntoResponse for JwtRejection {
+    fn into_response(self) -> Response {
+        let message = match self {
+            JwtRejection::Bearer(e) => return e.into_response(),
+            JwtRejection::NoKey => {
+                return LieResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "can not extract Jwt, no JwtKey",
+                )
+                .into()
+            }
+            JwtRejection::Malformed => "Malformed token",
+            JwtRejection::InvalidSignature => "Invalid token signature",
+            JwtRejection::Expired => "Token expired",
+            JwtRejection::InvalidClaims => "Invalid token claims",
+        };
+
+        LieResponse::new(StatusCode::UNAUTHORIZED, message)
+            .insert_header(
+                hyper::header::WWW_AUTHENTICATE,
+                r#"Bearer error="invalid_token""#,
+            )
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use bytes::Bytes;
+    use http_body_util::Full;
+    use ring::hmac;
+
+    use crate::http::{self, StatusCode};
+    use crate::test_helpers::{get, TestClient};
+    use crate::App;
+
+    use super::{Jwt, JwtKey};
+
+    #[derive(serde::Deserialize)]
+    struct Claims {
+        sub: String,
+    }
+
+    fn sign(secret: &[u8], alg: &str, claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(format!(r#"{{"alg":"{}","typ":"JWT"}}"#, alg));
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signed = format!("{}.{}", header, payload);
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+        let sig = URL_SAFE_NO_PAD.encode(hmac::sign(&key, signed.as_bytes()));
+
+        format!("{}.{}", signed, sig)
+    }
+
+    fn bearer(token: &str) -> http::Request<Full<Bytes>> {
+        http::Request::get("/")
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Full::default())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn jwt_hs256() {
+        let mut app = App::new();
+        app.data(JwtKey::hs256(b"secret"));
+        app.get("/", |claims: Jwt<Claims>| async move {
+            format!("hello, {}", claims.value().sub)
+        });
+        let client = TestClient::new(app);
+
+        let token = sign(b"secret", "HS256", serde_json::json!({ "sub": "lieweb" }));
+        let resp = client.send(bearer(&token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "hello, lieweb");
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers()[http::header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn jwt_invalid() {
+        let mut app = App::new();
+        app.data(JwtKey::hs256(b"secret"));
+        app.get("/", |claims: Jwt<Claims>| async move {
+            format!("hello, {}", claims.value().sub)
+        });
+        let client = TestClient::new(app);
+
+        let tokens = [
+            sign(b"wrong", "HS256", serde_json::json!({ "sub": "lieweb" })),
+            sign(b"secret", "RS256", serde_json::json!({ "sub": "lieweb" })),
+            sign(
+                b"secret",
+                "HS256",
+                serde_json::json!({ "sub": "lieweb", "exp": 1 }),
+            ),
+            sign(b"secret", "HS256", serde_json::json!({ "name": "lieweb" })),
+            "not.a.jwt".to_string(),
+        ];
+
+        for token in tokens {
+            let resp = client.send(bearer(&token)).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "{}", token);
+        }
+    }
+
+    #[tokio::test]
+    async fn jwt_numeric_dates() {
+        let mut app = App::new();
+        app.data(JwtKey::hs256(b"secret"));
+        app.get("/", |claims: Jwt<Claims>| async move {
+            format!("hello, {}", claims.value().sub)
+        });
+        let client = TestClient::new(app);
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+
+        let cases = [
+            (serde_json::json!({ "exp": now + 3600.5 }), "hello, lieweb"),
+            (serde_json::json!({ "exp": 1.7e9 }), "Token expired"),
+            (serde_json::json!({ "nbf": now + 3600.5 }), "Token expired"),
+            (
+                serde_json::json!({ "exp": "9999999999" }),
+                "Invalid token claims",
+            ),
+            (serde_json::json!({ "nbf": "0" }), "Invalid token claims"),
+            (serde_json::json!({ "exp": null }), "Invalid token claims"),
+        ];
+
+        for (mut claims, body) in cases {
+            claims["sub"] = "lieweb".into();
+            let token = sign(b"secret", "HS256", claims.clone());
+            let resp = client.send(bearer(&token)).await;
+            assert_eq!(resp.body(), body, "{}", claims);
+        }
+    }
+}
@@ -1,24 +1,63 @@
+mod config;
+#[cfg(feature = "early-hints")]
+mod early_hints;
 mod endpoint;
 mod error;
 pub mod extracts;
+mod forwarded;
+mod framing;
+pub mod grpc;
+#[cfg(feature = "jwt")]
+mod jwt;
 pub mod middleware;
+#[cfg(feature = "openapi")]
+mod openapi;
+pub mod proxy;
 pub mod request;
 pub mod response;
 mod router;
+#[cfg(feature = "fs")]
+mod serve_dir;
 mod server;
+#[cfg(feature = "signed-cookies")]
+mod signed_cookies;
+#[cfg(test)]
+mod test_helpers;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 #[cfg(feature = "tls")]
 mod tls;
 mod ty;
 mod utils;
+mod write_timeout;
 
-pub use endpoint::{Endpoint, Handler, IntoEndpoint};
-pub use error::Error;
-pub use extracts::{AppState, PathParam, Query, RemoteAddr};
-pub use request::{LieRequest, Request};
-pub use response::{LieResponse, Response};
-pub use router::Router;
+pub use config::{HttpConfig, ServerConfig};
+#[cfg(feature = "early-hints")]
+pub use early_hints::{EarlyHints, Interim};
+pub use endpoint::{blocking, Blocking, Endpoint, Handler, IntoEndpoint};
+pub use error::{Error, ErrorContext, WebError};
+pub use extracts::{
+    AllOf, AllowedMethods, AppState, Auth, AuthChallenge, Authenticator, Authorization,
+    BearerToken, Conditionals, ContentLengthLimit, Cookies, Extension, HeaderValues, Path,
+    PathAndQuery, PathParam, Query, QueryPairs, RemoteAddr, StartTime, Subdomain, TypedHeader,
+};
+pub use forwarded::{ClientIp, ForwardedHeader, TrustedProxies};
+#[cfg(feature = "jwt")]
+pub use jwt::{Jwt, JwtKey, JwtRejection};
+pub use request::{BodyLimit, BodyReadTimeout, BodyStream, ContentSniffing, LieRequest, Request};
+pub use response::{CachedResponse, LieResponse, Response};
+pub use router::{RouteHandle, RouteTags, Router, Scope};
+#[cfg(feature = "fs")]
+pub use serve_dir::ServeDir;
 pub use server::{server_id, App};
-pub use ty::{BytesBody, Form, Html, Json, StreamBody};
+#[cfg(feature = "signed-cookies")]
+pub use signed_cookies::{CookieKey, SignedCookies};
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+pub use ty::{
+    BytesBody, Form, Html, Json, JsonOrForm, KeepAliveStream, Paginated, PrettyJson,
+    RateLimitHeaders, Redirect, StreamBody, Streaming,
+};
 
 // reexport
 pub use async_trait::async_trait;
@@ -27,3 +66,5 @@ pub use headers;
 pub use hyper;
 pub use hyper::http;
 pub use mime;
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
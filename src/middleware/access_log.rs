@@ -1,9 +1,59 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde_json::{Map, Value};
+
 use crate::{
-    middleware::{Middleware, Next},
+    middleware::{response_complete::on_complete, Middleware, Next, RequestId},
     request::RequestCtx,
     Request, Response,
 };
 
+type Writer = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Field of a `JsonAccessLog` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogField {
+    /// `method`, e.g. `"GET"`.
+    Method,
+    /// `path`, without the query.
+    Path,
+    /// `status`, e.g. `200`.
+    Status,
+    /// `duration_ms`, from the request to the end of the response body.
+    Duration,
+    /// `remote_addr`, `null` when unknown.
+    RemoteAddr,
+    /// `request_id`, assigned by the `RequestId` middleware registered before, else `null`.
+    RequestId,
+    /// `bytes`, response body bytes sent.
+    Bytes,
+}
+
+impl AccessLogField {
+    const ALL: [AccessLogField; 7] = [
+        AccessLogField::Method,
+        AccessLogField::Path,
+        AccessLogField::Status,
+        AccessLogField::Duration,
+        AccessLogField::RemoteAddr,
+        AccessLogField::RequestId,
+        AccessLogField::Bytes,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            AccessLogField::Method => "method",
+            AccessLogField::Path => "path",
+            AccessLogField::Status => "status",
+            AccessLogField::Duration => "duration_ms",
+            AccessLogField::RemoteAddr => "remote_addr",
+            AccessLogField::RequestId => "request_id",
+            AccessLogField::Bytes => "bytes",
+        }
+    }
+}
+
 /// A simple requests logger
 #[derive(Debug, Default)]
 pub struct AccessLog;
@@ -41,3 +91,169 @@ impl Middleware for AccessLog {
         self.log_basic(ctx, next).await
     }
 }
+
+/// A requests logger writing a JSON object per request
+///
+/// Each line is written to stdout, or the writer set by `writer`, once the response
+/// body is sent, whatever the tracing setup of the app.
+#[derive(Clone)]
+pub struct JsonAccessLog {
+    writer: Writer,
+    fields: Vec<AccessLogField>,
+}
+
+impl JsonAccessLog {
+    /// Log all fields to stdout, unless set by `fields` and `writer`.
+    pub fn new() -> Self {
+        JsonAccessLog {
+            writer: Arc::new(|line| println!("{}", line)),
+            fields: AccessLogField::ALL.to_vec(),
+        }
+    }
+
+    /// Set the fields of log lines.
+    pub fn fields(&mut self, fields: impl IntoIterator<Item = AccessLogField>) {
+        self.fields = fields.into_iter().collect();
+    }
+
+    /// Write log lines, without the trailing newline, with `writer` instead of stdout.
+    pub fn writer<F>(&mut self, writer: F)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.writer = Arc::new(writer);
+    }
+}
+
+impl Default for JsonAccessLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for JsonAccessLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonAccessLog")
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for JsonAccessLog {
+    async fn handle<'a>(&'a self, ctx: Request, next: Next<'a>) -> Response {
+        let mut line = Map::new();
+        for field in &self.fields {
+            let value = match field {
+                AccessLogField::Method => Value::from(ctx.method().as_str()),
+                AccessLogField::Path => Value::from(ctx.uri().path()),
+                AccessLogField::RemoteAddr => RequestCtx::extract_remote_addr(&ctx)
+                    .map_or(Value::Null, |addr| Value::from(addr.to_string())),
+                AccessLogField::RequestId => RequestId::get(&ctx).map_or(Value::Null, Value::from),
+                // filled once the response is complete
+                AccessLogField::Status | AccessLogField::Duration | AccessLogField::Bytes => {
+                    Value::Null
+                }
+            };
+            line.insert(field.key().to_string(), value);
+        }
+
+        let start = Instant::now();
+        let res = next.run(ctx).await;
+
+        let writer = self.writer.clone();
+        let fields = self.fields.clone();
+        on_complete(
+            res,
+            start,
+            Arc::new(move |completed| {
+                let mut line = line.clone();
+                for field in &fields {
+                    let value = match field {
+                        AccessLogField::Status => Value::from(completed.status().as_u16()),
+                        AccessLogField::Duration => {
+                            Value::from(completed.elapsed().as_secs_f64() * 1000.0)
+                        }
+                        AccessLogField::Bytes => Value::from(completed.bytes()),
+                        _ => continue,
+                    };
+                    line.insert(field.key().to_string(), value);
+                }
+
+                writer(&Value::Object(line).to_string());
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::Level;
+
+    use crate::test_helpers::{get, send, LogCapture};
+    use crate::App;
+
+    use super::{AccessLog, AccessLogField, JsonAccessLog};
+
+    #[tokio::test]
+    async fn access_log() {
+        let capture = LogCapture::new();
+
+        let mut app = App::new();
+        app.middleware(AccessLog);
+        app.get("/", || async { "ok" });
+
+        send(app, get("/missing")).await;
+
+        let events = capture.events();
+        let event = events
+            .iter()
+            .find(|e| e.target() == "lieweb::middleware::access_log")
+            .expect("access log event");
+        assert_eq!(event.level(), Level::INFO);
+        assert_eq!(event.field("method"), Some("GET"));
+        assert_eq!(event.field("path"), Some("/missing"));
+        capture.assert_field("status", "404");
+    }
+
+    #[tokio::test]
+    async fn json_access_log() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::middleware::RequestId;
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+
+        let mut access_log = JsonAccessLog::new();
+        let writer = lines.clone();
+        access_log.writer(move |line| writer.lock().unwrap().push(line.to_string()));
+
+        let mut app = App::new();
+        app.middleware(RequestId::new());
+        app.middleware(access_log.clone());
+        app.get("/", || async { "hello" });
+        send(app, get("/")).await;
+
+        let line: serde_json::Value =
+            serde_json::from_str(&lines.lock().unwrap().pop().unwrap()).unwrap();
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["path"], "/");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["bytes"], 5);
+        assert_eq!(line["remote_addr"], "127.0.0.1:12345");
+        assert!(line["duration_ms"].is_number());
+        assert!(line["request_id"].is_string());
+
+        access_log.fields([AccessLogField::Status, AccessLogField::Path]);
+        let mut app = App::new();
+        app.middleware(access_log);
+        app.get("/", || async { "hello" });
+        send(app, get("/missing")).await;
+
+        let line: serde_json::Value =
+            serde_json::from_str(&lines.lock().unwrap().pop().unwrap()).unwrap();
+        let keys: Vec<_> = line.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, ["path", "status"]);
+        assert_eq!(line["status"], 404);
+    }
+}
@@ -0,0 +1,137 @@
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use futures::FutureExt;
+
+use crate::http::StatusCode;
+use crate::{
+    middleware::{Middleware, Next},
+    LieResponse, Request, Response,
+};
+
+type PanicHandler = Arc<dyn Fn(&str) -> Response + Send + Sync>;
+
+/// Turn a panic in the remaining middleware chain or the handler into a
+/// `500 Internal Server Error` response, instead of dropping the connection.
+///
+/// The panic message is logged with `tracing`. Add it first, so it covers all other
+/// middlewares.
+#[derive(Clone, Default)]
+pub struct CatchPanic {
+    handler: Option<PanicHandler>,
+}
+
+impl CatchPanic {
+    pub fn new() -> Self {
+        CatchPanic { handler: None }
+    }
+
+    /// Build the response from the panic message.
+    pub fn handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&str) -> Response + Send + Sync + 'static,
+    {
+        self.handler = Some(Arc::new(handler));
+    }
+}
+
+impl std::fmt::Debug for CatchPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CatchPanic")
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for CatchPanic {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+
+        match AssertUnwindSafe(next.run(req)).catch_unwind().await {
+            Ok(resp) => resp,
+            Err(payload) => {
+                let message = panic_message(payload.as_ref());
+                tracing::error!("handler panicked on {} {}: {}", method, path, message);
+
+                match &self.handler {
+                    Some(handler) => handler(message),
+                    None => {
+                        LieResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+                            .into()
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, send, LogCapture, TestClient};
+    use crate::{App, LieResponse};
+
+    use super::CatchPanic;
+
+    #[tokio::test]
+    async fn catch_panic() {
+        let mut app = App::new();
+        app.middleware(CatchPanic::new());
+        app.get("/", || async { "ok" });
+        app.get("/panic", || async {
+            if true {
+                panic!("boom");
+            }
+            "unreachable"
+        });
+        let client = TestClient::new(app);
+        let capture = LogCapture::new();
+
+        let resp = client.send(get("/panic")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(capture
+            .logs()
+            .contains("handler panicked on GET /panic: boom"));
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn custom_panic_handler() {
+        let mut catch = CatchPanic::new();
+        catch.handler(|message| {
+            LieResponse::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("panic: {}", message),
+            )
+            .into()
+        });
+
+        let mut app = App::new();
+        app.middleware(catch);
+        app.get("/panic", || async {
+            if true {
+                panic!("boom");
+            }
+            "unreachable"
+        });
+
+        let resp = send(app, get("/panic")).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.body(), "panic: boom");
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use http_body_util::{BodyExt, Full};
+
+use crate::http::{header, StatusCode};
+use crate::request::RequestCodec;
+use crate::response::IntoResponse;
+use crate::{
+    middleware::{Middleware, Next},
+    Error, LieResponse, Request, Response,
+};
+
+const DEFAULT_MAX_SIZE: usize = 1024 * 1024;
+
+/// Transform whole request and response bodies, e.g. to wrap JSON in an envelope or
+/// to encrypt bodies.
+pub trait BodyCodec: Send + Sync + 'static {
+    /// Transform the request body as read by extractors or `LieRequest::read_body`,
+    /// errors are rejected with `400 Bad Request`.
+    fn decode_request(&self, body: Bytes) -> Result<Bytes, Error>;
+
+    /// Transform the response body, errors give `500 Internal Server Error`.
+    fn encode_response(&self, body: Bytes) -> Result<Bytes, Error>;
+}
+
+/// Apply a `BodyCodec` to the bodies of all requests and responses.
+///
+/// Only `2xx` response bodies are encoded, so rejections keep their plain bodies.
+/// Bodies are buffered to be transformed, request bodies larger than `max_size` are
+/// rejected with `413 Payload Too Large` and larger response bodies are replaced with
+/// `500 Internal Server Error` rather than sent untransformed.
+pub struct Codec<C> {
+    codec: Arc<C>,
+    max_size: usize,
+}
+
+impl<C: BodyCodec> Codec<C> {
+    /// Buffer at most 1MiB by default.
+    pub fn new(codec: C) -> Self {
+        Codec {
+            codec: Arc::new(codec),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    pub fn max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    async fn encode(&self, resp: Response) -> Response {
+        if !resp.status().is_success() || resp.status() == StatusCode::NO_CONTENT {
+            return resp;
+        }
+
+        let content_length = resp
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if content_length.is_some_and(|len| len > self.max_size as u64) {
+            return self.too_large();
+        }
+
+        let (mut parts, mut body) = resp.into_parts();
+
+        let mut buf = BytesMut::new();
+        while let Some(frame) = body.frame().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    tracing::error!("Codec read response body failed: {}", e.report());
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+
+            if let Some(data) = frame.data_ref() {
+                if buf.len() + data.len() > self.max_size {
+                    return self.too_large();
+                }
+                buf.extend_from_slice(data);
+            }
+        }
+
+        let body = match self.codec.encode_response(buf.freeze()) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Codec encode response failed: {}", e.report());
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+
+        Response::from_parts(parts, Full::new(body).map_err(Into::into).boxed())
+    }
+
+    fn too_large(&self) -> Response {
+        tracing::error!("Codec response body exceeds {} bytes", self.max_size);
+        LieResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Response too large").into()
+    }
+}
+
+impl<C> std::fmt::Debug for Codec<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Codec")
+            .field("codec", &std::any::type_name::<C>())
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+#[crate::async_trait]
+impl<C: BodyCodec> Middleware for Codec<C> {
+    async fn handle<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+        req.extensions_mut().insert(RequestCodec {
+            codec: self.codec.clone(),
+            max_size: self.max_size,
+        });
+
+        let resp = next.run(req).await;
+
+        self.encode(resp).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, StatusCode};
+    use crate::test_helpers::{get, TestClient};
+    use crate::{App, Error, Json, LieResponse};
+
+    use super::{BodyCodec, Codec};
+
+    /// Wrap bodies as `{"data": ...}`.
+    struct Envelope;
+
+    impl BodyCodec for Envelope {
+        fn decode_request(&self, body: Bytes) -> Result<Bytes, Error> {
+            let mut envelope: serde_json::Value = serde_json::from_slice(&body)?;
+            let data = envelope
+                .get_mut("data")
+                .map(serde_json::Value::take)
+                .ok_or_else(|| crate::error_msg!("missing data"))?;
+
+            Ok(serde_json::to_vec(&data)?.into())
+        }
+
+        fn encode_response(&self, body: Bytes) -> Result<Bytes, Error> {
+            let data: serde_json::Value = serde_json::from_slice(&body)?;
+
+            Ok(serde_json::to_vec(&serde_json::json!({ "data": data }))?.into())
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Greeting {
+        name: String,
+    }
+
+    fn post_json(body: &'static str) -> http::Request<Full<Bytes>> {
+        http::Request::post("/greet")
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn envelope_codec() {
+        let mut app = App::new();
+        app.middleware(Codec::new(Envelope));
+        app.post("/greet", |greeting: Json<Greeting>| async move {
+            LieResponse::with_json(Greeting {
+                name: format!("hello, {}", greeting.value().name),
+            })
+        });
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(post_json(r#"{"data":{"name":"lieweb"}}"#))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), r#"{"data":{"name":"hello, lieweb"}}"#);
+
+        let resp = client.send(post_json(r#"{"name":"lieweb"}"#)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn codec_max_size() {
+        let mut app = App::new();
+        let mut codec = Codec::new(Envelope);
+        codec.max_size(16);
+        app.middleware(codec);
+        app.post("/greet", |_: Json<Greeting>| async { "unreachable" });
+        app.get("/large", || async {
+            LieResponse::with_json(vec![0u8; 1024])
+        });
+        let client = TestClient::new(app);
+
+        let body = r#"{"data":{"name":"a very long name"}}"#;
+        let resp = client.send(post_json(body)).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = client.send(get("/large")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,446 @@
+use std::io;
+
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder};
+use async_compression::Level;
+use bytes::Bytes;
+use futures::TryStreamExt;
+use http_body_util::{combinators::BoxBody, BodyExt, StreamBody};
+use hyper::body::{Body, Frame};
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::http::{header, HeaderValue, Method, StatusCode};
+use crate::{
+    middleware::{Middleware, Next},
+    Error, Request, Response,
+};
+
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Compression level, trade CPU for bandwidth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionLevel {
+    Fastest,
+    #[default]
+    Default,
+    Best,
+    /// Algorithm specific level, e.g. 1-9 for gzip.
+    Precise(i32),
+}
+
+impl From<CompressionLevel> for Level {
+    fn from(level: CompressionLevel) -> Self {
+        match level {
+            CompressionLevel::Fastest => Level::Fastest,
+            CompressionLevel::Default => Level::Default,
+            CompressionLevel::Best => Level::Best,
+            CompressionLevel::Precise(n) => Level::Precise(n),
+        }
+    }
+}
+
+/// Content coding used by `Compression`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    Gzip,
+    /// zlib format, as `Content-Encoding: deflate` requires.
+    Deflate,
+    Brotli,
+}
+
+impl CompressionAlgorithm {
+    fn encoding(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Deflate => "deflate",
+            CompressionAlgorithm::Brotli => "br",
+        }
+    }
+}
+
+/// Compress response bodies with the first configured algorithm the client accepts,
+/// gzip only by default.
+///
+/// Responses smaller than `min_size` (1KB by default), already encoded, partial, or with a
+/// denied content type (images, audio, video, archives and event streams by default) are
+/// left untouched. Bodies are compressed as a stream, they are never buffered as a whole.
+/// Strong `ETag`s of compressed responses are made weak, as the bytes differ from the
+/// uncompressed representation.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    algorithms: Vec<CompressionAlgorithm>,
+    level: CompressionLevel,
+    min_size: u64,
+    allowlist: Vec<String>,
+    denylist: Vec<String>,
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Compression {
+            algorithms: vec![CompressionAlgorithm::Gzip],
+            level: CompressionLevel::Default,
+            min_size: DEFAULT_MIN_SIZE,
+            allowlist: Vec::new(),
+            denylist: [
+                "image/*",
+                "audio/*",
+                "video/*",
+                "application/gzip",
+                "application/zip",
+                "application/x-7z-compressed",
+                "application/x-bzip2",
+                "application/x-rar-compressed",
+                "application/x-xz",
+                "application/zstd",
+                // compressed events would be held back until the encoder fills a block
+                "text/event-stream",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        }
+    }
+
+    /// Algorithms in order of preference, e.g. `[Brotli, Gzip]`.
+    pub fn algorithms(&mut self, algorithms: impl IntoIterator<Item = CompressionAlgorithm>) {
+        self.algorithms = algorithms.into_iter().collect();
+    }
+
+    pub fn level(&mut self, level: CompressionLevel) {
+        self.level = level;
+    }
+
+    /// Do not compress responses with a known size smaller than `size` bytes.
+    pub fn min_size(&mut self, size: u64) {
+        self.min_size = size;
+    }
+
+    /// Only compress these content types, e.g. `text/*` or `application/json`.
+    pub fn allow(&mut self, content_type: impl Into<String>) {
+        self.allowlist.push(content_type.into());
+    }
+
+    /// Never compress these content types, e.g. `image/*`.
+    pub fn deny(&mut self, content_type: impl Into<String>) {
+        self.denylist.push(content_type.into());
+    }
+
+    fn should_compress(&self, resp: &Response) -> bool {
+        let status = resp.status();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || status == StatusCode::PARTIAL_CONTENT
+        {
+            return false;
+        }
+
+        // ranges refer to the uncompressed bytes
+        if resp.headers().contains_key(header::CONTENT_ENCODING)
+            || resp.headers().contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+
+        if let Some(size) = resp.body().size_hint().exact() {
+            if size < self.min_size {
+                return false;
+            }
+        }
+
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<mime::Mime>().ok())
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+
+        if self
+            .denylist
+            .iter()
+            .any(|pattern| mime_matches(pattern, &content_type))
+        {
+            return false;
+        }
+
+        self.allowlist.is_empty()
+            || self
+                .allowlist
+                .iter()
+                .any(|pattern| mime_matches(pattern, &content_type))
+    }
+
+    async fn compress<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let algorithm = if req.method() == Method::HEAD {
+            None
+        } else {
+            self.algorithms
+                .iter()
+                .find(|algorithm| accepts_encoding(&req, algorithm.encoding()))
+                .copied()
+        };
+
+        let resp = next.run(req).await;
+
+        let algorithm = match algorithm {
+            Some(algorithm) if self.should_compress(&resp) => algorithm,
+            _ => return resp,
+        };
+
+        let (mut parts, body) = resp.into_parts();
+
+        let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+        let level = self.level.into();
+        let body = match algorithm {
+            CompressionAlgorithm::Gzip => encoded_body(GzipEncoder::with_quality(reader, level)),
+            CompressionAlgorithm::Deflate => encoded_body(ZlibEncoder::with_quality(reader, level)),
+            CompressionAlgorithm::Brotli => {
+                encoded_body(BrotliEncoder::with_quality(reader, level))
+            }
+        };
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        if let Some(etag) = parts.headers.get(header::ETAG).and_then(weak_etag) {
+            parts.headers.insert(header::ETAG, etag);
+        }
+        parts.headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(algorithm.encoding()),
+        );
+        parts
+            .headers
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+        Response::from_parts(parts, body)
+    }
+}
+
+fn encoded_body<R>(encoder: R) -> BoxBody<Bytes, Error>
+where
+    R: AsyncRead + Send + Sync + 'static,
+{
+    let body = ReaderStream::new(encoder)
+        .map_ok(Frame::data)
+        .map_err(Error::from);
+
+    BodyExt::boxed(StreamBody::new(body))
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for Compression {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        self.compress(req, next).await
+    }
+}
+
+/// Check `Accept-Encoding` for `encoding`, ignoring the ones with `q=0`.
+fn accepts_encoding(req: &Request, encoding: &str) -> bool {
+    req.headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let rejected = parts.any(|p| {
+                p.strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .map(|q| q == 0.0)
+                    .unwrap_or(false)
+            });
+
+            (name.eq_ignore_ascii_case(encoding) || name == "*") && !rejected
+        })
+}
+
+/// Weak form of a strong `ETag`, `None` if it is weak already.
+fn weak_etag(etag: &HeaderValue) -> Option<HeaderValue> {
+    if etag.as_bytes().starts_with(b"W/") {
+        return None;
+    }
+
+    let mut weak = b"W/".to_vec();
+    weak.extend_from_slice(etag.as_bytes());
+    HeaderValue::from_bytes(&weak).ok()
+}
+
+/// Match `type/subtype` or `type/*` against the essence of `content_type`.
+fn mime_matches(pattern: &str, content_type: &mime::Mime) -> bool {
+    match pattern.split_once('/') {
+        Some((ty, "*")) => ty.eq_ignore_ascii_case(content_type.type_().as_str()),
+        _ => pattern.eq_ignore_ascii_case(content_type.essence_str()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use crate::http::{self, header, StatusCode};
+    use crate::test_helpers::{send, TestClient};
+    use crate::App;
+
+    use super::{Compression, CompressionAlgorithm};
+
+    fn get(uri: &str) -> http::Request<http_body_util::Empty<bytes::Bytes>> {
+        http::Request::get(uri)
+            .header(header::ACCEPT_ENCODING, "gzip, deflate")
+            .body(http_body_util::Empty::new())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn small_response_uncompressed() {
+        let mut app = App::new();
+        app.middleware(Compression::new());
+        app.get("/small", || async { "hello" });
+
+        let resp = send(app, get("/small")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.body(), "hello");
+    }
+
+    #[tokio::test]
+    async fn large_text_compressed() {
+        let mut app = App::new();
+        app.middleware(Compression::new());
+        app.get("/large", || async { "hello, world! ".repeat(200) });
+
+        let resp = send(app, get("/large")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&resp.body()[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "hello, world! ".repeat(200));
+    }
+
+    #[tokio::test]
+    async fn algorithm_preference() {
+        use tokio::io::AsyncReadExt;
+
+        let mut compression = Compression::new();
+        compression.algorithms([CompressionAlgorithm::Brotli, CompressionAlgorithm::Deflate]);
+
+        let mut app = App::new();
+        app.middleware(compression);
+        app.get("/large", || async { "hello, world! ".repeat(200) });
+
+        // br is not accepted, fall back to deflate
+        let resp = send(app, get("/large")).await;
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "deflate");
+        let mut decoded = String::new();
+        flate2::read::ZlibDecoder::new(&resp.body()[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "hello, world! ".repeat(200));
+
+        let mut compression = Compression::new();
+        compression.algorithms([CompressionAlgorithm::Brotli, CompressionAlgorithm::Gzip]);
+
+        let mut app = App::new();
+        app.middleware(compression);
+        app.get("/large", || async { "hello, world! ".repeat(200) });
+
+        let req = http::Request::get("/large")
+            .header(header::ACCEPT_ENCODING, "gzip, br")
+            .body(http_body_util::Empty::<bytes::Bytes>::new())
+            .unwrap();
+        let resp = send(app, req).await;
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+        assert!(resp.headers().get(header::CONTENT_LENGTH).is_none());
+        let mut decoded = String::new();
+        async_compression::tokio::bufread::BrotliDecoder::new(&resp.body()[..])
+            .read_to_string(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, "hello, world! ".repeat(200));
+    }
+
+    #[tokio::test]
+    async fn denied_content_type_uncompressed() {
+        let mut app = App::new();
+        app.middleware(Compression::new());
+        app.get("/image", || async {
+            crate::LieResponse::from(crate::BytesBody::new(vec![0u8; 4096], mime::IMAGE_PNG))
+        });
+
+        let resp = send(app, get("/image")).await;
+
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.body().len(), 4096);
+    }
+
+    #[tokio::test]
+    async fn partial_content_uncompressed() {
+        let mut app = App::new();
+        app.middleware(Compression::new());
+        app.get("/partial", || async {
+            crate::LieResponse::new(StatusCode::PARTIAL_CONTENT, "a".repeat(2048))
+                .insert_header(header::CONTENT_RANGE, "bytes 0-2047/4096")
+        });
+        app.get("/range", || async {
+            crate::LieResponse::from("a".repeat(2048))
+                .insert_header(header::CONTENT_RANGE, "bytes 0-2047/2048")
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/partial")).await;
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.body().len(), 2048);
+
+        let resp = client.send(get("/range")).await;
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.body().len(), 2048);
+    }
+
+    #[tokio::test]
+    async fn event_stream_uncompressed() {
+        let mut app = App::new();
+        app.middleware(Compression::new());
+        app.get("/events", || async {
+            crate::LieResponse::from("data: hello\n\n".repeat(200))
+                .insert_header(header::CONTENT_TYPE, "text/event-stream")
+        });
+
+        let resp = send(app, get("/events")).await;
+
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    #[tokio::test]
+    async fn strong_etag_weakened() {
+        let mut app = App::new();
+        app.middleware(Compression::new());
+        app.get("/strong", || async {
+            crate::LieResponse::from("hello, world! ".repeat(200))
+                .insert_header(header::ETAG, "\"v1\"")
+        });
+        app.get("/weak", || async {
+            crate::LieResponse::from("hello, world! ".repeat(200))
+                .insert_header(header::ETAG, "W/\"v1\"")
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/strong")).await;
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.headers()[header::ETAG], "W/\"v1\"");
+
+        let resp = client.send(get("/weak")).await;
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.headers()[header::ETAG], "W/\"v1\"");
+    }
+}
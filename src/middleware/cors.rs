@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use crate::http::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Method, StatusCode,
+};
+use crate::{
+    middleware::{Middleware, Next},
+    response::IntoResponse,
+    Request, Response,
+};
+
+#[derive(Debug, Clone)]
+enum AllowOrigin {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+/// Cross-Origin Resource Sharing.
+///
+/// Preflight requests are answered with `204 No Content` without reaching the
+/// handler. The `Access-Control-Allow-*` headers are only sent when the `Origin`
+/// matches the configured origins.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: AllowOrigin,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// No origin is allowed until configured, methods default to `GET`, `HEAD`
+    /// and `POST`.
+    pub fn new() -> Self {
+        Cors {
+            origins: AllowOrigin::List(Vec::new()),
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow an exact origin, e.g. `https://example.com`.
+    pub fn allow_origin(&mut self, origin: &str) {
+        let origin = match HeaderValue::from_str(origin) {
+            Ok(origin) => origin,
+            Err(e) => {
+                tracing::error!("Cors.allow_origin error: {}", e);
+                return;
+            }
+        };
+
+        match &mut self.origins {
+            AllowOrigin::Any => {}
+            AllowOrigin::List(list) => list.push(origin),
+        }
+    }
+
+    /// Allow any origin, `*`. With credentials, the request origin is echoed back.
+    pub fn allow_any_origin(&mut self) {
+        self.origins = AllowOrigin::Any;
+    }
+
+    pub fn allow_methods(&mut self, methods: impl IntoIterator<Item = Method>) {
+        self.methods = methods.into_iter().collect();
+    }
+
+    pub fn allow_headers(&mut self, headers: impl IntoIterator<Item = HeaderName>) {
+        self.headers = headers.into_iter().collect();
+    }
+
+    pub fn allow_credentials(&mut self, allow: bool) {
+        self.credentials = allow;
+    }
+
+    pub fn max_age(&mut self, max_age: Duration) {
+        self.max_age = Some(max_age);
+    }
+
+    fn allowed_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.origins {
+            AllowOrigin::Any if self.credentials => Some(origin.clone()),
+            AllowOrigin::Any => Some(HeaderValue::from_static("*")),
+            AllowOrigin::List(list) => list.iter().find(|o| *o == origin).cloned(),
+        }
+    }
+
+    fn append_origin(&self, headers: &mut HeaderMap, origin: Option<&HeaderValue>) -> bool {
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+
+        let allowed = match origin.and_then(|o| self.allowed_origin(o)) {
+            Some(allowed) => allowed,
+            None => return false,
+        };
+
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        true
+    }
+
+    fn preflight(&self, origin: Option<&HeaderValue>) -> Response {
+        let mut resp = StatusCode::NO_CONTENT.into_response();
+        let headers = resp.headers_mut();
+
+        if !self.append_origin(headers, origin) {
+            return resp;
+        }
+
+        if let Some(methods) = join(self.methods.iter().map(Method::as_str)) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if let Some(allow_headers) = join(self.headers.iter().map(HeaderName::as_str)) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(max_age.as_secs()),
+            );
+        }
+
+        resp
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn join<'a>(items: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let joined = items.collect::<Vec<_>>().join(", ");
+    if joined.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&joined).ok()
+}
+
+#[crate::async_trait]
+impl Middleware for Cors {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let origin = req.headers().get(header::ORIGIN).cloned();
+
+        let is_preflight = req.method() == Method::OPTIONS
+            && origin.is_some()
+            && req
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            return self.preflight(origin.as_ref());
+        }
+
+        let mut resp = next.run(req).await;
+        if origin.is_some() {
+            self.append_origin(resp.headers_mut(), origin.as_ref());
+        }
+
+        resp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, header, Method, StatusCode};
+    use crate::test_helpers::{get, send, TestClient};
+    use crate::App;
+
+    use super::Cors;
+
+    fn cors() -> Cors {
+        let mut cors = Cors::new();
+        cors.allow_origin("https://example.com");
+        cors.allow_methods([Method::GET, Method::PUT]);
+        cors.allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]);
+        cors.allow_credentials(true);
+        cors.max_age(Duration::from_secs(600));
+        cors
+    }
+
+    fn with_origin(origin: &str) -> http::Request<Full<Bytes>> {
+        let mut req = get("/");
+        req.headers_mut()
+            .insert(header::ORIGIN, origin.parse().unwrap());
+        req
+    }
+
+    fn preflight(origin: &str) -> http::Request<Full<Bytes>> {
+        let mut req = with_origin(origin);
+        *req.method_mut() = Method::OPTIONS;
+        req.headers_mut().insert(
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            "PUT".parse().unwrap(),
+        );
+        req
+    }
+
+    #[tokio::test]
+    async fn cors_preflight() {
+        let mut app = App::new();
+        app.middleware(cors());
+        app.get("/", || async { "hello" });
+
+        let resp = send(app, preflight("https://example.com")).await;
+
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let headers = resp.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type, authorization"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert!(resp.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cors_actual_request() {
+        let mut app = App::new();
+        app.middleware(cors());
+        app.get("/", || async { "hello" });
+
+        let resp = send(app, with_origin("https://example.com")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+        assert_eq!(resp.body(), "hello");
+    }
+
+    #[tokio::test]
+    async fn cors_origin_mismatch() {
+        let mut app = App::new();
+        app.middleware(cors());
+        app.get("/", || async { "hello" });
+        let client = TestClient::new(app);
+
+        let resp = client.send(preflight("https://evil.com")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+        assert!(resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_METHODS)
+            .is_none());
+
+        let resp = client.send(with_origin("https://evil.com")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+        assert!(resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn cors_any_origin() {
+        let mut cors = Cors::new();
+        cors.allow_any_origin();
+
+        let mut app = App::new();
+        app.middleware(cors);
+        app.get("/", || async { "hello" });
+
+        let resp = send(app, with_origin("https://any.com")).await;
+        assert_eq!(resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+}
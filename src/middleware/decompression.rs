@@ -0,0 +1,175 @@
+use crate::http::{header, HeaderValue, StatusCode};
+use crate::request::ContentCoding;
+use crate::response::IntoResponse;
+use crate::{
+    middleware::{Middleware, Next},
+    LieResponse, Request, Response,
+};
+
+/// Decode request bodies with `Content-Encoding` gzip, deflate or br.
+///
+/// The body is decoded as it is read by `Json`, `Form`, `BytesBody` or
+/// `LieRequest::read_body`, so `BodyLimit` applies to the decoded size, and
+/// `Content-Encoding` and `Content-Length` are removed then. Handlers reading the raw body,
+/// like `BodyStream`, see it encoded with the headers intact. Other encodings are rejected
+/// with `415 Unsupported Media Type`, and corrupt bodies with `400 Bad Request` when read.
+#[derive(Debug, Clone, Default)]
+pub struct Decompression;
+
+impl Decompression {
+    pub fn new() -> Self {
+        Decompression
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for Decompression {
+    async fn handle<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+        let encoding = match req.headers().get(header::CONTENT_ENCODING) {
+            Some(encoding) => encoding.to_str().unwrap_or_default().trim(),
+            None => return next.run(req).await,
+        };
+
+        if !encoding.eq_ignore_ascii_case("identity") {
+            let coding = match ContentCoding::parse(encoding) {
+                Some(coding) => coding,
+                None => {
+                    return LieResponse::new(
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "Unsupported Content-Encoding",
+                    )
+                    .insert_header(
+                        header::ACCEPT_ENCODING,
+                        HeaderValue::from_static("gzip, deflate, br"),
+                    )
+                    .into_response();
+                }
+            };
+
+            req.extensions_mut().insert(coding);
+        }
+
+        next.run(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    use crate::extracts::{Rejected, RejectionKind};
+    use crate::http::{self, header, StatusCode};
+    use crate::test_helpers::{send, TestClient};
+    use crate::{App, BodyLimit, BytesBody, Json, LieResponse};
+
+    use super::Decompression;
+
+    #[derive(serde::Deserialize)]
+    struct Login {
+        name: String,
+    }
+
+    fn post(uri: &str, encoding: &str, body: impl Into<Bytes>) -> http::Request<Full<Bytes>> {
+        http::Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_ENCODING, encoding)
+            .body(Full::new(body.into()))
+            .unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn decompress_json() {
+        let mut app = App::new();
+        app.middleware(Decompression::new());
+        app.post("/json", |login: Json<Login>| async move {
+            format!("hello, {}", login.value().name)
+        });
+        app.post("/bytes", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        let client = TestClient::new(app);
+
+        let body = gzip(br#"{"name":"lieweb"}"#);
+        let resp = client.send(post("/json", "gzip", body)).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "hello, lieweb");
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello, deflate").unwrap();
+        let body = encoder.finish().unwrap();
+        let resp = client.send(post("/bytes", "deflate", body)).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "hello, deflate");
+    }
+
+    #[tokio::test]
+    async fn raw_body_keeps_headers() {
+        let mut app = App::new();
+        app.middleware(Decompression::new());
+        app.post("/raw", |req: crate::Request| async move {
+            let encoding = req.headers().get(header::CONTENT_ENCODING).cloned();
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            format!("{:?} {}", encoding, body.len())
+        });
+
+        let body = gzip(br#"{"name":"lieweb"}"#);
+        let len = body.len();
+        let resp = send(app, post("/raw", "gzip", body)).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), &format!("Some(\"gzip\") {}", len));
+    }
+
+    #[tokio::test]
+    async fn unsupported_encoding() {
+        let mut app = App::new();
+        app.middleware(Decompression::new());
+        app.post("/bytes", |_: BytesBody| async move { "ok" });
+
+        let resp = send(app, post("/bytes", "zstd", "whatever")).await;
+
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(resp.headers()[header::ACCEPT_ENCODING], "gzip, deflate, br");
+    }
+
+    #[tokio::test]
+    async fn corrupt_body() {
+        let mut app = App::new();
+        app.middleware(Decompression::new());
+        app.rejection(RejectionKind::DecodeFailed, |rejected: &Rejected| {
+            LieResponse::new(StatusCode::BAD_REQUEST, format!("{:?}", rejected.kind()))
+        });
+        app.post("/bytes", |_: BytesBody| async move { "ok" });
+
+        let resp = send(app, post("/bytes", "gzip", "not gzip at all")).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.body(), "DecodeFailed");
+    }
+
+    #[tokio::test]
+    async fn limit_decoded_size() {
+        let mut app = App::new();
+        app.middleware(Decompression::new());
+        app.data(BodyLimit(1024));
+        app.post("/bytes", |_: BytesBody| async move { "ok" });
+
+        let body = gzip(&vec![b'a'; 64 * 1024]);
+        assert!(body.len() < 1024);
+        let resp = send(app, post("/bytes", "gzip", body)).await;
+
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use crate::http::header;
+use crate::{
+    extracts::Subdomain,
+    middleware::{Middleware, Next},
+    Request, Response, Router,
+};
+
+use super::require_host::{canonicalize, split_port};
+
+/// Route requests to a router by host, e.g. `api.example.com`, falling back to the routes
+/// of the app when no host matches.
+///
+/// The host is taken from `Host`, or the URI authority for HTTP/2, and compared case
+/// insensitively without the port. A `*.example.com` pattern matches any subdomain of
+/// `example.com`, and the matched subdomain, e.g. `acme` of `acme.example.com`, is
+/// extracted by `Subdomain`. Hosts are matched in registration order.
+#[derive(Default)]
+pub struct HostRouter {
+    hosts: Vec<(String, Arc<Router>)>,
+}
+
+impl HostRouter {
+    pub fn new() -> Self {
+        HostRouter { hosts: Vec::new() }
+    }
+
+    /// Route requests to `host`, e.g. `example.com` or `*.example.com`, with `router`.
+    pub fn host(&mut self, host: impl AsRef<str>, router: Router) -> &mut Self {
+        let host = canonicalize(host.as_ref());
+        let host = split_port(&host).0.to_string();
+
+        self.hosts.push((host, Arc::new(router)));
+        self
+    }
+
+    fn find(&self, host: &str) -> Option<(&Router, Option<Subdomain>)> {
+        let host = canonicalize(host);
+        let hostname = split_port(&host).0;
+
+        self.hosts
+            .iter()
+            .find_map(|(pattern, router)| match pattern.strip_prefix("*.") {
+                Some(domain) => hostname
+                    .strip_suffix(domain)
+                    .and_then(|sub| sub.strip_suffix('.'))
+                    .filter(|sub| !sub.is_empty())
+                    .map(|sub| (router.as_ref(), Some(Subdomain::new(sub)))),
+                None => (hostname == pattern).then_some((router.as_ref(), None)),
+            })
+    }
+}
+
+impl std::fmt::Debug for HostRouter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.hosts.iter().map(|(host, _)| host))
+            .finish()
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for HostRouter {
+    async fn handle<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| req.uri().authority().map(|a| a.as_str()));
+
+        match host.and_then(|host| self.find(host)) {
+            Some((router, subdomain)) => {
+                if let Some(subdomain) = subdomain {
+                    req.extensions_mut().insert(subdomain);
+                }
+                router.route(req).await
+            }
+            None => next.run(req).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, header, StatusCode};
+    use crate::test_helpers::TestClient;
+    use crate::{App, Router, Subdomain};
+
+    use super::HostRouter;
+
+    fn get(host: &str) -> http::Request<Full<Bytes>> {
+        http::Request::get("/")
+            .header(header::HOST, host)
+            .body(Full::default())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn subdomain_param() {
+        let mut tenants = Router::new();
+        tenants.get("/", |tenant: Subdomain| async move {
+            format!("tenant = {:?}", tenant.value())
+        });
+
+        let mut api = Router::new();
+        api.get("/", || async { "api" });
+
+        let mut hosts = HostRouter::new();
+        hosts.host("api.example.com", api);
+        hosts.host("*.example.com", tenants);
+
+        let mut app = App::new();
+        app.middleware(hosts);
+        app.get("/", || async { "main" });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("acme.example.com")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), r#"tenant = "acme""#);
+
+        let resp = client.send(get("Globex.Example.com:8080")).await;
+        assert_eq!(resp.body(), r#"tenant = "globex""#);
+
+        let resp = client.send(get("api.example.com")).await;
+        assert_eq!(resp.body(), "api");
+
+        for host in ["example.com", "acme.example.org"] {
+            let resp = client.send(get(host)).await;
+            assert_eq!(resp.body(), "main", "{}", host);
+        }
+    }
+}
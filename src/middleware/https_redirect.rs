@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use crate::http::{header, uri::Authority, StatusCode};
+use crate::{
+    middleware::{Middleware, Next},
+    LieResponse, Request, Response,
+};
+
+const HTTP_PORT: u16 = 80;
+const HTTPS_PORT: u16 = 443;
+
+/// Redirect all requests to HTTPS with `308 Permanent Redirect`, preserving path and query.
+///
+/// Use it on an app serving the plain HTTP port. Port 80 maps to the default HTTPS
+/// port, other ports are kept unless mapped by `map_port`. Set `public_authority`
+/// to redirect to a fixed host and port instead of the `Host` of the request.
+#[derive(Debug, Clone, Default)]
+pub struct HttpsRedirect {
+    ports: HashMap<u16, u16>,
+    public_authority: Option<Authority>,
+}
+
+impl HttpsRedirect {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redirect requests on `http_port` to `https_port`, e.g. `8080` to `8443`.
+    pub fn map_port(&mut self, http_port: u16, https_port: u16) {
+        self.ports.insert(http_port, https_port);
+    }
+
+    /// Redirect to the public host and port, e.g. `example.com:8443`.
+    pub fn public_authority(&mut self, authority: &str) -> Result<(), crate::Error> {
+        let authority = authority
+            .parse::<Authority>()
+            .map_err(|e| crate::Error::Message(format!("invalid public authority: {}", e)))?;
+        self.public_authority = Some(authority);
+        Ok(())
+    }
+
+    fn target_authority(&self, req: &Request) -> Option<String> {
+        if let Some(authority) = &self.public_authority {
+            return Some(authority.to_string());
+        }
+
+        let authority = match req.uri().authority() {
+            Some(authority) => authority.clone(),
+            None => req
+                .headers()
+                .get(header::HOST)?
+                .to_str()
+                .ok()?
+                .parse::<Authority>()
+                .ok()?,
+        };
+
+        let port = match authority.port_u16() {
+            None | Some(HTTP_PORT) => self.ports.get(&HTTP_PORT).copied(),
+            Some(port) => Some(self.ports.get(&port).copied().unwrap_or(port)),
+        };
+
+        match port {
+            None | Some(HTTPS_PORT) => Some(authority.host().to_string()),
+            Some(port) => Some(format!("{}:{}", authority.host(), port)),
+        }
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for HttpsRedirect {
+    async fn handle<'a>(&'a self, req: Request, _next: Next<'a>) -> Response {
+        let authority = match self.target_authority(&req) {
+            Some(authority) => authority,
+            None => return LieResponse::with_status(StatusCode::BAD_REQUEST).into(),
+        };
+
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+
+        LieResponse::with_status(StatusCode::PERMANENT_REDIRECT)
+            .insert_header(
+                header::LOCATION,
+                format!("https://{}{}", authority, path_and_query),
+            )
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, header, StatusCode};
+    use crate::test_helpers::send;
+    use crate::App;
+
+    use super::HttpsRedirect;
+
+    async fn redirect(redirect: HttpsRedirect, host: &str, uri: &str) -> http::Response<Bytes> {
+        let mut app = App::new();
+        app.middleware(redirect);
+
+        let req = http::Request::get(uri)
+            .header(header::HOST, host)
+            .body(Full::<Bytes>::default())
+            .unwrap();
+
+        send(app, req).await
+    }
+
+    #[tokio::test]
+    async fn default_ports() {
+        let resp = redirect(HttpsRedirect::new(), "example.com", "/x?page=2").await;
+
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            resp.headers()[header::LOCATION],
+            "https://example.com/x?page=2"
+        );
+
+        let resp = redirect(HttpsRedirect::new(), "example.com:80", "/x").await;
+        assert_eq!(resp.headers()[header::LOCATION], "https://example.com/x");
+
+        // unmapped ports are kept
+        let resp = redirect(HttpsRedirect::new(), "example.com:8000", "/x").await;
+        assert_eq!(
+            resp.headers()[header::LOCATION],
+            "https://example.com:8000/x"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_ports() {
+        let mut https = HttpsRedirect::new();
+        https.map_port(8080, 8443);
+        https.map_port(80, 8443);
+
+        let resp = redirect(https.clone(), "example.com:8080", "/x?q=1").await;
+        assert_eq!(
+            resp.headers()[header::LOCATION],
+            "https://example.com:8443/x?q=1"
+        );
+
+        let resp = redirect(https, "[::1]", "/").await;
+        assert_eq!(resp.headers()[header::LOCATION], "https://[::1]:8443/");
+
+        let mut https = HttpsRedirect::new();
+        https.public_authority("secure.example.com:9443").unwrap();
+
+        let resp = redirect(https, "example.com:8080", "/x").await;
+        assert_eq!(
+            resp.headers()[header::LOCATION],
+            "https://secure.example.com:9443/x"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::http::StatusCode;
+use crate::{
+    middleware::{Middleware, Next},
+    LieResponse, Request, Response,
+};
+
+/// Answer `503 Service Unavailable` for all requests while maintenance mode is on.
+///
+/// The switch is shared by all clones, so keep a clone around to toggle it at runtime.
+/// Paths in the allowlist, such as `/healthz`, are always passed through.
+#[derive(Debug, Clone)]
+pub struct Maintenance {
+    enabled: Arc<AtomicBool>,
+    allowlist: HashSet<String>,
+    body: Bytes,
+}
+
+impl Maintenance {
+    pub fn new() -> Self {
+        Maintenance {
+            enabled: Arc::new(AtomicBool::new(false)),
+            allowlist: HashSet::new(),
+            body: Bytes::from_static(b"Service Unavailable"),
+        }
+    }
+
+    /// Let requests on `path` pass through during maintenance.
+    pub fn allow(&mut self, path: impl Into<String>) {
+        self.allowlist.insert(path.into());
+    }
+
+    /// Set the response body for rejected requests.
+    pub fn body(&mut self, body: impl Into<Bytes>) {
+        self.body = body.into();
+    }
+
+    pub fn enable(&self) {
+        self.set_enabled(true);
+    }
+
+    pub fn disable(&self) {
+        self.set_enabled(false);
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Release);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for Maintenance {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        if self.is_enabled() && !self.allowlist.contains(req.uri().path()) {
+            return LieResponse::new(StatusCode::SERVICE_UNAVAILABLE, self.body.clone()).into();
+        }
+
+        next.run(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, TestClient};
+    use crate::App;
+
+    use super::Maintenance;
+
+    #[tokio::test]
+    async fn toggle_at_runtime() {
+        let mut maintenance = Maintenance::new();
+        maintenance.allow("/healthz");
+        maintenance.body("down for maintenance");
+
+        let mut app = App::new();
+        app.middleware(maintenance.clone());
+        app.get("/", || async { "index" });
+        app.get("/healthz", || async { "ok" });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        maintenance.enable();
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.body(), "down for maintenance");
+
+        let resp = client.send(get("/healthz")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "ok");
+
+        maintenance.disable();
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Frame, SizeHint};
+
+use crate::request::{ReceivedBytes, RequestCtx};
+use crate::{
+    middleware::{Middleware, Next},
+    Error, Request, Response,
+};
+
+/// Label of requests matching no route.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Debug, Default)]
+struct RouteBytes {
+    received: Arc<AtomicU64>,
+    sent: Arc<AtomicU64>,
+}
+
+/// Count request and response body bytes per route, labeled by the path pattern the
+/// route was registered with, e.g. `/users/:id`. Routes of nested routers are labeled
+/// with the prefix they are merged at, e.g. `/api/users/:id`.
+///
+/// Counters grow as bodies stream, so a long download is visible before it ends.
+/// Received bytes are counted as the body is read, by extractors or
+/// `LieRequest::read_body`, and unread bodies are not counted. Keep a clone to read the
+/// counters, clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    routes: Arc<Mutex<HashMap<Arc<str>, RouteBytes>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Request body bytes received by `route`.
+    pub fn bytes_received(&self, route: &str) -> u64 {
+        self.get(route, |bytes| &bytes.received)
+    }
+
+    /// Response body bytes sent by `route`.
+    pub fn bytes_sent(&self, route: &str) -> u64 {
+        self.get(route, |bytes| &bytes.sent)
+    }
+
+    /// Routes with their received and sent bytes.
+    pub fn snapshot(&self) -> Vec<(String, u64, u64)> {
+        let routes = self.routes.lock().unwrap();
+
+        routes
+            .iter()
+            .map(|(route, bytes)| {
+                (
+                    route.to_string(),
+                    bytes.received.load(Ordering::Relaxed),
+                    bytes.sent.load(Ordering::Relaxed),
+                )
+            })
+            .collect()
+    }
+
+    fn get(&self, route: &str, counter: impl Fn(&RouteBytes) -> &Arc<AtomicU64>) -> u64 {
+        let routes = self.routes.lock().unwrap();
+
+        routes
+            .get(route)
+            .map_or(0, |bytes| counter(bytes).load(Ordering::Relaxed))
+    }
+
+    fn counters(&self, route: Arc<str>) -> (Arc<AtomicU64>, Arc<AtomicU64>) {
+        let mut routes = self.routes.lock().unwrap();
+        let bytes = routes.entry(route).or_default();
+
+        (bytes.received.clone(), bytes.sent.clone())
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for Metrics {
+    async fn handle<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+        let route = RequestCtx::route_pattern(&req)
+            .map_or_else(|| Arc::from(UNMATCHED_ROUTE), |pattern| pattern.clone());
+        let (received, sent) = self.counters(route);
+
+        req.extensions_mut().insert(ReceivedBytes(received));
+
+        let resp = next.run(req).await;
+
+        resp.map(|body| CountingBody { inner: body, sent }.boxed())
+    }
+}
+
+struct CountingBody {
+    inner: BoxBody<Bytes, Error>,
+    sent: Arc<AtomicU64>,
+}
+
+impl Body for CountingBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let ret = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &ret {
+            if let Some(data) = frame.data_ref() {
+                self.sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+
+        ret
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_helpers::{get, post, TestClient};
+    use crate::{App, BytesBody, Router};
+
+    use super::Metrics;
+
+    #[tokio::test]
+    async fn count_bytes() {
+        let metrics = Metrics::new();
+        let mut app = App::new();
+        app.middleware(metrics.clone());
+        app.post("/echo/:id", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        app.get("/hello", || async { "hello" });
+        let client = TestClient::new(app);
+
+        client
+            .send(post("/echo/1", "text/plain", "0123456789"))
+            .await;
+        client.send(post("/echo/2", "text/plain", "abcde")).await;
+        assert_eq!(metrics.bytes_received("/echo/:id"), 15);
+        assert_eq!(metrics.bytes_sent("/echo/:id"), 15);
+
+        client.send(get("/hello")).await;
+        assert_eq!(metrics.bytes_received("/hello"), 0);
+        assert_eq!(metrics.bytes_sent("/hello"), 5);
+
+        client.send(get("/missing")).await;
+        assert_eq!(metrics.snapshot().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn nested_routes() {
+        let metrics = Metrics::new();
+        let mut users = Router::new();
+        users.get("/:id", || async { "user" });
+
+        let mut posts = Router::new();
+        posts.get("/:id", || async { "post" });
+        // a router with its own metrics sees the full pattern too
+        posts.middleware(metrics.clone());
+
+        let mut app = App::new();
+        app.middleware(metrics.clone());
+        app.get("/top", || async { "top" });
+        app.merge("/users/", users).unwrap();
+        app.merge("/posts/", posts).unwrap();
+        let client = TestClient::new(app);
+
+        client.send(get("/top")).await;
+        client.send(get("/users/1")).await;
+        client.send(get("/posts/1")).await;
+
+        assert_eq!(metrics.bytes_sent("/top"), 3);
+        assert_eq!(metrics.bytes_sent("/users/:id"), 4);
+        // counted by both middlewares
+        assert_eq!(metrics.bytes_sent("/posts/:id"), 8);
+        assert_eq!(metrics.bytes_sent("unmatched"), 0);
+        assert_eq!(metrics.snapshot().len(), 3);
+    }
+}
@@ -1,19 +1,56 @@
 // import mod
 mod access_log;
+mod catch_panic;
+mod codec;
+#[cfg(feature = "compression")]
+mod compression;
+mod cors;
+#[cfg(feature = "compression")]
+mod decompression;
 mod default_headers;
+mod host_router;
+mod https_redirect;
+mod maintenance;
+mod metrics;
 mod request_id;
+mod request_timer;
+mod require_host;
+mod response_complete;
+mod response_header_limit;
+mod server_timing;
+mod tagged;
+mod timeout;
 mod with_state;
 
-pub use access_log::AccessLog;
+pub use access_log::{AccessLog, AccessLogField, JsonAccessLog};
+pub use catch_panic::CatchPanic;
+pub use codec::{BodyCodec, Codec};
+#[cfg(feature = "compression")]
+pub use compression::{Compression, CompressionAlgorithm, CompressionLevel};
+pub use cors::Cors;
+#[cfg(feature = "compression")]
+pub use decompression::Decompression;
 pub use default_headers::DefaultHeaders;
+pub use host_router::HostRouter;
+pub use https_redirect::HttpsRedirect;
+pub use maintenance::Maintenance;
+pub use metrics::Metrics;
 pub use request_id::RequestId;
-pub use with_state::WithState;
+pub use request_timer::RequestTimer;
+pub use require_host::RequireHost;
+pub use response_complete::{CompletedResponse, OnResponseComplete};
+pub use response_header_limit::{HeaderLimitAction, ResponseHeaderLimit};
+pub use server_timing::{ServerTiming, Timings};
+pub use tagged::Tagged;
+pub use timeout::Timeout;
+pub use with_state::{FromRef, WithState};
 
 use std::future::Future;
 use std::sync::Arc;
 
 use crate::endpoint::DynEndpoint;
 use crate::request::Request;
+use crate::router::ErrorRenderers;
 use crate::Response;
 
 /// Middleware that wraps around remaining middleware chain.
@@ -44,16 +81,24 @@ where
 pub struct Next<'a> {
     pub(crate) endpoint: &'a DynEndpoint,
     pub(crate) next_middleware: &'a [Arc<dyn Middleware>],
+    pub(crate) errors: Option<&'a ErrorRenderers>,
 }
 
 impl<'a> Next<'a> {
     /// Asynchronously execute the remaining middleware chain.
     pub async fn run(mut self, req: Request) -> Response {
-        if let Some((current, next)) = self.next_middleware.split_first() {
+        let errors = self.errors;
+
+        let resp = if let Some((current, next)) = self.next_middleware.split_first() {
             self.next_middleware = next;
             current.handle(req, self).await
         } else {
             (self.endpoint).call(req).await
+        };
+
+        match errors {
+            Some(errors) => errors.render(resp),
+            None => resp,
         }
     }
 }
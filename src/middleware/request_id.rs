@@ -1,27 +1,79 @@
+use crate::http::{HeaderName, HeaderValue};
 use crate::{
     middleware::{Middleware, Next},
     Request, Response,
 };
 
 const RANDOM_STRING_LEN: usize = 6;
+const MAX_INBOUND_LEN: usize = 128;
 
-#[derive(Debug, Clone, Default)]
-pub struct RequestId;
+/// Assign each request an id, available to handlers by `RequestId::get`.
+///
+/// An inbound id in the `X-Request-ID` header, or the header set by `header`, is reused
+/// when it is at most 128 chars of ASCII alphanumerics, `-`, `_`, `.` or `:`, otherwise
+/// a random id is generated. The id is echoed in the same response header.
+#[derive(Debug, Clone)]
+pub struct RequestId {
+    header: HeaderName,
+}
 
 impl RequestId {
+    pub fn new() -> Self {
+        RequestId {
+            header: HeaderName::from_static("x-request-id"),
+        }
+    }
+
+    /// Use another header, e.g. `X-Correlation-ID`.
+    pub fn header(&mut self, header: HeaderName) {
+        self.header = header;
+    }
+
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header
+    }
+
     pub fn get(req: &Request) -> Option<&str> {
         let val = req.extensions().get::<RequestIdValue>();
         val.map(|v| v.value.as_str())
     }
 }
 
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_INBOUND_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
 #[crate::async_trait]
 impl Middleware for RequestId {
     async fn handle<'a>(&'a self, mut ctx: Request, next: Next<'a>) -> Response {
-        let val = RequestIdValue::new(crate::utils::gen_random_string(RANDOM_STRING_LEN));
-        ctx.extensions_mut().insert(val);
+        let id = ctx
+            .headers()
+            .get(&self.header)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| is_valid_id(id))
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| crate::utils::gen_random_string(RANDOM_STRING_LEN));
+
+        let header_value = HeaderValue::from_str(&id).ok();
+        ctx.extensions_mut().insert(RequestIdValue::new(id));
+
+        let mut resp = next.run(ctx).await;
+
+        if let Some(value) = header_value {
+            resp.headers_mut().insert(self.header.clone(), value);
+        }
 
-        next.run(ctx).await
+        resp
     }
 }
 
@@ -35,3 +87,59 @@ impl RequestIdValue {
         RequestIdValue { value }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, HeaderName};
+    use crate::test_helpers::{get, send, TestClient};
+    use crate::{App, Request};
+
+    use super::RequestId;
+
+    fn with_header(name: &str, value: &str) -> http::Request<Full<Bytes>> {
+        http::Request::get("/")
+            .header(name, value)
+            .body(Full::default())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn inbound_request_id() {
+        let mut app = App::new();
+        app.middleware(RequestId::new());
+        app.get("/", |req: Request| async move {
+            RequestId::get(&req).unwrap_or_default().to_string()
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(with_header("x-request-id", "abc-123")).await;
+        assert_eq!(resp.headers()["x-request-id"], "abc-123");
+        assert_eq!(resp.body(), "abc-123");
+
+        let resp = client.send(with_header("x-request-id", "bad id!")).await;
+        let id = resp.headers()["x-request-id"].to_str().unwrap().to_string();
+        assert_ne!(id, "bad id!");
+        assert_eq!(resp.body(), id.as_str());
+
+        let resp = client.send(get("/")).await;
+        assert!(!resp.body().is_empty());
+        assert_eq!(resp.headers()["x-request-id"], resp.body().as_ref());
+    }
+
+    #[tokio::test]
+    async fn custom_header() {
+        let mut request_id = RequestId::new();
+        request_id.header(HeaderName::from_static("x-correlation-id"));
+
+        let mut app = App::new();
+        app.middleware(request_id);
+        app.get("/", || async { "ok" });
+
+        let resp = send(app, with_header("x-correlation-id", "trace-1")).await;
+        assert_eq!(resp.headers()["x-correlation-id"], "trace-1");
+        assert!(resp.headers().get("x-request-id").is_none());
+    }
+}
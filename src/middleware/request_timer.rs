@@ -0,0 +1,59 @@
+use std::time::{Instant, SystemTime};
+
+use crate::{
+    extracts::StartTime,
+    middleware::{Middleware, Next},
+    Request, Response,
+};
+
+/// Stamp the request start time, handlers get it by the `StartTime` extractor.
+#[derive(Debug, Clone, Default)]
+pub struct RequestTimer;
+
+#[crate::async_trait]
+impl Middleware for RequestTimer {
+    async fn handle<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+        req.extensions_mut()
+            .insert(StartTime::new(Instant::now(), SystemTime::now()));
+
+        next.run(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::extracts::StartTime;
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, send};
+    use crate::App;
+
+    use super::RequestTimer;
+
+    #[tokio::test]
+    async fn elapsed() {
+        let mut app = App::new();
+        app.middleware(RequestTimer);
+        app.get("/", |start: StartTime| async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            start.elapsed().as_millis().to_string()
+        });
+
+        let resp = send(app, get("/")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let elapsed: u128 = std::str::from_utf8(resp.body()).unwrap().parse().unwrap();
+        assert!(elapsed >= 20);
+    }
+
+    #[tokio::test]
+    async fn missing_timer() {
+        let mut app = App::new();
+        app.get("/", |_: StartTime| async { "" });
+
+        let resp = send(app, get("/")).await;
+
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::http::{header, StatusCode};
+use crate::{
+    middleware::{Middleware, Next},
+    response::IntoResponse,
+    Request, Response,
+};
+
+/// Reject requests whose host is not allowed with `400 Bad Request`, against Host header
+/// attacks such as cache or password reset poisoning.
+///
+/// The host is taken from `Host`, or the URI authority for HTTP/2, and compared case
+/// insensitively without a trailing dot. Patterns without a port match any port, and
+/// `*.example.com` matches any subdomain of `example.com`. With no allowed hosts, all
+/// requests pass.
+#[derive(Debug, Clone, Default)]
+pub struct RequireHost {
+    allowlist: Vec<String>,
+}
+
+impl RequireHost {
+    pub fn new() -> Self {
+        RequireHost {
+            allowlist: Vec::new(),
+        }
+    }
+
+    /// Allow a host, e.g. `example.com`, `example.com:8080` or `*.example.com`.
+    pub fn allow(&mut self, host: impl AsRef<str>) {
+        self.allowlist.push(canonicalize(host.as_ref()));
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        let host = canonicalize(host);
+        let hostname = split_port(&host).0;
+
+        self.allowlist.iter().any(|pattern| {
+            let (pattern_host, pattern_port) = split_port(pattern);
+            let candidate = if pattern_port.is_some() {
+                host.as_str()
+            } else {
+                hostname
+            };
+            let pattern = if pattern_port.is_some() {
+                pattern.as_str()
+            } else {
+                pattern_host
+            };
+
+            match pattern.strip_prefix("*.") {
+                Some(domain) => candidate
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => candidate == pattern,
+            }
+        })
+    }
+}
+
+pub(super) fn canonicalize(host: &str) -> String {
+    let host = host.trim().to_ascii_lowercase();
+    match split_port(&host) {
+        (name, Some(port)) => format!("{}:{}", name.trim_end_matches('.'), port),
+        (name, None) => name.trim_end_matches('.').to_string(),
+    }
+}
+
+/// Split `host:port`, keeping IPv6 literals like `[::1]` intact.
+pub(super) fn split_port(host: &str) -> (&str, Option<&str>) {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !name.is_empty()
+                && (!name.contains(':') || name.ends_with(']'))
+                && port.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            (name, Some(port))
+        }
+        _ => (host, None),
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for RequireHost {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        if self.allowlist.is_empty() {
+            return next.run(req).await;
+        }
+
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| req.uri().authority().map(|a| a.as_str()));
+
+        match host {
+            Some(host) if self.is_allowed(host) => next.run(req).await,
+            _ => {
+                tracing::debug!("RequireHost rejected host {:?}", host);
+                (StatusCode::BAD_REQUEST, "Invalid Host").into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, header, StatusCode};
+    use crate::test_helpers::{send_bytes, TestClient};
+    use crate::App;
+
+    use super::RequireHost;
+
+    fn require_host() -> RequireHost {
+        let mut require_host = RequireHost::new();
+        require_host.allow("example.com");
+        require_host.allow("*.example.org");
+        require_host.allow("localhost:8080");
+        require_host
+    }
+
+    fn get(host: &str) -> http::Request<Full<Bytes>> {
+        http::Request::get("/")
+            .header(header::HOST, host)
+            .body(Full::default())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn allowed_host() {
+        let mut app = App::new();
+        app.middleware(require_host());
+        app.get("/", || async { "ok" });
+        let client = TestClient::new(app);
+
+        for host in [
+            "example.com",
+            "EXAMPLE.com.",
+            "example.com:443",
+            "api.example.org",
+            "localhost:8080",
+        ] {
+            let resp = client.send(get(host)).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{}", host);
+            assert_eq!(resp.body(), "ok");
+        }
+    }
+
+    #[tokio::test]
+    async fn disallowed_host() {
+        let mut app = App::new();
+        app.middleware(require_host());
+        app.get("/", || async { "ok" });
+        let client = TestClient::new(app);
+
+        for host in [
+            "evil.com",
+            "example.com.evil.com",
+            "example.org",
+            "badexample.org",
+            "localhost:9090",
+        ] {
+            let resp = client.send(get(host)).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", host);
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_host() {
+        let mut app = App::new();
+        app.middleware(require_host());
+        app.get("/", || async { "ok" });
+
+        let resp = send_bytes(app, b"GET / HTTP/1.0\r\n\r\n").await;
+        assert!(resp.starts_with("HTTP/1.0 400 Bad Request"));
+
+        let mut app = App::new();
+        app.middleware(RequireHost::new());
+        app.get("/", || async { "ok" });
+        let resp = send_bytes(app, b"GET / HTTP/1.0\r\n\r\n").await;
+        assert!(resp.starts_with("HTTP/1.0 200 OK"));
+    }
+}
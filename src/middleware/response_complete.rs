@@ -0,0 +1,207 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Frame, SizeHint};
+
+use crate::http::StatusCode;
+use crate::{
+    middleware::{Middleware, Next},
+    Error, Request, Response,
+};
+
+pub(super) type Callback = Arc<dyn Fn(&CompletedResponse) + Send + Sync>;
+
+/// Summary of a response, passed to the `OnResponseComplete` callback.
+#[derive(Debug, Clone)]
+pub struct CompletedResponse {
+    status: StatusCode,
+    bytes: u64,
+    elapsed: Duration,
+    finished: bool,
+}
+
+impl CompletedResponse {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Body bytes sent.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Time from entering the middleware to the end of the body.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Whether the body was sent completely, `false` when it failed or the client went away.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// Run a callback once the response body is fully sent, failed or dropped.
+///
+/// Unlike other middlewares, which return once the headers are ready, this covers
+/// the whole streamed body, for latency and egress accounting.
+#[derive(Clone)]
+pub struct OnResponseComplete {
+    callback: Callback,
+}
+
+impl OnResponseComplete {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&CompletedResponse) + Send + Sync + 'static,
+    {
+        OnResponseComplete {
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for OnResponseComplete {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let start = Instant::now();
+
+        let resp = next.run(req).await;
+
+        on_complete(resp, start, self.callback.clone())
+    }
+}
+
+/// Wrap the body of `resp` to run `callback` once it is fully sent, failed or dropped.
+pub(super) fn on_complete(resp: Response, start: Instant, callback: Callback) -> Response {
+    let status = resp.status();
+    resp.map(|body| {
+        CompletionBody {
+            inner: body,
+            callback: Some(callback),
+            status,
+            bytes: 0,
+            start,
+        }
+        .boxed()
+    })
+}
+
+struct CompletionBody {
+    inner: http_body_util::combinators::BoxBody<Bytes, Error>,
+    callback: Option<Callback>,
+    status: StatusCode,
+    bytes: u64,
+    start: Instant,
+}
+
+impl CompletionBody {
+    fn complete(&mut self, finished: bool) {
+        if let Some(callback) = self.callback.take() {
+            callback(&CompletedResponse {
+                status: self.status,
+                bytes: self.bytes,
+                elapsed: self.start.elapsed(),
+                finished,
+            });
+        }
+    }
+}
+
+impl Body for CompletionBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let ret = Pin::new(&mut self.inner).poll_frame(cx);
+
+        match &ret {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.bytes += data.len() as u64;
+                }
+                if self.inner.is_end_stream() {
+                    self.complete(true);
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.complete(false),
+            Poll::Ready(None) => self.complete(true),
+            Poll::Pending => {}
+        }
+
+        ret
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CompletionBody {
+    fn drop(&mut self) {
+        // empty bodies may never be polled
+        let finished = self.inner.is_end_stream();
+        self.complete(finished);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use http_body_util::BodyExt;
+    use tokio::sync::mpsc;
+
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, send_raw};
+    use crate::{App, Error, Streaming};
+
+    use super::OnResponseComplete;
+
+    #[tokio::test]
+    async fn callback_after_stream() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let mut app = App::new();
+        app.middleware(OnResponseComplete::new(move |completed| {
+            tx.send(completed.clone()).unwrap();
+        }));
+        app.get("/", || async {
+            let s = futures::stream::iter(["hello, ", "world"]).then(|chunk| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok::<_, Error>(chunk)
+            });
+            Streaming::new(s, mime::TEXT_PLAIN)
+        });
+
+        let resp = send_raw(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // headers received, but the body is still streaming
+        assert!(rx.try_recv().is_err());
+
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello, world");
+
+        let completed = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.status(), StatusCode::OK);
+        assert_eq!(completed.bytes(), 12);
+        assert!(completed.finished());
+        assert!(completed.elapsed() >= Duration::from_millis(200));
+    }
+}
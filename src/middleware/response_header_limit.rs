@@ -0,0 +1,162 @@
+use crate::http::HeaderMap;
+use crate::{
+    middleware::{Middleware, Next},
+    Request, Response,
+};
+
+const DEFAULT_MAX_SIZE: usize = 8 * 1024;
+
+/// What `ResponseHeaderLimit` does with an oversized header block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderLimitAction {
+    /// Log a warning and send the headers as is.
+    #[default]
+    Warn,
+    /// Log a warning and drop the headers exceeding the limit.
+    Truncate,
+}
+
+/// Check the size of the serialized response header block, to catch header bloat
+/// such as many `Set-Cookie` before it breaks clients or proxies.
+///
+/// The size counts every `name: value\r\n` line. Add it first, so it inspects the
+/// final headers.
+#[derive(Debug, Clone)]
+pub struct ResponseHeaderLimit {
+    max_size: usize,
+    action: HeaderLimitAction,
+}
+
+impl ResponseHeaderLimit {
+    /// Warn on header blocks larger than 8KiB by default.
+    pub fn new() -> Self {
+        ResponseHeaderLimit {
+            max_size: DEFAULT_MAX_SIZE,
+            action: HeaderLimitAction::default(),
+        }
+    }
+
+    pub fn max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    pub fn action(&mut self, action: HeaderLimitAction) {
+        self.action = action;
+    }
+}
+
+impl Default for ResponseHeaderLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Size of the serialized header block.
+pub(crate) fn header_block_size(headers: &HeaderMap) -> usize {
+    headers.iter().map(|(k, v)| header_line_size(k, v)).sum()
+}
+
+fn header_line_size(name: &crate::http::HeaderName, value: &crate::http::HeaderValue) -> usize {
+    name.as_str().len() + value.len() + 4
+}
+
+/// Keep headers in order until the limit, drop the rest.
+fn truncate(headers: &mut HeaderMap, max_size: usize) {
+    let mut size = 0;
+    let mut kept = HeaderMap::with_capacity(headers.len());
+
+    for (name, value) in headers.iter() {
+        let line = header_line_size(name, value);
+        if size + line > max_size {
+            continue;
+        }
+        size += line;
+        kept.append(name.clone(), value.clone());
+    }
+
+    *headers = kept;
+}
+
+#[crate::async_trait]
+impl Middleware for ResponseHeaderLimit {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let path = req.uri().path().to_string();
+
+        let mut resp = next.run(req).await;
+
+        let size = header_block_size(resp.headers());
+        if size > self.max_size {
+            tracing::warn!(
+                %path,
+                size,
+                max_size = self.max_size,
+                "response header block exceeds limit"
+            );
+
+            if self.action == HeaderLimitAction::Truncate {
+                truncate(resp.headers_mut(), self.max_size);
+            }
+        }
+
+        resp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::header;
+    use crate::test_helpers::{get, send, LogCapture};
+    use crate::{App, LieResponse};
+
+    use super::{header_block_size, HeaderLimitAction, ResponseHeaderLimit};
+
+    fn app(action: HeaderLimitAction) -> App {
+        let mut limit = ResponseHeaderLimit::new();
+        limit.max_size(256);
+        limit.action(action);
+
+        let mut app = App::new();
+        app.middleware(limit);
+        app.get("/", || async {
+            let mut resp = LieResponse::with_str("ok");
+            for i in 0..10 {
+                resp = resp.append_header(
+                    header::SET_COOKIE,
+                    format!("cookie{}={}", i, "x".repeat(32)),
+                );
+            }
+            resp
+        });
+        app
+    }
+
+    #[tokio::test]
+    async fn warn_oversized_headers() {
+        let capture = LogCapture::new();
+
+        let resp = send(app(HeaderLimitAction::Warn), get("/")).await;
+
+        assert_eq!(
+            resp.headers().get_all(header::SET_COOKIE).iter().count(),
+            10
+        );
+        assert!(capture
+            .logs()
+            .contains("response header block exceeds limit"));
+    }
+
+    #[tokio::test]
+    async fn truncate_oversized_headers() {
+        let resp = send(app(HeaderLimitAction::Truncate), get("/")).await;
+
+        let cookies = resp.headers().get_all(header::SET_COOKIE).iter().count();
+        assert!(cookies > 0 && cookies < 10);
+        assert_eq!(resp.body(), "ok");
+
+        let mut headers = resp.headers().clone();
+        // added by the connection after the middleware
+        headers.remove(header::DATE);
+        headers.remove(header::CONTENT_LENGTH);
+        assert!(header_block_size(&headers) <= 256);
+    }
+}
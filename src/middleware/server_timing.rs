@@ -0,0 +1,194 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::http::{self, HeaderName, HeaderValue};
+use crate::{
+    middleware::{Middleware, Next},
+    Request, Response,
+};
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Emit a `Server-Timing` header with durations recorded during the request.
+///
+/// Middlewares and handlers record their durations into [`Timings`], taken from the
+/// request by `Timings::get` or by the extractor. Put it first in the middleware chain,
+/// so its `total` entry covers the others.
+///
+/// Enabled in debug builds by default, as timings may leak internals to clients.
+#[derive(Debug, Clone)]
+pub struct ServerTiming {
+    enabled: bool,
+}
+
+impl ServerTiming {
+    pub fn new() -> Self {
+        ServerTiming {
+            enabled: cfg!(debug_assertions),
+        }
+    }
+
+    /// Emit the header or not, overriding the default of the build.
+    pub fn enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    async fn emit<'a>(&'a self, mut req: Request, next: Next<'a>) -> Response {
+        if !self.enabled {
+            return next.run(req).await;
+        }
+
+        let start = Instant::now();
+
+        let timings = Timings::enabled();
+        req.extensions_mut().insert(timings.clone());
+
+        let mut resp = next.run(req).await;
+
+        timings.record("total", start.elapsed());
+
+        match HeaderValue::from_str(&timings.to_header_value()) {
+            Ok(value) => {
+                resp.headers_mut().append(SERVER_TIMING, value);
+            }
+            Err(e) => {
+                tracing::error!("ServerTiming header error: {}", e);
+            }
+        }
+
+        resp
+    }
+}
+
+impl Default for ServerTiming {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for ServerTiming {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        self.emit(req, next).await
+    }
+}
+
+type Entries = Arc<Mutex<Vec<(String, Duration)>>>;
+
+/// Shared accumulator of `Server-Timing` entries.
+///
+/// Recording is a no-op when `ServerTiming` is not enabled.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    entries: Option<Entries>,
+}
+
+impl Timings {
+    fn enabled() -> Self {
+        Timings {
+            entries: Some(Default::default()),
+        }
+    }
+
+    /// Get the accumulator of the request.
+    pub fn get<B>(req: &http::Request<B>) -> Timings {
+        req.extensions()
+            .get::<Timings>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Record a duration, `name` must be a valid token, e.g. `db` or `auth-req`.
+    pub fn record(&self, name: impl Into<String>, duration: Duration) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().push((name.into(), duration));
+        }
+    }
+
+    fn to_header_value(&self) -> String {
+        let mut value = String::new();
+
+        if let Some(entries) = &self.entries {
+            for (name, duration) in entries.lock().unwrap().iter() {
+                if !value.is_empty() {
+                    value.push_str(", ");
+                }
+                let _ = write!(value, "{};dur={:.3}", name, duration.as_secs_f64() * 1000.0);
+            }
+        }
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use crate::http::StatusCode;
+    use crate::middleware::{Middleware, Next};
+    use crate::test_helpers::{get, send};
+    use crate::{App, Request, Response};
+
+    use super::{ServerTiming, Timings};
+
+    struct Auth;
+
+    #[crate::async_trait]
+    impl Middleware for Auth {
+        async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+            let start = Instant::now();
+            let timings = Timings::get(&req);
+            timings.record("auth-req", start.elapsed());
+
+            let resp = next.run(req).await;
+
+            let start = Instant::now();
+            timings.record("auth-resp", start.elapsed());
+            resp
+        }
+    }
+
+    #[tokio::test]
+    async fn server_timing_header() {
+        let mut server_timing = ServerTiming::new();
+        server_timing.enabled(true);
+
+        let mut app = App::new();
+        app.middleware(server_timing);
+        app.middleware(Auth);
+        app.get("/", |timings: Timings| async move {
+            timings.record("handler", std::time::Duration::from_millis(1));
+            "ok"
+        });
+
+        let resp = send(app, get("/")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let value = resp.headers()["server-timing"].to_str().unwrap();
+        let names: Vec<&str> = value
+            .split(", ")
+            .map(|entry| entry.split(';').next().unwrap())
+            .collect();
+        assert_eq!(names, ["auth-req", "handler", "auth-resp", "total"]);
+        assert!(value.contains("handler;dur=1.000"));
+    }
+
+    #[tokio::test]
+    async fn disabled() {
+        let mut server_timing = ServerTiming::new();
+        server_timing.enabled(false);
+
+        let mut app = App::new();
+        app.middleware(server_timing);
+        app.get("/", |timings: Timings| async move {
+            timings.record("handler", std::time::Duration::from_millis(1));
+            "ok"
+        });
+
+        let resp = send(app, get("/")).await;
+
+        assert!(resp.headers().get("server-timing").is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    middleware::{Middleware, Next},
+    Request, Response, RouteTags,
+};
+
+/// Run the middleware only for routes tagged by `RouteHandle::tag`.
+///
+/// Tags are known once a route matched in the router the middleware is added to, tags
+/// of routes in nested routers are not visible to middlewares of the parent router.
+#[derive(Debug, Clone)]
+pub struct Tagged<M> {
+    tag: String,
+    inner: M,
+}
+
+impl<M: Middleware> Tagged<M> {
+    pub fn new(tag: impl Into<String>, inner: M) -> Self {
+        Tagged {
+            tag: tag.into(),
+            inner,
+        }
+    }
+}
+
+#[crate::async_trait]
+impl<M: Middleware> Middleware for Tagged<M> {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let tagged = req
+            .extensions()
+            .get::<RouteTags>()
+            .map(|tags| tags.contains(&self.tag))
+            .unwrap_or_default();
+
+        if tagged {
+            self.inner.handle(req, next).await
+        } else {
+            next.run(req).await
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::{header, StatusCode};
+    use crate::middleware::{Middleware, Next};
+    use crate::test_helpers::{get, TestClient};
+    use crate::{App, LieResponse, Request, Response};
+
+    use super::Tagged;
+
+    struct Auth;
+
+    #[crate::async_trait]
+    impl Middleware for Auth {
+        async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+            if req.headers().contains_key(header::AUTHORIZATION) {
+                next.run(req).await
+            } else {
+                LieResponse::with_status(StatusCode::UNAUTHORIZED).into()
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn tagged_middleware() {
+        let mut app = App::new();
+        app.middleware(Tagged::new("protected", Auth));
+        app.get("/public", || async { "public" });
+        app.get("/admin", || async { "admin" }).tag("protected");
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/public")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = client.send(get("/admin")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let mut req = get("/admin");
+        req.headers_mut()
+            .insert(header::AUTHORIZATION, "Bearer token".parse().unwrap());
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "admin");
+    }
+}
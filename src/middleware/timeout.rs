@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use crate::http::StatusCode;
+use crate::{
+    middleware::{Middleware, Next},
+    LieResponse, Request, Response,
+};
+
+/// Answer `504 Gateway Timeout` when the remaining middleware chain and the handler do
+/// not produce a response in time, e.g. `Timeout::new(Duration::from_secs(30))`.
+///
+/// The handler future is dropped on timeout, cancelling its pending work. Only the time
+/// until the response headers is limited, not streaming the body.
+#[derive(Debug, Clone)]
+pub struct Timeout {
+    timeout: Duration,
+}
+
+impl Timeout {
+    pub fn new(timeout: Duration) -> Self {
+        Timeout { timeout }
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for Timeout {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        let path = req.uri().path().to_string();
+
+        match tokio::time::timeout(self.timeout, next.run(req)).await {
+            Ok(resp) => resp,
+            Err(_) => {
+                tracing::warn!("request {} timed out after {:?}", path, self.timeout);
+                LieResponse::new(StatusCode::GATEWAY_TIMEOUT, "Gateway Timeout").into()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, send};
+    use crate::App;
+
+    use super::Timeout;
+
+    /// Set the flag when dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let dropped = Arc::new(AtomicBool::new(false));
+
+        let mut app = App::new();
+        app.middleware(Timeout::new(Duration::from_millis(100)));
+        app.get("/fast", || async { "ok" });
+        let flag = dropped.clone();
+        app.get("/slow", move || {
+            let guard = DropFlag(flag.clone());
+            async move {
+                let _guard = guard;
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                "late"
+            }
+        });
+
+        let resp = send(app, get("/slow")).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn no_timeout() {
+        let mut app = App::new();
+        app.middleware(Timeout::new(Duration::from_millis(100)));
+        app.get("/", || async { "ok" });
+
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "ok");
+    }
+}
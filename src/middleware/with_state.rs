@@ -1,30 +1,81 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use crate::{
     middleware::{Middleware, Next},
     request::RequestParts,
     Request, Response,
 };
 
-#[derive(Debug, Clone)]
+/// Derive a substate `Self` from the app state `S`, so handlers can extract parts of
+/// one composite state, e.g. `AppState<DbPool>` and `AppState<Config>`.
+///
+/// Register each substate with `WithState::substate`.
+pub trait FromRef<S> {
+    fn from_ref(state: &S) -> Self;
+}
+
+impl<T: Clone> FromRef<T> for T {
+    fn from_ref(state: &T) -> Self {
+        state.clone()
+    }
+}
+
+type SubStateFn = Arc<dyn Fn() -> Box<dyn Any + Send> + Send + Sync>;
+
+#[derive(Clone)]
 pub struct WithState<T: Clone + Send + Sync + 'static> {
     extension: AppState<T>,
+    substates: SubStates,
 }
 
 impl<T: Clone + Send + Sync + 'static> WithState<T> {
     pub fn new(extension: T) -> Self {
         WithState {
             extension: AppState { inner: extension },
+            substates: SubStates::default(),
         }
     }
 
+    /// Make `AppState<S>` extractable, derived from the state with `FromRef`.
+    pub fn substate<S>(&mut self)
+    where
+        S: FromRef<T> + Send + 'static,
+    {
+        let state = self.extension.inner.clone();
+        let derive: SubStateFn = Arc::new(move || Box::new(S::from_ref(&state)));
+
+        Arc::make_mut(&mut self.substates.inner).insert(TypeId::of::<S>(), derive);
+    }
+
     async fn append_extension<'a>(&'a self, mut ctx: Request, next: Next<'a>) -> Response {
         ctx.extensions_mut().insert(self.extension.clone());
+        if !self.substates.inner.is_empty() {
+            ctx.extensions_mut().insert(self.substates.clone());
+        }
         next.run(ctx).await
     }
 
     pub(crate) fn get_state(ctx: &RequestParts) -> Option<T> {
+        if let Some(state) = ctx.extensions().get::<AppState<T>>() {
+            return Some(state.inner.clone());
+        }
+
         ctx.extensions()
-            .get::<AppState<T>>()
-            .map(|o| o.inner.clone())
+            .get::<SubStates>()
+            .and_then(|substates| substates.inner.get(&TypeId::of::<T>()))
+            .and_then(|derive| derive().downcast::<T>().ok())
+            .map(|state| *state)
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static + std::fmt::Debug> std::fmt::Debug for WithState<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WithState")
+            .field("extension", &self.extension)
+            .field("substates", &self.substates.inner.len())
+            .finish()
     }
 }
 
@@ -39,3 +90,76 @@ impl<T: Send + Sync + 'static + Clone> Middleware for WithState<T> {
 pub(crate) struct AppState<T: Clone + Send + Sync + 'static> {
     pub(crate) inner: T,
 }
+
+/// Substates registered by `WithState::substate`, keyed by the substate type.
+#[derive(Clone, Default)]
+struct SubStates {
+    inner: Arc<HashMap<TypeId, SubStateFn>>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, TestClient};
+    use crate::{App, AppState};
+
+    use super::{FromRef, WithState};
+
+    #[derive(Clone)]
+    struct DbPool(&'static str);
+
+    #[derive(Clone)]
+    struct Config {
+        name: &'static str,
+    }
+
+    #[derive(Clone)]
+    struct Ctx {
+        db: DbPool,
+        config: Config,
+    }
+
+    impl FromRef<Ctx> for DbPool {
+        fn from_ref(ctx: &Ctx) -> Self {
+            ctx.db.clone()
+        }
+    }
+
+    impl FromRef<Ctx> for Config {
+        fn from_ref(ctx: &Ctx) -> Self {
+            ctx.config.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn substates() {
+        let mut state = WithState::new(Ctx {
+            db: DbPool("postgres"),
+            config: Config { name: "lieweb" },
+        });
+        state.substate::<DbPool>();
+        state.substate::<Config>();
+
+        let mut app = App::new();
+        app.middleware(state);
+        app.get(
+            "/",
+            |db: AppState<DbPool>, cfg: AppState<Config>| async move {
+                format!("{} {}", db.0, cfg.name)
+            },
+        );
+        app.get("/ctx", |ctx: AppState<Ctx>| async move { ctx.config.name });
+        app.get("/missing", |_: AppState<String>| async move { "" });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "postgres lieweb");
+
+        let resp = client.send(get("/ctx")).await;
+        assert_eq!(resp.body(), "lieweb");
+
+        let resp = client.send(get("/missing")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
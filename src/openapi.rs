@@ -0,0 +1,99 @@
+use hyper::http;
+use serde_json::{json, Map, Value};
+
+/// Build a minimal OpenAPI 3.0 document, with only the `paths` skeleton of routes.
+pub(crate) fn document(
+    title: &str,
+    version: &str,
+    routes: &[(String, Vec<http::Method>)],
+) -> Value {
+    let mut paths = Map::new();
+
+    for (path, methods) in routes {
+        let (path, params) = convert_path(path);
+
+        let parameters: Vec<Value> = params
+            .iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+
+        let item = paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::new()));
+
+        for method in methods {
+            let mut operation = json!({
+                "responses": { "default": { "description": "" } },
+            });
+            if !parameters.is_empty() {
+                operation["parameters"] = Value::Array(parameters.clone());
+            }
+            item[method.as_str().to_ascii_lowercase()] = operation;
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": title, "version": version },
+        "paths": paths,
+    })
+}
+
+/// Convert `:param` and `*param` segments into `{param}`.
+fn convert_path(path: &str) -> (String, Vec<String>) {
+    let mut params = Vec::new();
+
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix([':', '*']) {
+            Some(name) if !name.is_empty() => {
+                params.push(name.to_string());
+                format!("{{{}}}", name)
+            }
+            _ => segment.to_string(),
+        })
+        .collect();
+
+    (segments.join("/"), params)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::StatusCode;
+    use crate::test_helpers::{get, send};
+    use crate::App;
+
+    #[tokio::test]
+    async fn openapi_paths() {
+        let mut app = App::new();
+        app.get("/users", || async { "users" });
+        app.post("/users", || async { "new user" });
+        app.get("/users/:id", || async { "user" });
+        app.delete("/users/:id/posts/:post_id", || async { "deleted" });
+        app.openapi("demo", "1.0.0");
+
+        let resp = send(app, get("/openapi.json")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let doc: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let paths = &doc["paths"];
+
+        assert_eq!(doc["info"]["title"], "demo");
+        assert!(paths["/users"]["get"].is_object());
+        assert!(paths["/users"]["post"].is_object());
+        assert_eq!(paths["/users/{id}"]["get"]["parameters"][0]["name"], "id");
+        assert_eq!(
+            paths["/users/{id}/posts/{post_id}"]["delete"]["parameters"][1]["name"],
+            "post_id"
+        );
+        assert!(paths.get("/openapi.json").is_none());
+    }
+}
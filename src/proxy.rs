@@ -0,0 +1,627 @@
+//! Helpers for proxying requests to upstream servers.
+
+use std::collections::HashSet;
+use std::io;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Incoming};
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::net::TcpStream;
+
+use crate::endpoint::Handler;
+use crate::http::{header, uri, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use crate::middleware::{Middleware, Next};
+use crate::request::{LieRequest, RequestCtx};
+use crate::response::IntoResponse;
+use crate::{Error, Request, Response};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Hop-by-hop headers, which are meaningful only for a single connection.
+pub(crate) const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Remove hop-by-hop headers, including those listed in `Connection`.
+pub(crate) fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+
+    for name in listed.iter().chain(HOP_BY_HOP_HEADERS.iter()) {
+        headers.remove(name);
+    }
+}
+
+/// Convert an upstream response into a `Response`, streaming its body.
+///
+/// Hop-by-hop headers are removed. With `decode`, a body encoded by gzip, deflate or br
+/// is decoded, and `Content-Encoding` and `Content-Length` are removed accordingly.
+/// Other encodings can't be decoded, and respond `502 Bad Gateway`, as other upstream
+/// failures of `ReverseProxy` do.
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamResponse {
+    #[cfg(feature = "compression")]
+    decode: bool,
+}
+
+impl UpstreamResponse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode the body according to `Content-Encoding`, unknown encodings respond `502`.
+    #[cfg(feature = "compression")]
+    pub fn decode(mut self, decode: bool) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    pub fn into_response<B>(&self, resp: crate::http::Response<B>) -> Response
+    where
+        B: Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (mut parts, body) = resp.into_parts();
+
+        remove_hop_by_hop_headers(&mut parts.headers);
+
+        let body = body.map_err(|e| Error::from(io::Error::other(e))).boxed();
+
+        #[cfg(feature = "compression")]
+        let body = if self.decode {
+            match decode::decode_body(&mut parts.headers, body) {
+                Some(body) => body,
+                None => {
+                    tracing::error!(
+                        "upstream response with unsupported Content-Encoding {:?}",
+                        parts.headers.get(crate::http::header::CONTENT_ENCODING)
+                    );
+                    return (StatusCode::BAD_GATEWAY, "Bad Gateway").into_response();
+                }
+            }
+        } else {
+            body
+        };
+
+        Response::from_parts(parts, body)
+    }
+}
+
+/// Endpoint forwarding requests to an upstream `http://` server, streaming both bodies.
+///
+/// Mount it at a wildcard route such as `/proxy/*path`, the captured `path` is appended
+/// to the upstream base path, otherwise the whole request path is. Hop-by-hop headers
+/// are removed in both directions, and `X-Forwarded-For`, `X-Forwarded-Host` and
+/// `X-Forwarded-Proto` are set. Upstream HTTP/1 connections are pooled and shared by the
+/// clones, failures respond `502 Bad Gateway`.
+#[derive(Clone)]
+pub struct ReverseProxy {
+    inner: Arc<ReverseProxyInner>,
+}
+
+struct ReverseProxyInner {
+    authority: uri::Authority,
+    host: HeaderValue,
+    base_path: String,
+    response: UpstreamResponse,
+    client: Client<HttpConnector, Incoming>,
+}
+
+impl ReverseProxy {
+    /// Upstream base URL, e.g. `http://127.0.0.1:8080/api`.
+    pub fn new(upstream: &str) -> Result<Self, Error> {
+        let uri: Uri = upstream
+            .parse()
+            .map_err(|e| Error::Message(format!("invalid upstream {:?}: {}", upstream, e)))?;
+
+        if uri.scheme() != Some(&uri::Scheme::HTTP) {
+            return Err(Error::Message(format!(
+                "unsupported upstream {:?}, only http:// is supported",
+                upstream
+            )));
+        }
+
+        let authority = uri
+            .authority()
+            .cloned()
+            .ok_or_else(|| Error::Message(format!("upstream {:?} without host", upstream)))?;
+
+        let host = HeaderValue::from_str(authority.as_str()).map_err(crate::http::Error::from)?;
+
+        Ok(ReverseProxy {
+            inner: Arc::new(ReverseProxyInner {
+                authority,
+                host,
+                base_path: uri.path().trim_end_matches('/').to_string(),
+                response: UpstreamResponse::new(),
+                client: Client::builder(TokioExecutor::new()).build_http(),
+            }),
+        })
+    }
+
+    pub async fn forward(&self, req: Request) -> Response {
+        match self.try_forward(req).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                tracing::error!(
+                    "proxy to upstream {} failed: {}",
+                    self.inner.authority,
+                    e.report()
+                );
+                (StatusCode::BAD_GATEWAY, "Bad Gateway").into_response()
+            }
+        }
+    }
+
+    async fn try_forward(&self, req: Request) -> Result<Response, Error> {
+        let inner = &self.inner;
+
+        let path = req
+            .get_param::<String>("path")
+            .unwrap_or_else(|_| req.uri().path().to_string());
+        let mut path_and_query = format!("{}/{}", inner.base_path, path.trim_start_matches('/'));
+        if let Some(query) = req.uri().query() {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+
+        let remote_addr = req.remote_addr();
+        let proto = req
+            .uri()
+            .scheme()
+            .cloned()
+            .unwrap_or_else(|| RequestCtx::extract_scheme(&req));
+        let (mut parts, body) = req.into_parts();
+
+        parts.uri = Uri::builder()
+            .scheme(uri::Scheme::HTTP)
+            .authority(inner.authority.clone())
+            .path_and_query(path_and_query)
+            .build()?;
+        parts.version = crate::http::Version::HTTP_11;
+
+        let headers = &mut parts.headers;
+        remove_hop_by_hop_headers(headers);
+        if let Some(host) = headers.remove(header::HOST) {
+            headers.insert(X_FORWARDED_HOST, host);
+        }
+        headers.insert(header::HOST, inner.host.clone());
+        headers.insert(
+            X_FORWARDED_PROTO,
+            HeaderValue::from_str(proto.as_str()).map_err(crate::http::Error::from)?,
+        );
+        if let Some(addr) = remote_addr {
+            let forwarded_for = match headers.get(X_FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+                Some(prev) => format!("{}, {}", prev, addr.ip()),
+                None => addr.ip().to_string(),
+            };
+            headers.insert(
+                X_FORWARDED_FOR,
+                HeaderValue::from_str(&forwarded_for).map_err(crate::http::Error::from)?,
+            );
+        }
+
+        let resp = inner
+            .client
+            .request(crate::http::Request::from_parts(parts, body))
+            .await
+            .map_err(|e| Error::from(io::Error::other(e)))?;
+
+        Ok(inner.response.into_response(resp))
+    }
+}
+
+#[crate::async_trait]
+impl Handler<ReverseProxy> for ReverseProxy {
+    async fn call(self, req: Request) -> Response {
+        self.forward(req).await
+    }
+}
+
+#[cfg(feature = "compression")]
+mod decode {
+    use std::io;
+
+    use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+    use futures::{Stream, TryStreamExt};
+    use http_body_util::{combinators::BoxBody, BodyExt, StreamBody};
+    use hyper::body::Frame;
+    use tokio::io::AsyncRead;
+    use tokio_util::io::{ReaderStream, StreamReader};
+
+    use bytes::Bytes;
+
+    use crate::http::{header, HeaderMap};
+    use crate::Error;
+
+    /// Decode `body` by its `Content-Encoding`, `None` when the encoding is unknown.
+    pub(super) fn decode_body(
+        headers: &mut HeaderMap,
+        body: BoxBody<Bytes, Error>,
+    ) -> Option<BoxBody<Bytes, Error>> {
+        let encoding = match headers.get(header::CONTENT_ENCODING) {
+            Some(encoding) => encoding.to_str().ok()?.trim().to_ascii_lowercase(),
+            None => return Some(body),
+        };
+
+        if encoding == "identity" {
+            headers.remove(header::CONTENT_ENCODING);
+            return Some(body);
+        }
+
+        let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+
+        let body = match encoding.as_str() {
+            "gzip" | "x-gzip" => boxed(GzipDecoder::new(reader)),
+            "deflate" => boxed(ZlibDecoder::new(reader)),
+            "br" => boxed(BrotliDecoder::new(reader)),
+            _ => return None,
+        };
+
+        headers.remove(header::CONTENT_ENCODING);
+        headers.remove(header::CONTENT_LENGTH);
+
+        Some(body)
+    }
+
+    fn boxed<R>(decoder: R) -> BoxBody<Bytes, Error>
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let body = frames(ReaderStream::new(decoder));
+        BodyExt::boxed(StreamBody::new(body))
+    }
+
+    fn frames<S>(s: S) -> impl Stream<Item = Result<Frame<Bytes>, Error>>
+    where
+        S: Stream<Item = io::Result<Bytes>>,
+    {
+        s.map_ok(Frame::data).map_err(Error::from)
+    }
+}
+
+/// Answer `CONNECT` requests by tunneling to the requested authority, for forward
+/// proxies, e.g. `CONNECT example.com:443`.
+///
+/// Add it with `App::middleware`, it handles `CONNECT` requests on any path and passes
+/// other requests through. Only destinations in the allowlist are tunneled, others are
+/// rejected with `403 Forbidden`, and unreachable ones with `502 Bad Gateway`. After the
+/// `200` response, bytes are copied in both directions until either side closes.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectTunnel {
+    allowlist: HashSet<String>,
+}
+
+impl ConnectTunnel {
+    pub fn new() -> Self {
+        ConnectTunnel::default()
+    }
+
+    /// Allow tunneling to `authority`, as `host:port`.
+    pub fn allow(&mut self, authority: impl Into<String>) {
+        self.allowlist.insert(authority.into().to_ascii_lowercase());
+    }
+
+    async fn tunnel(&self, mut req: Request) -> Response {
+        let authority = match req.uri().authority() {
+            Some(authority) if authority.port_u16().is_some() => authority.to_string(),
+            _ => return StatusCode::BAD_REQUEST.into_response(),
+        };
+
+        if !self.allowlist.contains(&authority.to_ascii_lowercase()) {
+            tracing::debug!("CONNECT to {} not allowed", authority);
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
+        let upstream = match TcpStream::connect(&authority).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                tracing::error!("CONNECT to {} failed: {}", authority, e);
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+        };
+
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        tokio::spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    tracing::error!("CONNECT upgrade failed: {}", e);
+                    return;
+                }
+            };
+
+            let mut client = TokioIo::new(upgraded);
+            let mut upstream = upstream;
+            if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                tracing::debug!("CONNECT tunnel to {} closed: {}", authority, e);
+            }
+        });
+
+        StatusCode::OK.into_response()
+    }
+}
+
+#[crate::async_trait]
+impl Middleware for ConnectTunnel {
+    async fn handle<'a>(&'a self, req: Request, next: Next<'a>) -> Response {
+        if req.method() != Method::CONNECT {
+            return next.run(req).await;
+        }
+
+        self.tunnel(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{header, Response, StatusCode};
+    use crate::test_helpers::{get, send, TestClient};
+    use crate::App;
+
+    use super::{ReverseProxy, UpstreamResponse};
+
+    fn upstream(body: impl Into<Bytes>, encoding: Option<&str>) -> Response<Full<Bytes>> {
+        let body = body.into();
+        let mut builder = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, body.len())
+            .header(header::CONNECTION, "keep-alive, x-upstream-hop")
+            .header("x-upstream-hop", "1")
+            .header("x-upstream", "1");
+        if let Some(encoding) = encoding {
+            builder = builder.header(header::CONTENT_ENCODING, encoding);
+        }
+        builder.body(Full::new(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pass_through() {
+        let mut app = App::new();
+        app.get("/", || async {
+            UpstreamResponse::new().into_response(upstream("hello", None))
+        });
+
+        let resp = send(app, get("/")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["x-upstream"], "1");
+        assert!(resp.headers().get("x-upstream-hop").is_none());
+        assert_eq!(resp.body(), "hello");
+    }
+
+    #[tokio::test]
+    async fn reverse_proxy() {
+        use crate::request::LieRequest;
+        use crate::{Request, ServerConfig};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = listener.local_addr().unwrap();
+
+        let mut upstream = App::new();
+        upstream.post("/api/*rest", |mut req: Request| async move {
+            let body = crate::request::read_body(&mut req).await.unwrap();
+            format!(
+                "{} {} for={} host={} proto={} {}",
+                req.method(),
+                req.uri(),
+                req.headers()["x-forwarded-for"].to_str().unwrap(),
+                req.headers()["x-forwarded-host"].to_str().unwrap(),
+                req.headers()["x-forwarded-proto"].to_str().unwrap(),
+                String::from_utf8_lossy(&body)
+            )
+        });
+        upstream.get("/api/port", |req: Request| async move {
+            req.remote_addr().unwrap().port().to_string()
+        });
+        upstream.get("/api/*rest", || async {
+            (StatusCode::NOT_FOUND, "nothing here")
+        });
+        tokio::spawn(upstream.serve(listener, ServerConfig::default()));
+
+        let proxy = ReverseProxy::new(&format!("http://{}/api", upstream_addr)).unwrap();
+        let mut app = App::new();
+        app.get("/proxy/*path", proxy.clone());
+        app.post("/proxy/*path", proxy);
+        let client = TestClient::new(app);
+
+        let req = crate::http::Request::post("/proxy/echo?x=1")
+            .header(header::HOST, "example.com")
+            .body(Full::new(Bytes::from("hello, upstream")))
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.body(),
+            "POST /api/echo?x=1 for=127.0.0.1 host=example.com proto=http hello, upstream"
+        );
+
+        // upstream connections are reused
+        let resp = client.send(get("/proxy/port")).await;
+        let port = resp.body().clone();
+        let resp = client.send(get("/proxy/port")).await;
+        assert_eq!(resp.body(), &port);
+
+        let resp = client.send(get("/proxy/missing")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.body(), "nothing here");
+
+        let proxy = ReverseProxy::new("http://127.0.0.1:1").unwrap();
+        let mut app = App::new();
+        app.get("/proxy/*path", proxy);
+        let resp = send(app, get("/proxy/down")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn reverse_proxy_over_tls() {
+        use std::sync::Arc;
+
+        use tokio::net::{TcpListener, TcpStream};
+        use tokio_rustls::rustls::pki_types::ServerName;
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+        use crate::{Request, ServerConfig};
+
+        const CERT: &[u8] = include_bytes!("../testdata/ec.crt");
+        const KEY: &[u8] = include_bytes!("../testdata/ec-sec1.key");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = listener.local_addr().unwrap();
+        let mut upstream = App::new();
+        upstream.get("/*rest", |req: Request| async move {
+            req.headers()["x-forwarded-proto"]
+                .to_str()
+                .unwrap()
+                .to_string()
+        });
+        tokio::spawn(upstream.serve(listener, ServerConfig::default()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut app = App::new();
+        app.get(
+            "/*path",
+            ReverseProxy::new(&format!("http://{}", upstream_addr)).unwrap(),
+        );
+        let tls = crate::TlsConfig::from_pem(CERT, KEY).unwrap();
+        tokio::spawn(app.serve(listener, ServerConfig::new().tls(tls)));
+
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &CERT[..]) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+
+        let (mut sender, conn) =
+            hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+
+        let resp = sender.send_request(get("/proto")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = http_body_util::BodyExt::collect(resp.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(body, "https");
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn decode_gzip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello, upstream").unwrap();
+        let encoded = encoder.finish().unwrap();
+
+        let mut app = App::new();
+        app.get("/", move || {
+            let encoded = encoded.clone();
+            async move {
+                UpstreamResponse::new()
+                    .decode(true)
+                    .into_response(upstream(encoded, Some("gzip")))
+            }
+        });
+
+        let resp = send(app, get("/")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.body(), "hello, upstream");
+
+        let mut app = App::new();
+        app.get("/", || async {
+            UpstreamResponse::new()
+                .decode(true)
+                .into_response(upstream("hello", Some("zstd")))
+        });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn connect_tunnel() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        use super::ConnectTunnel;
+        use crate::ServerConfig;
+
+        // echo server
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = echo.accept().await.unwrap();
+            let (mut reader, mut writer) = socket.split();
+            tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut tunnel = ConnectTunnel::new();
+        tunnel.allow(echo_addr.to_string());
+        let mut app = App::new();
+        app.middleware(tunnel);
+        tokio::spawn(app.serve(listener, ServerConfig::default()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let connect = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", echo_addr);
+        stream.write_all(connect.as_bytes()).await.unwrap();
+
+        let mut buf = Vec::new();
+        while !buf.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            buf.push(byte[0]);
+        }
+        assert!(buf.starts_with(b"HTTP/1.1 200 OK"));
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut pong = [0; 4];
+        stream.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"ping");
+
+        // not in the allowlist
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"CONNECT 127.0.0.1:1 HTTP/1.1\r\nHost: 127.0.0.1:1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(buf[..n].starts_with(b"HTTP/1.1 403 Forbidden"));
+    }
+}
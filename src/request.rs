@@ -1,9 +1,16 @@
+use std::future::Future;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use cookie::Cookie;
 use headers::{Header, HeaderMapExt};
 use http_body_util::BodyExt;
+use hyper::body::Incoming;
 use hyper::http;
 use hyper::http::{HeaderName, HeaderValue};
 use pathrouter::Params;
@@ -12,6 +19,7 @@ use serde::de::DeserializeOwned;
 pub type Request = hyper::Request<hyper::body::Incoming>;
 
 use crate::error::{invalid_header, invalid_param, missing_cookie, missing_header, missing_param};
+use crate::extracts::{AllowedMethods, BodyBeenTaken, ReadBodyRejection};
 use crate::response::IntoResponse;
 use crate::Error;
 
@@ -24,6 +32,408 @@ pub trait FromRequest: Sized {
 
 pub type RequestParts = hyper::Request<Option<hyper::body::Incoming>>;
 
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for hyper::body::Incoming {}
+    impl Sealed for Option<hyper::body::Incoming> {}
+}
+
+/// Body of [`Request`] or [`RequestParts`].
+///
+/// The body can be read at most once, no matter whether it is read by `LieRequest`
+/// methods or by `FromRequest` extractors, reading it again gives `BodyBeenTaken`.
+pub trait RequestBody: sealed::Sealed + Send {
+    #[doc(hidden)]
+    fn incoming_mut(&mut self) -> Option<&mut Incoming>;
+}
+
+impl RequestBody for Incoming {
+    fn incoming_mut(&mut self) -> Option<&mut Incoming> {
+        Some(self)
+    }
+}
+
+impl RequestBody for Option<Incoming> {
+    fn incoming_mut(&mut self) -> Option<&mut Incoming> {
+        self.as_mut()
+    }
+}
+
+/// Abort reading the request body with `408 Request Timeout` when no data arrives within
+/// the interval.
+///
+/// Set it for all routes with `App::data`, or for a single route with `RouteHandle::data`.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyReadTimeout(pub Duration);
+
+/// Reject request bodies larger than the limit in bytes with `413 Payload Too Large`.
+///
+/// Set a default with `App::with_body_limit` or `App::data`, and override it for a single
+/// route with `RouteHandle::data`.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimit(pub usize);
+
+/// Accept a `Json` body without a JSON content type, when the content type is absent
+/// or `application/octet-stream` and the body starts with `{` or `[`.
+///
+/// Set it for all routes with `App::data`, or for a single route with `RouteHandle::data`.
+#[derive(Debug, Clone, Copy)]
+pub struct ContentSniffing;
+
+/// Marker stored in extensions once the body has been taken.
+#[derive(Debug, Clone, Copy)]
+struct BodyTaken;
+
+pub(crate) fn body_taken<B>(req: &http::Request<B>) -> bool {
+    req.extensions().get::<BodyTaken>().is_some()
+}
+
+/// Mark the body as taken, fails if it has been taken before.
+pub(crate) fn mark_body_taken<B>(req: &mut http::Request<B>) -> Result<(), BodyBeenTaken> {
+    match req.extensions_mut().insert(BodyTaken) {
+        Some(_) => Err(BodyBeenTaken),
+        None => Ok(()),
+    }
+}
+
+/// Take the body for reading, the body can be taken only once.
+pub(crate) fn take_body<B: RequestBody>(
+    req: &mut http::Request<B>,
+) -> Result<&mut Incoming, BodyBeenTaken> {
+    mark_body_taken(req)?;
+    req.body_mut().incoming_mut().ok_or(BodyBeenTaken)
+}
+
+/// Read the whole body, the body can be read only once.
+pub(crate) async fn read_body<B: RequestBody>(
+    req: &mut http::Request<B>,
+) -> Result<Bytes, ReadBodyRejection> {
+    let timeout = req.extensions().get::<BodyReadTimeout>().map(|t| t.0);
+    let limit = req.extensions().get::<BodyLimit>().map(|l| l.0);
+
+    #[cfg(feature = "compression")]
+    let coding = req.extensions().get::<ContentCoding>().copied();
+    #[cfg(feature = "compression")]
+    let decoded = coding.is_some();
+    #[cfg(not(feature = "compression"))]
+    let decoded = false;
+
+    // the limit applies to the decoded size, which the encoded length says nothing about
+    if let Some(limit) = limit.filter(|_| !decoded) {
+        let content_length = req
+            .headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+
+        if content_length.is_some_and(|len| len > limit as u64) {
+            return Err(ReadBodyRejection::TooLarge);
+        }
+    }
+
+    // the body is replaced by its decoded form, so the headers no longer describe it
+    if decoded && !body_taken(req) {
+        req.headers_mut().remove(hyper::header::CONTENT_ENCODING);
+        req.headers_mut().remove(hyper::header::CONTENT_LENGTH);
+    }
+
+    let codec = req.extensions().get::<RequestCodec>().cloned();
+    let limit = match &codec {
+        Some(codec) => Some(limit.map_or(codec.max_size, |limit| limit.min(codec.max_size))),
+        None => limit,
+    };
+
+    let counter = req
+        .extensions()
+        .get::<ReceivedBytes>()
+        .map(|counter| counter.0.clone());
+
+    let body = CountingBody {
+        inner: take_body(req)?,
+        counter,
+    };
+
+    #[cfg(feature = "compression")]
+    let bytes = match coding {
+        Some(coding) => decode::read_decoded(body, coding, timeout, limit).await?,
+        None => collect_body(body, timeout, limit).await?,
+    };
+    #[cfg(not(feature = "compression"))]
+    let bytes = collect_body(body, timeout, limit).await?;
+
+    match codec {
+        Some(codec) => codec
+            .codec
+            .decode_request(bytes)
+            .map_err(ReadBodyRejection::DecodeFailed),
+        None => Ok(bytes),
+    }
+}
+
+/// Counter of request body bytes, set by `middleware::Metrics` and increased as the
+/// body is read.
+#[derive(Debug, Clone)]
+pub(crate) struct ReceivedBytes(pub(crate) Arc<AtomicU64>);
+
+/// The incoming body, counting data into `ReceivedBytes` as frames arrive.
+struct CountingBody<'a> {
+    inner: &'a mut Incoming,
+    counter: Option<Arc<AtomicU64>>,
+}
+
+impl hyper::body::Body for CountingBody<'_> {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<hyper::body::Frame<Bytes>, hyper::Error>>> {
+        let ret = Pin::new(&mut *self.inner).poll_frame(cx);
+
+        if let (Poll::Ready(Some(Ok(frame))), Some(counter)) = (&ret, &self.counter) {
+            if let Some(data) = frame.data_ref() {
+                counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+
+        ret
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The request body as a stream of frames, for handlers reading it without buffering.
+///
+/// `BodyLimit` applies as the frames arrive: the stream fails with
+/// `ReadBodyRejection::TooLarge` once more data than the limit has been received, and with
+/// `ReadBodyRejection::Timeout` when no frame arrives within `BodyReadTimeout`. The body
+/// is passed through as received, `middleware::Decompression` and `middleware::Codec` only
+/// apply to the buffered extractors.
+pub struct BodyStream {
+    inner: Incoming,
+    counter: Option<Arc<AtomicU64>>,
+    limit: Option<usize>,
+    received: usize,
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl BodyStream {
+    pub(crate) fn from_request(req: &mut RequestParts) -> Result<Self, ReadBodyRejection> {
+        let timeout = req.extensions().get::<BodyReadTimeout>().map(|t| t.0);
+        let limit = req.extensions().get::<BodyLimit>().map(|l| l.0);
+
+        if let Some(limit) = limit {
+            let content_length = req
+                .headers()
+                .get(hyper::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+
+            if content_length.is_some_and(|len| len > limit as u64) {
+                return Err(ReadBodyRejection::TooLarge);
+            }
+        }
+
+        let counter = req
+            .extensions()
+            .get::<ReceivedBytes>()
+            .map(|counter| counter.0.clone());
+
+        mark_body_taken(req)?;
+        let inner = req.body_mut().take().ok_or(BodyBeenTaken)?;
+
+        Ok(BodyStream {
+            inner,
+            counter,
+            limit,
+            received: 0,
+            timeout,
+            sleep: None,
+        })
+    }
+}
+
+impl std::fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodyStream")
+            .field("limit", &self.limit)
+            .field("received", &self.received)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl hyper::body::Body for BodyStream {
+    type Data = Bytes;
+    type Error = ReadBodyRejection;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<hyper::body::Frame<Bytes>, ReadBodyRejection>>> {
+        let frame = match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {
+                if let Some(timeout) = self.timeout {
+                    let sleep = self
+                        .sleep
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                    if sleep.as_mut().poll(cx).is_ready() {
+                        return Poll::Ready(Some(Err(ReadBodyRejection::Timeout)));
+                    }
+                }
+                return Poll::Pending;
+            }
+        };
+
+        // restart the timeout for the next frame
+        self.sleep = None;
+
+        if let Some(data) = frame.data_ref() {
+            if let Some(counter) = &self.counter {
+                counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+
+            self.received += data.len();
+            if self.limit.is_some_and(|limit| self.received > limit) {
+                return Poll::Ready(Some(Err(ReadBodyRejection::TooLarge)));
+            }
+        }
+
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Codec of the request body, set by `middleware::Codec` and applied when the body is
+/// read.
+#[derive(Clone)]
+pub(crate) struct RequestCodec {
+    pub(crate) codec: std::sync::Arc<dyn crate::middleware::BodyCodec>,
+    pub(crate) max_size: usize,
+}
+
+/// Collect frames of the body, applying the per-frame timeout and the limit.
+async fn collect_body<B>(
+    mut body: B,
+    timeout: Option<Duration>,
+    limit: Option<usize>,
+) -> Result<Bytes, ReadBodyRejection>
+where
+    B: hyper::body::Body<Data = Bytes> + Unpin,
+    B::Error: Into<ReadBodyRejection>,
+{
+    let mut buf = BytesMut::new();
+
+    loop {
+        let frame = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, body.frame())
+                .await
+                .map_err(|_| ReadBodyRejection::Timeout)?,
+            None => body.frame().await,
+        };
+
+        let frame = match frame {
+            Some(frame) => frame.map_err(Into::into)?,
+            None => break,
+        };
+
+        if let Some(data) = frame.data_ref() {
+            if limit.is_some_and(|limit| buf.len() + data.len() > limit) {
+                return Err(ReadBodyRejection::TooLarge);
+            }
+            buf.extend_from_slice(data);
+        }
+    }
+
+    Ok(buf.freeze())
+}
+
+/// Content coding of the request body, set by `middleware::Decompression` and decoded
+/// when the body is read.
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContentCoding {
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+#[cfg(feature = "compression")]
+impl ContentCoding {
+    pub(crate) fn parse(coding: &str) -> Option<Self> {
+        match coding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(ContentCoding::Gzip),
+            "deflate" => Some(ContentCoding::Deflate),
+            "br" => Some(ContentCoding::Brotli),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+mod decode {
+    use std::io;
+    use std::time::Duration;
+
+    use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+    use bytes::Bytes;
+    use futures::TryStreamExt;
+    use http_body_util::{BodyExt, StreamBody};
+    use hyper::body::Frame;
+    use tokio::io::AsyncRead;
+    use tokio_util::io::{ReaderStream, StreamReader};
+
+    use super::{collect_body, ContentCoding, CountingBody};
+    use crate::extracts::ReadBodyRejection;
+
+    pub(super) async fn read_decoded(
+        body: CountingBody<'_>,
+        coding: ContentCoding,
+        timeout: Option<Duration>,
+        limit: Option<usize>,
+    ) -> Result<Bytes, ReadBodyRejection> {
+        let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+
+        match coding {
+            ContentCoding::Gzip => collect(GzipDecoder::new(reader), timeout, limit).await,
+            ContentCoding::Deflate => collect(ZlibDecoder::new(reader), timeout, limit).await,
+            ContentCoding::Brotli => collect(BrotliDecoder::new(reader), timeout, limit).await,
+        }
+    }
+
+    async fn collect<R: AsyncRead>(
+        decoder: R,
+        timeout: Option<Duration>,
+        limit: Option<usize>,
+    ) -> Result<Bytes, ReadBodyRejection> {
+        let frames = ReaderStream::new(decoder)
+            .map_ok(Frame::data)
+            .map_err(|e| ReadBodyRejection::DecodeFailed(e.into()));
+        let body = std::pin::pin!(StreamBody::new(frames));
+
+        collect_body(body, timeout, limit).await
+    }
+}
+
 #[crate::async_trait]
 pub trait LieRequest {
     fn path(&self) -> &str;
@@ -33,18 +443,22 @@ pub trait LieRequest {
         T: std::str::FromStr,
         <T as std::str::FromStr>::Err: std::error::Error;
     fn get_cookie(&self, name: &str) -> Result<String, Error>;
+    fn cookies(&self) -> Result<headers::Cookie, Error>;
     fn get_header<K>(&self, header: K) -> Result<&HeaderValue, Error>
     where
         HeaderName: From<K>;
     fn get_typed_header<T: Header + Send + 'static>(&self) -> Result<T, Error>;
 
+    #[deprecated(note = "use the `BytesBody` extractor")]
     async fn read_body(&mut self) -> Result<Bytes, Error>;
+    #[deprecated(note = "use the `Form` extractor")]
     async fn read_form<T: DeserializeOwned>(&mut self) -> Result<T, Error>;
+    #[deprecated(note = "use the `Json` extractor")]
     async fn read_json<T: DeserializeOwned>(&mut self) -> Result<T, Error>;
 }
 
 #[crate::async_trait]
-impl LieRequest for Request {
+impl<B: RequestBody> LieRequest for http::Request<B> {
     fn path(&self) -> &str {
         self.uri().path()
     }
@@ -104,20 +518,24 @@ impl LieRequest for Request {
             .ok_or(missing_cookie(name))
     }
 
+    fn cookies(&self) -> Result<headers::Cookie, Error> {
+        self.get_typed_header::<headers::Cookie>()
+    }
+
     async fn read_body(&mut self) -> Result<Bytes, Error> {
-        let body = BodyExt::collect(self.body_mut()).await?;
-        Ok(body.to_bytes())
+        let body = read_body(self).await?;
+        Ok(body)
     }
 
     async fn read_form<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
-        let body = self.read_body().await?;
+        let body = read_body(self).await?;
         let form = serde_urlencoded::from_bytes(&body)?;
 
         Ok(form)
     }
 
     async fn read_json<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
-        let body = self.read_body().await?;
+        let body = read_body(self).await?;
         let json = serde_json::from_slice(&body)?;
 
         Ok(json)
@@ -127,16 +545,31 @@ impl LieRequest for Request {
 #[derive(Debug, Clone)]
 pub(crate) struct RequestCtx {
     params: Params,
+    /// Names of `params` in route order, `Params` keeps them sorted by name.
+    param_names: Vec<String>,
     remote_addr: Option<SocketAddr>,
+    scheme: http::uri::Scheme,
     route_path: Option<String>,
+    /// Pattern of the matched route, prefixed by the patterns of nested routers.
+    route_pattern: Option<Arc<str>>,
+    /// Methods registered for the matched path.
+    allowed_methods: Option<AllowedMethods>,
 }
 
 impl RequestCtx {
-    pub(crate) fn init<B>(req: &mut http::Request<B>, remote_addr: Option<SocketAddr>) {
+    pub(crate) fn init<B>(
+        req: &mut http::Request<B>,
+        remote_addr: Option<SocketAddr>,
+        scheme: http::uri::Scheme,
+    ) {
         let ctx = RequestCtx {
             params: Params::new(),
+            param_names: Vec::new(),
             remote_addr,
+            scheme,
             route_path: None,
+            route_pattern: None,
+            allowed_methods: None,
         };
 
         req.extensions_mut().insert(ctx);
@@ -146,12 +579,39 @@ impl RequestCtx {
         req.extensions().get::<Self>().map(|ctx| &ctx.params)
     }
 
+    /// Params in route order, for deserializing them by position, e.g. into a tuple.
+    pub(crate) fn ordered_params<B>(req: &http::Request<B>) -> Vec<(&str, &str)> {
+        let Some(ctx) = req.extensions().get::<Self>() else {
+            return Vec::new();
+        };
+
+        let mut ordered: Vec<(&str, &str)> = ctx
+            .param_names
+            .iter()
+            .filter_map(|name| ctx.params.find(name).map(|value| (name.as_str(), value)))
+            .collect();
+        ordered.extend(
+            ctx.params
+                .iter()
+                .filter(|(name, _)| !ctx.param_names.iter().any(|n| n == name)),
+        );
+
+        ordered
+    }
+
     pub(crate) fn extract_remote_addr<B>(req: &http::Request<B>) -> Option<SocketAddr> {
         req.extensions()
             .get::<RequestCtx>()
             .and_then(|ctx| ctx.remote_addr)
     }
 
+    /// Scheme of the connection, `https` when it was accepted over TLS.
+    pub(crate) fn extract_scheme<B>(req: &http::Request<B>) -> http::uri::Scheme {
+        req.extensions()
+            .get::<RequestCtx>()
+            .map_or(http::uri::Scheme::HTTP, |ctx| ctx.scheme.clone())
+    }
+
     pub(crate) fn route_path<B>(req: &http::Request<B>) -> &str {
         let ctx = req
             .extensions()
@@ -164,22 +624,262 @@ impl RequestCtx {
         }
     }
 
-    pub(crate) fn set_route_path<B>(req: &mut http::Request<B>, path: &str) {
+    pub(crate) fn set_route_path<B>(req: &mut http::Request<B>, path: String) {
+        let ctx = req
+            .extensions_mut()
+            .get_mut::<Self>()
+            .expect("can not extract RequestCtx from request");
+        ctx.route_path = Some(path);
+    }
+
+    /// Path pattern the matched route was registered with, e.g. `/users/:id`, prefixed by
+    /// the patterns of the nested routers it is in.
+    pub(crate) fn route_pattern<B>(req: &http::Request<B>) -> Option<&Arc<str>> {
+        req.extensions()
+            .get::<Self>()
+            .and_then(|ctx| ctx.route_pattern.as_ref())
+    }
+
+    pub(crate) fn set_route_pattern<B>(req: &mut http::Request<B>, pattern: Arc<str>) {
+        let ctx = req
+            .extensions_mut()
+            .get_mut::<Self>()
+            .expect("can not extract RequestCtx from request");
+        ctx.route_pattern = Some(pattern);
+    }
+
+    pub(crate) fn allowed_methods<B>(req: &http::Request<B>) -> Option<&AllowedMethods> {
+        req.extensions()
+            .get::<Self>()
+            .and_then(|ctx| ctx.allowed_methods.as_ref())
+    }
+
+    pub(crate) fn set_allowed_methods<B>(req: &mut http::Request<B>, methods: AllowedMethods) {
         let ctx = req
             .extensions_mut()
             .get_mut::<Self>()
             .expect("can not extract RequestCtx from request");
-        ctx.route_path = Some(path.to_string());
+        ctx.allowed_methods = Some(methods);
     }
 
-    pub(crate) fn merge_params<B>(req: &mut http::Request<B>, other: &Params) {
+    /// Merge the params matched by `pattern` into the context, taking them over as is
+    /// when the context has none yet, e.g. outside of nested routers.
+    pub(crate) fn merge_params<B>(
+        req: &mut http::Request<B>,
+        other: Params,
+        pattern: Option<&str>,
+    ) {
+        if (&other).into_iter().next().is_none() {
+            return;
+        }
+
         let ctx = req
             .extensions_mut()
             .get_mut::<Self>()
             .expect("can not extract RequestCtx from request");
 
-        for (k, v) in other {
+        ctx.param_names.retain(|name| other.find(name).is_none());
+        ctx.param_names.extend(
+            pattern
+                .unwrap_or_default()
+                .split('/')
+                .filter_map(|seg| seg.strip_prefix(':').or_else(|| seg.strip_prefix('*')))
+                .filter(|name| other.find(name).is_some())
+                .map(str::to_string),
+        );
+
+        if (&ctx.params).into_iter().next().is_none() {
+            ctx.params = other;
+            return;
+        }
+
+        for (k, v) in &other {
             ctx.params.insert(k.to_string(), v.to_string());
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use http_body_util::BodyExt;
+
+    use crate::extracts::BodyBeenTaken;
+    use crate::http::{self, StatusCode};
+    use crate::request::{LieRequest, Request, RequestParts};
+    use crate::response::IntoResponse;
+    use crate::test_helpers::{post, send, TestClient};
+    use crate::{App, BytesBody, Error};
+
+    #[allow(deprecated)]
+    #[tokio::test]
+    async fn read_body_once() {
+        let mut app = App::new();
+
+        app.post("/", |mut req: Request| async move {
+            let content_type = req
+                .get_header(http::header::CONTENT_TYPE)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+
+            let body = req.read_body().await.unwrap();
+            assert!(matches!(req.read_body().await, Err(Error::BodyBeenTaken)));
+
+            format!("{content_type}: {}", String::from_utf8_lossy(&body))
+        });
+
+        let resp = send(app, post("/", "text/plain", "hello")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "text/plain: hello");
+    }
+
+    #[allow(deprecated)]
+    #[tokio::test]
+    async fn mix_lie_request_and_extractor() {
+        let mut app = App::new();
+
+        app.post("/", |mut req: RequestParts| async move {
+            let body = req.read_body().await.unwrap();
+            assert_eq!(body, "hello");
+
+            match crate::request::read_body(&mut req).await {
+                Err(crate::extracts::ReadBodyRejection::BodyBeenTaken(BodyBeenTaken)) => {}
+                _ => panic!("body should be taken"),
+            }
+
+            StatusCode::NO_CONTENT
+        });
+
+        let resp = send(app, post("/", "text/plain", "hello")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let mut app = App::new();
+        app.post(
+            "/twice",
+            |body: BytesBody, other: Result<BytesBody, _>| async move {
+                assert_eq!(body.value(), "hello");
+                other.map(|_| StatusCode::OK).into_response()
+            },
+        );
+
+        let resp = send(app, post("/twice", "text/plain", "hello")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(resp.body(), "Body has been taken");
+    }
+
+    fn stalled_body() -> impl hyper::body::Body<Data = bytes::Bytes, Error = std::io::Error> {
+        use futures::StreamExt;
+
+        let s = futures::stream::iter([Ok(hyper::body::Frame::data(bytes::Bytes::from(
+            "{\"partial\":",
+        )))])
+        .chain(futures::stream::pending());
+
+        http_body_util::StreamBody::new(s)
+    }
+
+    #[tokio::test]
+    async fn body_read_timeout() {
+        use std::time::Duration;
+
+        use crate::{BodyReadTimeout, Json};
+
+        let mut app = App::new();
+        app.data(BodyReadTimeout(Duration::from_millis(50)));
+        app.post("/", |_: Json<serde_json::Value>| async { StatusCode::OK });
+
+        let req = http::Request::post("/")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(stalled_body())
+            .unwrap();
+        let resp = crate::test_helpers::send_raw(app, req).await;
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+
+        // route setting overrides the global one
+        let mut app = App::new();
+        app.data(BodyReadTimeout(Duration::from_secs(60)));
+        app.post("/", |_: BytesBody| async { "" })
+            .data(BodyReadTimeout(Duration::from_millis(50)));
+
+        let req = http::Request::post("/").body(stalled_body()).unwrap();
+        let resp = crate::test_helpers::send_raw(app, req).await;
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+
+        // applies to the streamed body as well
+        let mut app = App::new();
+        app.data(BodyReadTimeout(Duration::from_millis(50)));
+        app.post("/", |body: crate::BodyStream| async move {
+            body.collect().await.map(|_| "")
+        });
+
+        let req = http::Request::post("/").body(stalled_body()).unwrap();
+        let resp = crate::test_helpers::send_raw(app, req).await;
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn body_limit() {
+        use crate::BodyLimit;
+
+        let mut app = App::with_body_limit(8);
+        app.post("/", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        app.post("/large", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        })
+        .data(BodyLimit(64));
+        let client = TestClient::new(app);
+
+        let resp = client
+            .send(post("/", "text/plain", "0123456789abcdef"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = client.send(post("/", "text/plain", "01234567")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // chunked body, without content-length
+        let body = futures::stream::iter(["01234", "56789"].map(|chunk| {
+            Ok::<_, std::io::Error>(hyper::body::Frame::data(bytes::Bytes::from(chunk)))
+        }));
+        let req = http::Request::post("/")
+            .body(http_body_util::StreamBody::new(body))
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // route setting overrides the global one
+        let resp = client
+            .send(post("/large", "text/plain", "0123456789abcdef"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "0123456789abcdef");
+    }
+
+    #[tokio::test]
+    async fn read_cookies() {
+        let mut app = App::new();
+        app.get("/", |req: Request| async move {
+            let cookies = req.cookies().unwrap();
+
+            cookies
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+
+        let req = http::Request::get("/")
+            .header(http::header::COOKIE, "a=1; b=2")
+            .header(http::header::COOKIE, "c=3")
+            .body(http_body_util::Empty::<bytes::Bytes>::new())
+            .unwrap();
+        let resp = send(app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "a=1,b=2,c=3");
+    }
+}
@@ -1,8 +1,9 @@
-use std::{borrow::Cow, convert::Infallible};
+use std::{borrow::Cow, convert::Infallible, sync::Arc};
 
 use bytes::Bytes;
 
 use futures_util::StreamExt;
+use headers::HeaderMapExt;
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Empty, Full};
 use hyper::body::Frame;
@@ -12,7 +13,11 @@ use hyper::http::{
     StatusCode,
 };
 
-use crate::ty::{BytesBody, Form, Html, Json, StreamBody};
+use crate::error::WebError;
+use crate::extracts::Conditionals;
+use crate::ty::{
+    BytesBody, Form, Html, Json, Paginated, PrettyJson, RateLimitHeaders, Redirect, StreamBody,
+};
 use crate::Error;
 
 pub type Response = http::Response<BoxBody<Bytes, Error>>;
@@ -39,11 +44,22 @@ pub struct LieResponse {
 }
 
 impl LieResponse {
+    /// Response with `status` and a fixed body, `Content-Length` is set unless the status
+    /// forbids a body.
     pub fn new(status: StatusCode, body: impl Into<Bytes>) -> Self {
+        let body = body.into();
+
+        let mut builder = http::Response::builder().status(status);
+        if !(status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED)
+        {
+            builder = builder.header(http::header::CONTENT_LENGTH, body.len());
+        }
+
         LieResponse {
-            inner: http::Response::builder()
-                .status(status)
-                .body(Full::new(body.into()).map_err(Into::into).boxed())
+            inner: builder
+                .body(Full::new(body).map_err(Into::into).boxed())
                 .unwrap(),
         }
     }
@@ -89,8 +105,133 @@ impl LieResponse {
         StreamBody::new(s, content_type).into()
     }
 
+    /// Redirect to `location` with the redirection `status`, failing when `location` is
+    /// not a valid header value.
+    pub fn redirect(status: StatusCode, location: impl AsRef<str>) -> Result<Self, Error> {
+        let location = HeaderValue::from_str(location.as_ref()).map_err(http::Error::from)?;
+
+        let mut resp = LieResponse::with_status(status);
+        resp.headers_mut().insert(http::header::LOCATION, location);
+        Ok(resp)
+    }
+
+    /// Redirect with `307 Temporary Redirect`, keeping the method and body.
+    pub fn redirect_temporary(location: impl AsRef<str>) -> Result<Self, Error> {
+        Self::redirect(StatusCode::TEMPORARY_REDIRECT, location)
+    }
+
+    /// Redirect with `308 Permanent Redirect`, keeping the method and body.
+    pub fn redirect_permanent(location: impl AsRef<str>) -> Result<Self, Error> {
+        Self::redirect(StatusCode::PERMANENT_REDIRECT, location)
+    }
+
+    /// Redirect with `303 See Other`, followed with `GET`, e.g. after a form post.
+    pub fn see_other(location: impl AsRef<str>) -> Result<Self, Error> {
+        Self::redirect(StatusCode::SEE_OTHER, location)
+    }
+
+    /// Redirect with `302 Found`, which clients may follow with `GET`.
+    pub fn found(location: impl AsRef<str>) -> Result<Self, Error> {
+        Self::redirect(StatusCode::FOUND, location)
+    }
+
+    /// Serve an in-memory body honoring a single `Range`, responding `206 Partial Content`
+    /// or `416 Range Not Satisfiable`. Multiple ranges and `If-Range` fall back to the
+    /// full body.
+    pub fn with_bytes_range(
+        body: impl Into<Bytes>,
+        content_type: mime::Mime,
+        conditionals: &Conditionals,
+    ) -> Self {
+        let body = body.into();
+        let len = body.len() as u64;
+
+        let range = if conditionals.if_range().is_some() {
+            ByteRange::Full
+        } else {
+            byte_range(conditionals.range(), len)
+        };
+
+        let resp = match range {
+            ByteRange::Full => BytesBody::new(body, content_type).into(),
+            ByteRange::Partial(range) => {
+                let content_range = headers::ContentRange::bytes(range.clone(), len)
+                    .expect("range checked by byte_range");
+                let body = body.slice(range.start as usize..range.end as usize);
+
+                LieResponse::from(BytesBody::new(body, content_type))
+                    .set_status(StatusCode::PARTIAL_CONTENT)
+                    .insert_typed_header(content_range)
+            }
+            ByteRange::Unsatisfiable => LieResponse::with_status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .insert_typed_header(headers::ContentRange::unsatisfied_bytes(len)),
+        };
+
+        resp.insert_typed_header(headers::AcceptRanges::bytes())
+    }
+
+    #[cfg(feature = "fs")]
     pub async fn send_file(path: impl AsRef<std::path::Path>) -> Result<Self, crate::Error> {
-        match tokio::fs::File::open(path.as_ref()).await {
+        Self::send_file_conditional(path, &Conditionals::default()).await
+    }
+
+    /// Like `send_file_conditional`, with the conditional headers of `req`.
+    #[cfg(feature = "fs")]
+    pub async fn send_file_cached<B>(
+        req: &http::Request<B>,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, crate::Error> {
+        Self::send_file_conditional(path, &Conditionals::from_headers(req.headers())).await
+    }
+
+    /// Like `send_file`, with `Last-Modified` and a weak ETag derived from file size and
+    /// modified time. Respond `304 Not Modified` without opening the file when
+    /// `If-None-Match` matches the ETag, or without `If-None-Match`, when the file is not
+    /// modified since `If-Modified-Since`.
+    #[cfg(feature = "fs")]
+    pub async fn send_file_conditional(
+        path: impl AsRef<std::path::Path>,
+        conditionals: &Conditionals,
+    ) -> Result<Self, crate::Error> {
+        let path = path.as_ref();
+
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(LieResponse::with_status(StatusCode::NOT_FOUND));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let etag = file_etag(&metadata);
+        let modified = metadata.modified().ok();
+
+        let not_modified = match (conditionals.if_none_match(), &etag) {
+            (Some(if_none_match), Some(etag)) => !if_none_match.precondition_passes(etag),
+            (Some(_), None) => false,
+            (None, _) => match (conditionals.if_modified_since(), modified) {
+                (Some(since), Some(modified)) => !since.is_modified(modified),
+                _ => false,
+            },
+        };
+
+        let validators = |mut resp: LieResponse| {
+            if let Some(etag) = &etag {
+                resp = resp.insert_typed_header(etag.clone());
+            }
+            if let Some(modified) = modified {
+                resp = resp.insert_typed_header(headers::LastModified::from(modified));
+            }
+            resp
+        };
+
+        if not_modified {
+            return Ok(validators(LieResponse::with_status(
+                StatusCode::NOT_MODIFIED,
+            )));
+        }
+
+        match tokio::fs::File::open(path).await {
             Ok(file) => {
                 let s =
                     tokio_util::codec::FramedRead::new(file, tokio_util::codec::BytesCodec::new());
@@ -100,7 +241,7 @@ impl LieResponse {
                     mime_guess::from_path(path).first_or_octet_stream(),
                 );
 
-                Ok(resp)
+                Ok(validators(resp))
             }
             Err(err) => {
                 if err.kind() == std::io::ErrorKind::NotFound {
@@ -180,6 +321,11 @@ impl LieResponse {
         self
     }
 
+    pub fn insert_typed_header<H: headers::Header>(mut self, header: H) -> Self {
+        self.inner.headers_mut().typed_insert(header);
+        self
+    }
+
     pub fn append_cookie(self, cookie: crate::Cookie) -> Self {
         self.append_header(http::header::SET_COOKIE, cookie.to_string())
     }
@@ -202,6 +348,64 @@ impl LieResponse {
     // }
 }
 
+/// A fully buffered response, rebuilt into a fresh `Response` as often as needed, e.g.
+/// by response caches. Cloning is cheap, the body is shared.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CachedResponse {
+    pub fn new(status: StatusCode, headers: HeaderMap, body: impl Into<Bytes>) -> Self {
+        CachedResponse {
+            status,
+            headers,
+            body: body.into(),
+        }
+    }
+
+    /// Buffer the whole body of `resp`.
+    pub async fn from_response(resp: Response) -> Result<Self, Error> {
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await?.to_bytes();
+
+        Ok(CachedResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Build a fresh response.
+    pub fn to_response(&self) -> Response {
+        let mut resp =
+            http::Response::new(Full::new(self.body.clone()).map_err(Into::into).boxed());
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers.clone();
+        resp
+    }
+}
+
+impl IntoResponse for CachedResponse {
+    fn into_response(self) -> Response {
+        self.to_response()
+    }
+}
+
 impl From<Response> for LieResponse {
     fn from(response: Response) -> Self {
         LieResponse { inner: response }
@@ -241,117 +445,63 @@ impl IntoResponse for StatusCode {
 
 impl From<&'static [u8]> for LieResponse {
     fn from(val: &'static [u8]) -> Self {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::APPLICATION_OCTET_STREAM.to_string(),
-            )
-            .body(
-                Full::new(Bytes::from_static(val))
-                    .map_err(Into::into)
-                    .boxed(),
-            )
-            .unwrap()
-            .into()
+        full_response(
+            mime::APPLICATION_OCTET_STREAM.as_ref(),
+            Bytes::from_static(val),
+        )
+        .into()
     }
 }
 
 impl IntoResponse for &'static [u8] {
     fn into_response(self) -> Response {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::APPLICATION_OCTET_STREAM.to_string(),
-            )
-            .body(
-                Full::new(Bytes::from_static(self))
-                    .map_err(Into::into)
-                    .boxed(),
-            )
-            .unwrap()
+        full_response(
+            mime::APPLICATION_OCTET_STREAM.as_ref(),
+            Bytes::from_static(self),
+        )
     }
 }
 
 impl From<Vec<u8>> for LieResponse {
     fn from(val: Vec<u8>) -> Self {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::APPLICATION_OCTET_STREAM.to_string(),
-            )
-            .body(Full::new(Bytes::from(val)).map_err(Into::into).boxed())
-            .unwrap()
-            .into()
+        full_response(mime::APPLICATION_OCTET_STREAM.as_ref(), Bytes::from(val)).into()
     }
 }
 
 impl IntoResponse for Vec<u8> {
     fn into_response(self) -> Response {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::APPLICATION_OCTET_STREAM.to_string(),
-            )
-            .body(Full::new(Bytes::from(self)).map_err(Into::into).boxed())
-            .unwrap()
+        full_response(mime::APPLICATION_OCTET_STREAM.as_ref(), Bytes::from(self))
     }
 }
 
 impl From<&'static str> for LieResponse {
     fn from(val: &'static str) -> Self {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::TEXT_PLAIN_UTF_8.to_string(),
-            )
-            .body(
-                Full::new(Bytes::from_static(val.as_bytes()))
-                    .map_err(Into::into)
-                    .boxed(),
-            )
-            .unwrap()
-            .into()
+        full_response(
+            mime::TEXT_PLAIN_UTF_8.as_ref(),
+            Bytes::from_static(val.as_bytes()),
+        )
+        .into()
     }
 }
 
 impl IntoResponse for &'static str {
     fn into_response(self) -> Response {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::TEXT_PLAIN_UTF_8.to_string(),
-            )
-            .body(
-                Full::new(Bytes::from_static(self.as_bytes()))
-                    .map_err(Into::into)
-                    .boxed(),
-            )
-            .unwrap()
+        full_response(
+            mime::TEXT_PLAIN_UTF_8.as_ref(),
+            Bytes::from_static(self.as_bytes()),
+        )
     }
 }
 
 impl From<String> for LieResponse {
     fn from(val: String) -> Self {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::TEXT_PLAIN_UTF_8.to_string(),
-            )
-            .body(Full::new(Bytes::from(val)).map_err(Into::into).boxed())
-            .unwrap()
-            .into()
+        full_response(mime::TEXT_PLAIN_UTF_8.as_ref(), Bytes::from(val)).into()
     }
 }
 
 impl IntoResponse for String {
     fn into_response(self) -> Response {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::TEXT_PLAIN_UTF_8.to_string(),
-            )
-            .body(Full::new(Bytes::from(self)).map_err(Into::into).boxed())
-            .unwrap()
+        full_response(mime::TEXT_PLAIN_UTF_8.as_ref(), Bytes::from(self))
     }
 }
 
@@ -373,50 +523,173 @@ impl IntoResponse for Cow<'static, str> {
     }
 }
 
-impl IntoResponse for (StatusCode, &'static str) {
+/// The status overrides the status of `T`'s response, headers and body are kept, e.g.
+/// `(StatusCode::CREATED, Json::new(user))`.
+impl<T> IntoResponse for (StatusCode, T)
+where
+    T: IntoResponse,
+{
     fn into_response(self) -> Response {
-        http::Response::builder()
-            .status(self.0)
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::TEXT_PLAIN_UTF_8.to_string(),
-            )
-            .body(Full::new(Bytes::from(self.1)).map_err(Into::into).boxed())
-            .unwrap()
+        let (status, val) = self;
+
+        let mut resp = val.into_response();
+        *resp.status_mut() = status;
+        resp
+    }
+}
+
+/// Like `(StatusCode, T)`, the headers replace those of the same name in `T`'s response.
+impl<T> IntoResponse for (StatusCode, HeaderMap, T)
+where
+    T: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        let (status, headers, val) = self;
+
+        let mut resp = (status, val).into_response();
+        let mut last = None;
+        for (name, value) in headers {
+            match name {
+                Some(name) => {
+                    resp.headers_mut().insert(name.clone(), value);
+                    last = Some(name);
+                }
+                None => {
+                    if let Some(name) = &last {
+                        resp.headers_mut().append(name, value);
+                    }
+                }
+            }
+        }
+        resp
     }
 }
 
 impl IntoResponse for crate::Error {
     fn into_response(self) -> Response {
-        tracing::error!("on IntoResponse for lieweb::Error, error: {:?}", self);
+        let mut resp = error_response(&self);
+
+        if resp.status().is_server_error() {
+            tracing::error!(
+                "on IntoResponse for lieweb::Error, error: {}",
+                self.report()
+            );
+        } else {
+            tracing::debug!(
+                "on IntoResponse for lieweb::Error, error: {}",
+                self.report()
+            );
+        }
 
-        http::Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(
-                Full::new(Bytes::from("Internal Server Error"))
-                    .map_err(Into::into)
-                    .boxed(),
-            )
-            .unwrap()
+        resp.extensions_mut().insert(ErrorSource(Arc::new(self)));
+        resp
     }
 }
 
 impl From<crate::Error> for LieResponse {
     fn from(e: crate::Error) -> Self {
-        tracing::error!("on From<lieweb::Error> for LieResponse, error: {:?}", e);
+        e.into_response().into()
+    }
+}
 
-        http::Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(
-                Full::new(Bytes::from("Internal Server Error"))
-                    .map_err(Into::into)
-                    .boxed(),
-            )
-            .unwrap()
-            .into()
+impl<E> IntoResponse for E
+where
+    E: WebError,
+{
+    fn into_response(self) -> Response {
+        crate::Error::from(self).into_response()
     }
 }
 
+/// The error a response was rendered from, for `App::error_handler` and
+/// `App::error_renderer`.
+#[derive(Clone)]
+pub(crate) struct ErrorSource(pub(crate) Arc<crate::Error>);
+
+/// Resolved `Range` for a body of known length.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ByteRange {
+    Full,
+    Partial(std::ops::Range<u64>),
+    Unsatisfiable,
+}
+
+/// Resolve a `Range` header against the body length, only a single range is served.
+pub(crate) fn byte_range(range: Option<&headers::Range>, len: u64) -> ByteRange {
+    use std::ops::Bound;
+
+    let range = match range {
+        Some(range) => range,
+        None => return ByteRange::Full,
+    };
+
+    let ranges: Vec<_> = range
+        .satisfiable_ranges(len)
+        .filter_map(|(start, end)| {
+            let start = match start {
+                Bound::Included(start) => start,
+                Bound::Excluded(start) => start + 1,
+                Bound::Unbounded => 0,
+            };
+            let end = match end {
+                Bound::Included(end) => end.saturating_add(1).min(len),
+                Bound::Excluded(end) => end.min(len),
+                Bound::Unbounded => len,
+            };
+
+            (start < end).then_some(start..end)
+        })
+        .collect();
+
+    match ranges.len() {
+        0 => ByteRange::Unsatisfiable,
+        1 => ByteRange::Partial(ranges.into_iter().next().unwrap()),
+        _ => ByteRange::Full,
+    }
+}
+
+/// Weak ETag from file size and modified time, e.g. `W/"1a2b-5f3e1c2d.0"`.
+#[cfg(feature = "fs")]
+fn file_etag(metadata: &std::fs::Metadata) -> Option<headers::ETag> {
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+
+    format!(
+        "W/\"{:x}-{:x}.{:x}\"",
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )
+    .parse()
+    .ok()
+}
+
+fn error_response(e: &crate::Error) -> Response {
+    let (status, body) = match e {
+        crate::Error::BodyReadTimeout => (StatusCode::REQUEST_TIMEOUT, "Read body timeout"),
+        crate::Error::BodyTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Payload Too Large"),
+        crate::Error::Context { source, .. } => return error_response(source),
+        crate::Error::Web(e) => {
+            return (e.status_code(), Json::new(e.error_body())).into_response();
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+    };
+
+    LieResponse::new(status, body).into()
+}
+
+/// Response with a fixed body, `Content-Type` and `Content-Length` set.
+fn full_response(content_type: &str, body: Bytes) -> Response {
+    http::Response::builder()
+        .header(http::header::CONTENT_TYPE, content_type)
+        .header(http::header::CONTENT_LENGTH, body.len())
+        .body(Full::new(body).map_err(Into::into).boxed())
+        .unwrap()
+}
+
 impl<E, R> From<Result<R, E>> for LieResponse
 where
     R: Into<LieResponse>,
@@ -443,6 +716,19 @@ where
     }
 }
 
+/// `None` gives `404 Not Found` with an empty body, same as an unmatched route.
+impl<T> IntoResponse for Option<T>
+where
+    T: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        match self {
+            Some(r) => r.into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
+
 impl<T> From<Form<T>> for LieResponse
 where
     T: serde::Serialize,
@@ -450,15 +736,10 @@ where
     fn from(form: Form<T>) -> LieResponse {
         serde_urlencoded::to_string(&form.value)
             .map(|b| {
-                LieResponse::from(
-                    http::Response::builder()
-                        .header(
-                            hyper::header::CONTENT_TYPE,
-                            mime::APPLICATION_WWW_FORM_URLENCODED.to_string(),
-                        )
-                        .body(Full::new(Bytes::from(b)).map_err(Into::into).boxed())
-                        .unwrap(),
-                )
+                LieResponse::from(full_response(
+                    mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+                    Bytes::from(b),
+                ))
             })
             .map_err(|e| {
                 tracing::error!("urlencoded form serialize failed, {:?}", e);
@@ -468,16 +749,24 @@ where
     }
 }
 
+impl<T> IntoResponse for Form<T>
+where
+    T: serde::Serialize,
+{
+    fn into_response(self) -> Response {
+        LieResponse::from(self).into()
+    }
+}
+
 impl From<Html> for LieResponse {
     fn from(val: Html) -> LieResponse {
-        http::Response::builder()
-            .header(
-                hyper::header::CONTENT_TYPE,
-                mime::TEXT_HTML_UTF_8.to_string(),
-            )
-            .body(val.body.map_err(Into::into).boxed())
-            .unwrap()
-            .into()
+        full_response(mime::TEXT_HTML_UTF_8.as_ref(), val.body).into()
+    }
+}
+
+impl IntoResponse for Html {
+    fn into_response(self) -> Response {
+        LieResponse::from(self).into()
     }
 }
 
@@ -486,35 +775,102 @@ where
     T: serde::Serialize,
 {
     fn from(json: Json<T>) -> LieResponse {
-        serde_json::to_vec(&json.value)
-            .map(|b| {
-                LieResponse::from(
-                    http::Response::builder()
-                        .header(
-                            hyper::header::CONTENT_TYPE,
-                            mime::APPLICATION_JSON.to_string(),
-                        )
-                        .body(Full::new(Bytes::from(b)).map_err(Into::into).boxed())
-                        .unwrap(),
-                )
-            })
-            .map_err(|e| {
-                tracing::error!("json serialize failed, {:?}", e);
-                crate::Error::from(e)
-            })
-            .into()
+        json_response(serde_json::to_vec(&json.value).map(Bytes::from))
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    T: serde::Serialize,
+{
+    fn into_response(self) -> Response {
+        LieResponse::from(self).into()
     }
 }
 
+impl<T> From<PrettyJson<T>> for LieResponse
+where
+    T: serde::Serialize,
+{
+    fn from(json: PrettyJson<T>) -> LieResponse {
+        json_response(serde_json::to_vec_pretty(&json.value).map(Bytes::from))
+    }
+}
+
+impl<T> IntoResponse for PrettyJson<T>
+where
+    T: serde::Serialize,
+{
+    fn into_response(self) -> Response {
+        LieResponse::from(self).into()
+    }
+}
+
+impl<T> Json<T>
+where
+    T: serde::Serialize,
+{
+    /// Serialize into a response, then finish it with `f` given the serialized body,
+    /// e.g. to set an `ETag` of the body.
+    pub fn into_response_with<F>(self, f: F) -> LieResponse
+    where
+        F: FnOnce(&[u8], LieResponse) -> LieResponse,
+    {
+        match serde_json::to_vec(&self.value) {
+            Ok(body) => {
+                let body = Bytes::from(body);
+                f(&body, json_response(Ok(body.clone())))
+            }
+            Err(e) => json_response(Err(e)),
+        }
+    }
+}
+
+fn json_response(body: Result<Bytes, serde_json::Error>) -> LieResponse {
+    body.map(|b| LieResponse::from(full_response(mime::APPLICATION_JSON.as_ref(), b)))
+        .map_err(|e| {
+            tracing::error!("json serialize failed, {:?}", e);
+            crate::Error::from(e)
+        })
+        .into()
+}
+
 impl From<BytesBody> for LieResponse {
     fn from(body: BytesBody) -> Self {
         let BytesBody { body, content_type } = body;
 
-        http::Response::builder()
-            .header(hyper::header::CONTENT_TYPE, content_type.to_string())
-            .body(Full::new(body).map_err(Into::into).boxed())
-            .unwrap()
-            .into()
+        full_response(content_type.as_ref(), body).into()
+    }
+}
+
+impl IntoResponse for BytesBody {
+    fn into_response(self) -> Response {
+        LieResponse::from(self).into()
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Response {
+        LieResponse::redirect(self.status, &self.location).into_response()
+    }
+}
+
+impl<T> IntoResponse for Paginated<T>
+where
+    T: serde::Serialize,
+{
+    fn into_response(self) -> Response {
+        let mut resp = Json::new(self.items()).into_response();
+        self.apply(resp.headers_mut());
+        resp
+    }
+}
+
+impl IntoResponse for RateLimitHeaders {
+    fn into_response(self) -> Response {
+        let mut resp = (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests").into_response();
+        self.apply(resp.headers_mut());
+        resp
     }
 }
 
@@ -537,3 +893,653 @@ where
         resp.into()
     }
 }
+
+impl<S, B, E> IntoResponse for StreamBody<S>
+where
+    S: futures::Stream<Item = Result<B, E>> + Send + Sync + 'static,
+    B: Into<Bytes> + 'static,
+    E: Into<Error> + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        LieResponse::from(self).into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use futures::{Stream, StreamExt};
+    use http_body_util::BodyExt;
+
+    use crate::http::{header, StatusCode};
+    use crate::response::IntoResponse;
+    use crate::test_helpers::{capture_logs, get, post, send, send_raw, TestClient};
+    use crate::{App, Error, ErrorContext, Paginated, RateLimitHeaders, Redirect, Streaming};
+
+    #[tokio::test]
+    async fn paginated() {
+        let mut app = App::new();
+        app.get("/todos", |uri: crate::http::Uri| async move {
+            Paginated::new(
+                &uri,
+                vec![20, 21, 22, 23, 24, 25, 26, 27, 28, 29],
+                20,
+                10,
+                45,
+            )
+        });
+        let resp = send(app, get("/todos?completed=true&offset=20&limit=10")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["x-total-count"], "45");
+        assert_eq!(
+            resp.headers()[header::LINK],
+            concat!(
+                r#"</todos?completed=true&offset=0&limit=10>; rel="first", "#,
+                r#"</todos?completed=true&offset=10&limit=10>; rel="prev", "#,
+                r#"</todos?completed=true&offset=30&limit=10>; rel="next", "#,
+                r#"</todos?completed=true&offset=40&limit=10>; rel="last""#,
+            )
+        );
+        assert_eq!(resp.body(), "[20,21,22,23,24,25,26,27,28,29]");
+
+        let page = Paginated::new(&"/todos".parse().unwrap(), Vec::<u8>::new(), 0, 10, 0);
+        assert_eq!(
+            page.link().unwrap(),
+            r#"</todos?offset=0&limit=10>; rel="first", </todos?offset=0&limit=10>; rel="last""#
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect() {
+        let mut app = App::new();
+        app.get("/", || async { Redirect::to("/login") });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers()[header::LOCATION], "/login");
+
+        let mut app = App::new();
+        app.get("/", || async { Redirect::permanent("/new") });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(resp.headers()[header::LOCATION], "/new");
+
+        let mut app = App::new();
+        app.get("/", || async { crate::LieResponse::found("/old") });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        assert_eq!(resp.headers()[header::LOCATION], "/old");
+
+        assert!(crate::LieResponse::redirect_temporary("/a\nb").is_err());
+
+        let mut app = App::new();
+        app.get("/", || async { Redirect::temporary("/a\nb") });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn return_stream() {
+        let mut app = App::new();
+        app.get("/", || async {
+            let s = futures::stream::iter(vec![Ok::<_, Error>("hello, "), Ok("world")]);
+            Streaming::new(s, mime::TEXT_PLAIN)
+        });
+
+        let resp = send(app, get("/")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(resp.body(), "hello, world");
+    }
+
+    #[tokio::test]
+    async fn pretty_json() {
+        use crate::{Json, LieResponse, PrettyJson};
+
+        let mut app = App::new();
+        app.get("/compact", || async {
+            LieResponse::from(Json::new(serde_json::json!({ "name": "lieweb" })))
+        });
+        app.get("/pretty", || async {
+            LieResponse::from(PrettyJson::new(serde_json::json!({ "name": "lieweb" })))
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/compact")).await;
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.body(), r#"{"name":"lieweb"}"#);
+
+        let resp = client.send(get("/pretty")).await;
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.body(), "{\n  \"name\": \"lieweb\"\n}");
+    }
+
+    #[tokio::test]
+    async fn option_response() {
+        let mut app = App::new();
+        app.get("/users/:id", |id: crate::Path<u32>| async move {
+            (*id.value() == 1).then_some("lieweb")
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/users/1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "lieweb");
+
+        let resp = client.send(get("/users/2")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(resp.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn status_tuple_response() {
+        use crate::http::{HeaderMap, HeaderValue};
+        use crate::Json;
+
+        let mut app = App::new();
+        app.post("/users", || async {
+            (StatusCode::CREATED, Json::new(vec!["lieweb"]))
+        });
+        let resp = send(app, post("/users", "text/plain", "")).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.body(), r#"["lieweb"]"#);
+
+        let mut app = App::new();
+        app.get("/", || async {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+            headers.append(header::VARY, HeaderValue::from_static("accept"));
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+            (StatusCode::ACCEPTED, headers, "a,b")
+        });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/csv");
+        assert_eq!(resp.headers().get_all(header::VARY).iter().count(), 2);
+        assert_eq!(resp.body(), "a,b");
+    }
+
+    #[test]
+    fn fixed_body_content_length() {
+        use crate::{Html, Json, LieResponse};
+
+        let content_length = |resp: crate::Response| {
+            resp.headers()
+                .get(header::CONTENT_LENGTH)
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+
+        assert_eq!(content_length("hello".into_response()).unwrap(), "5");
+        assert_eq!(
+            content_length(String::from("hi").into_response()).unwrap(),
+            "2"
+        );
+        assert_eq!(
+            content_length(vec![1u8, 2, 3].into_response()).unwrap(),
+            "3"
+        );
+        assert_eq!(
+            content_length(Json::new([1, 2]).into_response()).unwrap(),
+            "5"
+        );
+        assert_eq!(
+            content_length(Html::new("<p>").into_response()).unwrap(),
+            "3"
+        );
+        assert_eq!(
+            content_length(LieResponse::new(StatusCode::BAD_REQUEST, "bad").into()).unwrap(),
+            "3"
+        );
+        assert!(content_length(LieResponse::new(StatusCode::NO_CONTENT, "").into()).is_none());
+    }
+
+    #[tokio::test]
+    async fn web_error_response() {
+        use crate::{ErrorContext, WebError};
+
+        #[derive(Debug, thiserror::Error)]
+        enum UserError {
+            #[error("user {0} exists")]
+            Exists(u32),
+            #[error("invalid name")]
+            InvalidName,
+        }
+
+        impl WebError for UserError {
+            fn status_code(&self) -> StatusCode {
+                match self {
+                    UserError::Exists(_) => StatusCode::CONFLICT,
+                    UserError::InvalidName => StatusCode::BAD_REQUEST,
+                }
+            }
+        }
+
+        let mut app = App::new();
+        app.get("/direct", || async { Err::<&str, _>(UserError::Exists(1)) });
+        app.get("/error", || async {
+            Err::<&str, _>(UserError::InvalidName).context("create user")
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/direct")).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.body(), r#"{"error":"user 1 exists"}"#);
+
+        let resp = client.send(get("/error")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.body(), r#"{"error":"invalid name"}"#);
+
+        // global handler for the domain error
+        let mut app = App::new();
+        app.error_handler(|e: &UserError| {
+            (
+                e.status_code(),
+                crate::Json::new(serde_json::json!({ "code": e.status_code().as_u16() })),
+            )
+        });
+        app.get("/error", || async {
+            Err::<&str, _>(UserError::InvalidName).context("create user")
+        });
+        let resp = send(app, get("/error")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.body(), r#"{"code":400}"#);
+    }
+
+    #[tokio::test]
+    async fn error_renderer() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::http::HeaderMap;
+        use crate::middleware::{AccessLogField, JsonAccessLog};
+        use crate::{error_msg, Json};
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut access_log = JsonAccessLog::new();
+        access_log.fields([AccessLogField::Status]);
+        let writer = lines.clone();
+        access_log.writer(move |line| writer.lock().unwrap().push(line.to_string()));
+
+        let mut app = App::new();
+        app.get("/", || async { Err::<&str, _>(error_msg!("db is down")) });
+        app.get("/ok", || async { "ok" });
+        app.error_renderer(|e: &Error| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-request-id", "42".parse().unwrap());
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                headers,
+                Json::new(serde_json::json!({ "ok": false, "error": format!("{:?}", e) })),
+            )
+        });
+        app.middleware(access_log);
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/")).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()["x-request-id"], "42");
+        assert_eq!(
+            resp.body(),
+            r#"{"error":"Message(\"db is down\")","ok":false}"#
+        );
+        // middlewares see the rendered response
+        assert_eq!(lines.lock().unwrap().pop().unwrap(), r#"{"status":503}"#);
+
+        let resp = client.send(get("/ok")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "ok");
+    }
+
+    #[tokio::test]
+    async fn cached_response() {
+        use crate::response::CachedResponse;
+        use crate::Json;
+
+        let resp = Json::new(vec!["lieweb"]).into_response();
+        let cached = CachedResponse::from_response(resp).await.unwrap();
+
+        for _ in 0..2 {
+            let resp = cached.to_response();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, r#"["lieweb"]"#);
+        }
+
+        let mut app = App::new();
+        app.get("/", move || {
+            let cached = cached.clone();
+            async move { cached }
+        });
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.body(), r#"["lieweb"]"#);
+    }
+
+    #[tokio::test]
+    async fn option_json_response() {
+        use crate::Json;
+
+        #[derive(serde::Serialize)]
+        struct User {
+            name: &'static str,
+        }
+
+        async fn get_user(id: crate::Path<u32>) -> Option<Json<User>> {
+            (*id.value() == 1).then(|| Json::new(User { name: "lieweb" }))
+        }
+
+        let mut app = App::new();
+        app.get("/users/:id", get_user);
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/users/1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.body(), r#"{"name":"lieweb"}"#);
+
+        let resp = client.send(get("/users/2")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn json_into_response_with() {
+        use std::hash::{Hash, Hasher};
+
+        use crate::Json;
+
+        fn etag(body: &[u8]) -> String {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            body.hash(&mut hasher);
+            format!("\"{:x}\"", hasher.finish())
+        }
+
+        let mut app = App::new();
+        app.get("/", || async {
+            Json::new(serde_json::json!({ "name": "lieweb" }))
+                .into_response_with(|body, resp| resp.insert_header(header::ETAG, etag(body)))
+        });
+
+        let resp = send(app, get("/")).await;
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.headers()[header::ETAG], etag(resp.body()).as_str());
+        assert_eq!(resp.body(), r#"{"name":"lieweb"}"#);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_headers() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let reset = UNIX_EPOCH + Duration::from_secs(1_700_000_060);
+
+        let mut app = App::new();
+        app.get("/ok", move || async move {
+            let mut resp = "ok".into_response();
+            RateLimitHeaders::new(100, 42, reset).apply(resp.headers_mut());
+            resp
+        });
+        app.get("/limited", move || async move {
+            RateLimitHeaders::new(100, 0, reset).retry_after(Duration::from_millis(1500))
+        });
+        app.get("/exhausted", || async {
+            RateLimitHeaders::new(10, 0, SystemTime::now() + Duration::from_secs(30))
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/ok")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["x-ratelimit-limit"], "100");
+        assert_eq!(resp.headers()["x-ratelimit-remaining"], "42");
+        assert_eq!(resp.headers()["x-ratelimit-reset"], "1700000060");
+        assert!(resp.headers().get(header::RETRY_AFTER).is_none());
+
+        let resp = client.send(get("/limited")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["x-ratelimit-remaining"], "0");
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "2");
+
+        let resp = client.send(get("/exhausted")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "30");
+    }
+
+    #[test]
+    fn typed_header() {
+        let resp =
+            crate::LieResponse::with_str("{}").insert_typed_header(headers::ContentType::json());
+
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn error_context() {
+        let logs = capture_logs(|| {
+            let ret: Result<&str, Error> = Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "config.toml not found",
+            ))
+            .context("load config");
+
+            let resp = ret.context("init app").into_response();
+            assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        });
+
+        assert!(logs.contains("init app: load config: io error: config.toml not found"));
+    }
+
+    /// Collect body frames with their arrival time since the request was sent.
+    async fn timed_frames(app: App) -> Vec<(String, Duration)> {
+        let start = Instant::now();
+        let mut body = send_raw(app, get("/")).await.into_body();
+
+        let mut frames = Vec::new();
+        while let Some(frame) = body.frame().await {
+            if let Ok(data) = frame.unwrap().into_data() {
+                frames.push((String::from_utf8_lossy(&data).into_owned(), start.elapsed()));
+            }
+        }
+        frames
+    }
+
+    fn delayed(items: Vec<(u64, &'static str)>) -> impl Stream<Item = Result<&'static str, Error>> {
+        futures::stream::iter(items).then(|(delay, item)| async move {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Ok(item)
+        })
+    }
+
+    #[tokio::test]
+    async fn stream_flush_per_frame() {
+        let mut app = App::new();
+        app.get("/", || async {
+            Streaming::new(
+                delayed(vec![(0, "first"), (300, "second")]),
+                mime::TEXT_PLAIN,
+            )
+        });
+
+        let frames = timed_frames(app).await;
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, "first");
+        assert_eq!(frames[1].0, "second");
+        assert!(frames[1].1 - frames[0].1 >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn stream_keep_alive() {
+        let mut app = App::new();
+        app.get("/", || async {
+            Streaming::new(delayed(vec![(0, "start"), (350, "done")]), mime::TEXT_PLAIN)
+                .keep_alive(Duration::from_millis(100), ".")
+        });
+
+        let frames = timed_frames(app).await;
+        let data: Vec<&str> = frames.iter().map(|(data, _)| data.as_str()).collect();
+
+        assert_eq!(data, ["start", ".", ".", ".", "done"]);
+        for pair in frames.windows(2) {
+            assert!(pair[1].1 > pair[0].1 + Duration::from_millis(20));
+        }
+    }
+
+    #[tokio::test]
+    async fn bytes_range() {
+        use crate::extracts::Conditionals;
+        use crate::LieResponse;
+
+        let mut app = App::new();
+        app.get("/blob", |conditionals: Conditionals| async move {
+            LieResponse::with_bytes_range("hello, world", mime::TEXT_PLAIN, &conditionals)
+        });
+        let client = TestClient::new(app);
+
+        fn range(range: &str) -> crate::http::Request<http_body_util::Full<bytes::Bytes>> {
+            crate::http::Request::get("/blob")
+                .header(header::RANGE, range)
+                .body(http_body_util::Full::default())
+                .unwrap()
+        }
+
+        let resp = client.send(get("/blob")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(resp.body(), "hello, world");
+
+        let resp = client.send(range("bytes=7-11")).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes 7-11/12");
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(resp.body(), "world");
+
+        let resp = client.send(range("bytes=-5")).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.body(), "world");
+
+        let resp = client.send(range("bytes=20-30")).await;
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes */12");
+        assert!(resp.body().is_empty());
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn send_file_last_modified() {
+        use crate::LieResponse;
+
+        let path = std::env::temp_dir().join(format!(
+            "lieweb-send-file-cached-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "hello, cached").unwrap();
+
+        let mut app = App::new();
+        let file = path.clone();
+        app.get("/file", move |req: crate::Request| {
+            let path = file.clone();
+            async move { LieResponse::send_file_cached(&req, path).await }
+        });
+        let client = TestClient::new(app);
+        let request = |name, value: &crate::http::HeaderValue| {
+            crate::http::Request::get("/file")
+                .header(name, value)
+                .body(http_body_util::Full::<bytes::Bytes>::default())
+                .unwrap()
+        };
+
+        let resp = client.send(get("/file")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "hello, cached");
+        let last_modified = resp.headers()[header::LAST_MODIFIED].clone();
+        let etag = resp.headers()[header::ETAG].clone();
+
+        let resp = client
+            .send(request(header::IF_MODIFIED_SINCE, &last_modified))
+            .await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::LAST_MODIFIED], last_modified);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+        assert!(resp.body().is_empty());
+
+        let resp = client
+            .send(request(
+                header::IF_MODIFIED_SINCE,
+                &"Thu, 01 Jan 1970 00:00:00 GMT".parse().unwrap(),
+            ))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // If-None-Match takes precedence over If-Modified-Since
+        let req = crate::http::Request::get("/file")
+            .header(header::IF_NONE_MATCH, "W/\"other\"")
+            .header(header::IF_MODIFIED_SINCE, last_modified)
+            .body(http_body_util::Full::<bytes::Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn send_file_not_modified() {
+        use crate::extracts::Conditionals;
+        use crate::LieResponse;
+
+        let path =
+            std::env::temp_dir().join(format!("lieweb-send-file-{}.txt", std::process::id()));
+        std::fs::write(&path, "hello, file").unwrap();
+
+        let mut app = App::new();
+        let file = path.clone();
+        app.get("/file", move |conditionals: Conditionals| {
+            let path = file.clone();
+            async move { LieResponse::send_file_conditional(path, &conditionals).await }
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/file")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "hello, file");
+        let etag = resp.headers()[header::ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with("W/"));
+
+        let last_modified = resp.headers()[header::LAST_MODIFIED].clone();
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+
+        // same size and modified time, the decision is made from metadata only
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "HELLO, FILE").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let request = |etag: &crate::http::HeaderValue| {
+            crate::http::Request::get("/file")
+                .header(header::IF_NONE_MATCH, etag)
+                .body(http_body_util::Full::<bytes::Bytes>::default())
+                .unwrap()
+        };
+
+        let other = crate::http::HeaderValue::from_static("W/\"other\"");
+        let resp = client.send(request(&other)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+        assert_eq!(resp.body(), "HELLO, FILE");
+
+        let resp = client.send(request(&etag)).await;
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+        assert_eq!(resp.headers()[header::LAST_MODIFIED], last_modified);
+        assert!(resp.headers().get(header::CONTENT_TYPE).is_none());
+        assert!(resp.body().is_empty());
+    }
+}
@@ -1,17 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use hyper::http;
 use pathrouter::{Params, Router as PathRouter};
 
-use crate::endpoint::{DynEndpoint, Handler, RouterEndpoint};
+use crate::endpoint::{DynEndpoint, Endpoint, Handler, RouterEndpoint};
+use crate::extracts::{AllowedMethods, Rejections};
 use crate::middleware::{Middleware, Next};
 use crate::register_method;
 use crate::request::{Request, RequestCtx};
-use crate::response::LieResponse;
-use crate::Response;
+use crate::response::{ErrorSource, IntoResponse, LieResponse};
+use crate::{Error, Response};
 
-type MethodRoute = HashMap<http::Method, Box<DynEndpoint>>;
+/// Endpoints of a path by method.
+#[derive(Default)]
+struct MethodRoute {
+    endpoints: HashMap<http::Method, MethodEndpoint>,
+    /// Methods of `endpoints`, built as they are registered.
+    allowed: AllowedMethods,
+}
+
+impl MethodRoute {
+    fn insert(&mut self, method: http::Method, endpoint: MethodEndpoint) -> &mut MethodEndpoint {
+        self.endpoints.insert(method.clone(), endpoint);
+        self.allowed = AllowedMethods::new(self.endpoints.keys().cloned());
+
+        self.endpoints.get_mut(&method).unwrap()
+    }
+}
 
 const LIEWEB_NESTED_ROUTER: &str = "--lieweb-nested-router";
 
@@ -19,10 +35,22 @@ lazy_static::lazy_static! {
     pub static ref METHOD_ANY: http::Method = http::Method::from_bytes(b"__ANY__").unwrap();
 }
 
+pub(crate) struct MethodEndpoint {
+    endpoint: Box<DynEndpoint>,
+    /// Path pattern the route was registered with.
+    pattern: Arc<str>,
+    data: http::Extensions,
+}
+
 #[derive(Default)]
 enum Route {
     Method(MethodRoute),
-    Sub(RouterEndpoint),
+    /// Nested router, with the pattern it is registered at.
+    Sub {
+        pattern: Arc<str>,
+        router: Arc<Router>,
+        endpoint: Box<DynEndpoint>,
+    },
     #[default]
     Empty,
 }
@@ -30,21 +58,196 @@ enum Route {
 /// The result of routing a URL
 pub(crate) struct Selection<'a> {
     pub(crate) endpoint: &'a DynEndpoint,
-    pub(crate) params: Params,
+    /// Matched params, `None` when the route has none to hand over.
+    pub(crate) params: Option<Params>,
+    /// Pattern the params were captured by.
+    pub(crate) pattern: Option<&'a Arc<str>>,
+    pub(crate) data: Option<&'a http::Extensions>,
+    pub(crate) allowed: Option<&'a AllowedMethods>,
+    /// Nested router the request is handed over to.
+    pub(crate) nested: Option<&'a Router>,
+}
+
+/// Handle of a registered route, to attach per-route data.
+pub struct RouteHandle<'a> {
+    data: &'a mut http::Extensions,
+}
+
+impl<'a> RouteHandle<'a> {
+    /// Attach data to the route.
+    ///
+    /// The data is inserted into the request extensions when the route matched,
+    /// replacing data of the same type set by `Router::data`.
+    pub fn data<T>(self, val: T) -> Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.data.insert(val);
+        self
+    }
+
+    /// Tag the route, for middlewares wrapped by `middleware::Tagged`.
+    pub fn tag(self, tag: impl Into<String>) -> Self {
+        self.data
+            .get_or_insert_default::<RouteTags>()
+            .tags
+            .insert(tag.into());
+        self
+    }
+
+    /// Set `Content-Type` of successful responses of the route, after the handler and
+    /// middlewares ran, e.g. to serve a generic handler as JSON.
+    ///
+    /// It replaces the content type set by the handler. Error responses, such as
+    /// rejections, keep their own content type.
+    pub fn content_type(self, content_type: mime::Mime) -> Self {
+        match http::HeaderValue::from_str(content_type.as_ref()) {
+            Ok(value) => {
+                self.data.insert(RouteContentType(value));
+            }
+            Err(e) => tracing::error!("invalid route content type {}: {}", content_type, e),
+        }
+        self
+    }
+}
+
+/// Content type of successful responses, set by `RouteHandle::content_type`.
+#[derive(Debug, Clone)]
+struct RouteContentType(http::HeaderValue);
+
+/// Tags of the matched route, set by `RouteHandle::tag`.
+#[derive(Debug, Clone, Default)]
+pub struct RouteTags {
+    tags: HashSet<String>,
+}
+
+impl RouteTags {
+    pub fn contains(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+}
+
+/// Register routes under a common path prefix, see `Router::scope`.
+pub struct Scope<'a> {
+    router: &'a mut Router,
+    prefix: String,
+}
+
+impl<'a> Scope<'a> {
+    pub fn register<H, T>(
+        &mut self,
+        method: http::Method,
+        path: impl AsRef<str>,
+        handler: H,
+    ) -> RouteHandle<'_>
+    where
+        H: Handler<T> + Send + Sync + 'static,
+        T: 'static,
+    {
+        let path = join_path(&self.prefix, path.as_ref());
+        self.router.register(method, path, handler)
+    }
+
+    register_method!(options, http::Method::OPTIONS);
+    register_method!(get, http::Method::GET);
+    register_method!(head, http::Method::HEAD);
+    register_method!(post, http::Method::POST);
+    register_method!(put, http::Method::PUT);
+    register_method!(delete, http::Method::DELETE);
+    register_method!(trace, http::Method::TRACE);
+    register_method!(connect, http::Method::CONNECT);
+    register_method!(patch, http::Method::PATCH);
+
+    /// Nested scope, with the prefix appended to this one.
+    pub fn scope(&mut self, prefix: impl AsRef<str>) -> Scope<'_> {
+        Scope {
+            prefix: join_path(&self.prefix, prefix.as_ref()),
+            router: self.router,
+        }
+    }
+}
+
+fn join_path(prefix: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        prefix.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Prefix a nested router is merged at, e.g. `/api/` of `/api/*--lieweb-nested-router`.
+fn mount_prefix(pattern: &str) -> &str {
+    pattern
+        .strip_suffix(LIEWEB_NESTED_ROUTER)
+        .and_then(|prefix| prefix.strip_suffix('*'))
+        .unwrap_or(pattern)
+}
+
+/// Request pre-processor run before routing, see `Router::before_routing`.
+type BeforeRouting = dyn Fn(Request) -> Result<Request, Response> + Send + Sync;
+
+/// Renderer of errors of one type, see `Router::error_handler`.
+type ErrorHandler = dyn Fn(&Error) -> Option<Response> + Send + Sync;
+
+/// Renderer of all errors, see `Router::error_renderer`.
+type ErrorRenderer = dyn Fn(&Error) -> Response + Send + Sync;
+
+/// Renderers of error responses, applied by `Next` as soon as a middleware or endpoint
+/// returns, so outer middlewares see the rendered response.
+#[derive(Default)]
+pub(crate) struct ErrorRenderers {
+    handlers: Vec<Box<ErrorHandler>>,
+    renderer: Option<Box<ErrorRenderer>>,
+}
+
+impl ErrorRenderers {
+    pub(crate) fn render(&self, resp: Response) -> Response {
+        if self.handlers.is_empty() && self.renderer.is_none() {
+            return resp;
+        }
+
+        let Some(ErrorSource(e)) = resp.extensions().get::<ErrorSource>().cloned() else {
+            return resp;
+        };
+        let rendered = self
+            .handlers
+            .iter()
+            .find_map(|f| f(&e))
+            .or_else(|| self.renderer.as_ref().map(|f| f(&e)));
+
+        match rendered {
+            Some(mut rendered) => {
+                // rendered once, even if the renderer returned an error
+                rendered.extensions_mut().remove::<ErrorSource>();
+                rendered
+            }
+            None => resp,
+        }
+    }
 }
 
 pub struct Router {
+    before_routing: Vec<Box<BeforeRouting>>,
+    errors: ErrorRenderers,
     middlewares: Vec<Arc<dyn Middleware>>,
     handle_not_found: Box<DynEndpoint>,
     path_router: PathRouter<Route>,
+    paths: Vec<String>,
+    data: http::Extensions,
+    case_insensitive: bool,
 }
 
 impl Router {
     pub fn new() -> Self {
         Router {
+            before_routing: Vec::new(),
+            errors: ErrorRenderers::default(),
             middlewares: Vec::new(),
             handle_not_found: Box::new(&not_found_endpoint),
             path_router: PathRouter::new(),
+            paths: Vec::new(),
+            data: http::Extensions::new(),
+            case_insensitive: false,
         }
     }
 
@@ -81,23 +284,35 @@ impl Router {
     //     }
     // }
 
-    pub fn register<H, T>(&mut self, method: http::Method, path: impl AsRef<str>, handler: H)
+    pub fn register<H, T>(
+        &mut self,
+        method: http::Method,
+        path: impl AsRef<str>,
+        handler: H,
+    ) -> RouteHandle<'_>
     where
         H: Handler<T> + Send + Sync + 'static,
         T: 'static,
     {
-        let route = self.path_router.at_or_default(path.as_ref());
+        let route = self.route_at(path.as_ref());
 
-        let handler = Box::new(handler.into_endpoint());
+        let handler = MethodEndpoint {
+            endpoint: Box::new(handler.into_endpoint()),
+            pattern: Arc::from(path.as_ref()),
+            data: http::Extensions::new(),
+        };
+
+        if let Route::Empty = route {
+            *route = Route::Method(MethodRoute::default());
+        }
 
         match route {
             Route::Method(m) => {
-                m.insert(method, handler);
-            }
-            Route::Empty => {
-                let mut map: MethodRoute = HashMap::new();
-                map.insert(method, handler);
-                *route = Route::Method(map);
+                let handler = m.insert(method, handler);
+
+                RouteHandle {
+                    data: &mut handler.data,
+                }
             }
             _ => unreachable!(),
         }
@@ -118,6 +333,76 @@ impl Router {
         self
     }
 
+    /// Process requests before the route is looked up, e.g. for IP allowlists, so rejected
+    /// requests cost no routing. Returning `Err(resp)` responds with `resp` at once.
+    ///
+    /// Pre-processors run in registration order, before middlewares, and see neither
+    /// path params nor the data of routes.
+    pub fn before_routing<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(Request) -> Result<Request, Response> + Send + Sync + 'static,
+    {
+        self.before_routing.push(Box::new(f));
+        self
+    }
+
+    /// Attach data to all routes of the router.
+    ///
+    /// The data is inserted into the request extensions before routing, data of nested
+    /// routers and routes take precedence over it. It is cloned into every request, so
+    /// share large values behind an `Arc`.
+    pub fn data<T>(&mut self, val: T) -> &mut Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.data.insert(val);
+        self
+    }
+
+    /// Render responses of handlers and middlewares failed with an error of type `E`, e.g.
+    /// a `WebError` returned by `?`, or a source of `Error::context`.
+    ///
+    /// The first handler whose type matches is used, others keep their default response.
+    /// Responses are rendered as soon as the failed handler or middleware returns, so
+    /// middlewares around it, e.g. `AccessLog`, see the rendered response.
+    pub fn error_handler<E, F, R>(&mut self, f: F) -> &mut Self
+    where
+        E: std::error::Error + 'static,
+        F: Fn(&E) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.errors.handlers.push(Box::new(move |e: &Error| {
+            e.downcast_ref::<E>().map(|e| f(e).into_response())
+        }));
+        self
+    }
+
+    /// Render responses of handlers and middlewares failed with any error, e.g. to wrap
+    /// them in a JSON envelope, replacing the default `500 Internal Server Error` and
+    /// friends. Errors matched by an `error_handler` are left to it, and like it the
+    /// renderer runs inside the middleware chain.
+    pub fn error_renderer<F, R>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Error) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.errors.renderer = Some(Box::new(move |e: &Error| f(e).into_response()));
+        self
+    }
+
+    /// Match paths case-insensitively, by lowercasing ASCII letters of the path before
+    /// matching, so routes must be registered in lowercase, e.g. `/Users` matches
+    /// `/users`. Handlers still see the original URI, and path params and the path
+    /// left to nested routers keep their case.
+    pub fn case_insensitive(&mut self, enabled: bool) -> &mut Self {
+        self.case_insensitive = enabled;
+        self
+    }
+
+    pub(crate) fn rejections_mut(&mut self) -> &mut Rejections {
+        self.data.get_or_insert_default()
+    }
+
     pub fn set_not_found_handler<H, T>(&mut self, handler: H)
     where
         H: Handler<T> + Send + Sync + 'static,
@@ -126,6 +411,17 @@ impl Router {
         self.handle_not_found = Box::new(handler.into_endpoint());
     }
 
+    /// Register routes under `prefix` directly into this router.
+    ///
+    /// Unlike `merge`, no nested router is created, so the routes share the middlewares,
+    /// data and not found handler of this router.
+    pub fn scope(&mut self, prefix: impl AsRef<str>) -> Scope<'_> {
+        Scope {
+            router: self,
+            prefix: prefix.as_ref().to_string(),
+        }
+    }
+
     pub fn merge(
         &mut self,
         prefix: impl AsRef<str>,
@@ -141,70 +437,355 @@ impl Router {
 
         let path = prefix.to_string() + "*" + LIEWEB_NESTED_ROUTER;
 
-        let sub_router = RouterEndpoint::new(Arc::new(sub));
+        let router = Arc::new(sub);
+        let endpoint = RouterEndpoint::new(router.clone());
+
+        *self.route_at(&path) = Route::Sub {
+            pattern: Arc::from(path.as_str()),
+            router,
+            endpoint: Box::new(endpoint),
+        };
+
+        Ok(())
+    }
+
+    /// Merge routes of another router at their existing paths.
+    ///
+    /// Middlewares, data, error handlers and the error renderer of `other` only apply to
+    /// its own routes, its not found handler is dropped. Fails without changing anything
+    /// when the same method of a path, or a nested router, is registered in both routers,
+    /// when both routers register paths differing only in param names, e.g. `/users/:id`
+    /// and `/users/:name`, which would share one route,
+    /// when `other` has `before_routing` pre-processors, which can't run before routing
+    /// for its routes only, or when it matches case differently than this router.
+    pub fn extend(&mut self, mut other: Router) -> Result<(), crate::error::Error> {
+        if !other.before_routing.is_empty() {
+            return Err(crate::error::Error::Message(
+                "extend router, before_routing of the other router is not supported, \
+                 register it on this router"
+                    .to_string(),
+            ));
+        }
+        if other.case_insensitive != self.case_insensitive {
+            return Err(crate::error::Error::Message(
+                "extend router, routers must both match case sensitively or insensitively"
+                    .to_string(),
+            ));
+        }
+
+        let mut conflicts = Vec::new();
+
+        for path in &other.paths {
+            let shape = path_shape(path);
+
+            for our_path in self.paths.iter().filter(|p| path_shape(p) == shape) {
+                let ours = self.path_router.at_or_default(our_path);
+                let theirs = other.path_router.at_or_default(path);
 
-        self.path_router.add(&path, Route::Sub(sub_router));
+                match (ours, theirs) {
+                    (Route::Empty, _) | (_, Route::Empty) => {}
+                    // same route, but params named differently
+                    _ if our_path != path => conflicts.push(format!("{} and {}", our_path, path)),
+                    (Route::Method(ours), Route::Method(theirs)) => {
+                        for method in theirs
+                            .endpoints
+                            .keys()
+                            .filter(|m| ours.endpoints.contains_key(m))
+                        {
+                            conflicts.push(format!("{} {}", method, path));
+                        }
+                    }
+                    _ => conflicts.push(path.clone()),
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(crate::error::Error::Message(format!(
+                "extend router, conflicting routes: {}",
+                conflicts.join(", ")
+            )));
+        }
+
+        let Router {
+            errors,
+            middlewares,
+            mut path_router,
+            paths,
+            data,
+            ..
+        } = other;
+        let middlewares: Arc<[Arc<dyn Middleware>]> = middlewares.into();
+        let errors = Arc::new(errors);
+
+        for path in paths {
+            let theirs = std::mem::take(path_router.at_or_default(&path));
+
+            match theirs {
+                Route::Method(theirs) => {
+                    let route = self.route_at(&path);
+                    if let Route::Empty = route {
+                        *route = Route::Method(MethodRoute::default());
+                    }
+                    let Route::Method(ours) = route else {
+                        unreachable!()
+                    };
+
+                    for (method, ep) in theirs.endpoints {
+                        let mut route_data = data.clone();
+                        route_data.extend(ep.data);
+
+                        let endpoint = Layered {
+                            errors: errors.clone(),
+                            middlewares: middlewares.clone(),
+                            data: http::Extensions::new(),
+                            endpoint: ep.endpoint,
+                        };
+
+                        ours.insert(
+                            method,
+                            MethodEndpoint {
+                                endpoint: Box::new(endpoint),
+                                pattern: ep.pattern,
+                                data: route_data,
+                            },
+                        );
+                    }
+                }
+                Route::Sub {
+                    pattern,
+                    router,
+                    endpoint,
+                } => {
+                    let endpoint = Layered {
+                        errors: errors.clone(),
+                        middlewares: middlewares.clone(),
+                        data: data.clone(),
+                        endpoint,
+                    };
+                    *self.route_at(&path) = Route::Sub {
+                        pattern,
+                        router,
+                        endpoint: Box::new(endpoint),
+                    };
+                }
+                Route::Empty => {}
+            }
+        }
 
         Ok(())
     }
 
-    pub(crate) fn find(&self, path: &str, method: http::Method) -> Selection {
+    /// Registered paths and their methods, nested routers are not included.
+    #[cfg(feature = "openapi")]
+    pub(crate) fn routes(&mut self) -> Vec<(String, Vec<http::Method>)> {
+        let mut routes = Vec::new();
+
+        for path in &self.paths {
+            if let Route::Method(map) = self.path_router.at_or_default(path) {
+                routes.push((path.clone(), map.allowed.value().to_vec()));
+            }
+        }
+
+        routes
+    }
+
+    fn route_at(&mut self, path: &str) -> &mut Route {
+        if !self.paths.iter().any(|p| p == path) {
+            self.paths.push(path.to_string());
+        }
+        self.path_router.at_or_default(path)
+    }
+
+    pub(crate) fn find(&self, path: &str, method: http::Method) -> Selection<'_> {
         match self.path_router.route(path) {
             Some((route, params)) => match route {
                 Route::Method(map) => {
-                    if let Some(ep) = map.get(&method) {
+                    if let Some(ep) = map.endpoints.get(&method) {
                         return Selection {
-                            endpoint: &**ep,
-                            params,
+                            endpoint: &*ep.endpoint,
+                            params: Some(params),
+                            pattern: Some(&ep.pattern),
+                            data: Some(&ep.data),
+                            allowed: Some(&map.allowed),
+                            nested: None,
                         };
                     }
-                    if map.is_empty() {
+                    if map.endpoints.is_empty() {
                         Selection {
                             endpoint: &*self.handle_not_found,
-                            params: Params::new(),
+                            params: None,
+                            pattern: None,
+                            data: None,
+                            allowed: None,
+                            nested: None,
+                        }
+                    } else if method == http::Method::OPTIONS {
+                        Selection {
+                            endpoint: &options_allowed,
+                            params: None,
+                            pattern: None,
+                            data: None,
+                            allowed: Some(&map.allowed),
+                            nested: None,
                         }
                     } else {
                         Selection {
                             endpoint: &method_not_allowed,
-                            params: Params::new(),
+                            params: None,
+                            pattern: None,
+                            data: None,
+                            allowed: Some(&map.allowed),
+                            nested: None,
                         }
                     }
                 }
-                Route::Sub(sub) => Selection {
-                    endpoint: sub,
-                    params,
+                Route::Sub {
+                    pattern,
+                    router,
+                    endpoint,
+                } => Selection {
+                    endpoint: &**endpoint,
+                    params: Some(params),
+                    pattern: Some(pattern),
+                    data: None,
+                    allowed: None,
+                    nested: Some(router),
                 },
                 Route::Empty => Selection {
                     endpoint: &*self.handle_not_found,
-                    params: Params::new(),
+                    params: None,
+                    pattern: None,
+                    data: None,
+                    allowed: None,
+                    nested: None,
                 },
             },
             None => Selection {
                 endpoint: &*self.handle_not_found,
-                params: Params::new(),
+                params: None,
+                pattern: None,
+                data: None,
+                allowed: None,
+                nested: None,
             },
         }
     }
 
+    /// Pattern of the route matching `method` and `path`, prefixed by the patterns
+    /// nested routers are merged at, e.g. `/api/items/:id`.
+    fn matched_pattern(&self, path: &str, method: &http::Method) -> Option<String> {
+        let lowercase = self.case_insensitive.then(|| path.to_ascii_lowercase());
+        let (route, params) = self
+            .path_router
+            .route(lowercase.as_deref().unwrap_or(path))?;
+
+        match route {
+            Route::Method(map) => map.endpoints.get(method).map(|ep| ep.pattern.to_string()),
+            Route::Sub {
+                pattern, router, ..
+            } => {
+                let params = match lowercase {
+                    Some(_) => original_params(Some(pattern), path, params),
+                    None => params,
+                };
+                let inner = router.matched_pattern(params.find(LIEWEB_NESTED_ROUTER)?, method)?;
+                Some(join_path(mount_prefix(pattern), &inner))
+            }
+            Route::Empty => None,
+        }
+    }
+
     pub(crate) async fn route(&self, req: Request) -> Response {
         let mut req = req;
 
+        for f in &self.before_routing {
+            req = match f(req) {
+                Ok(req) => req,
+                Err(resp) => return resp,
+            };
+        }
+
         let method = req.method().clone();
 
         let path = RequestCtx::route_path(&req);
-        let Selection { endpoint, params } = self.find(path, method);
+        let Selection {
+            endpoint,
+            params,
+            pattern,
+            data,
+            allowed,
+            nested,
+        } = if self.case_insensitive && path.bytes().any(|b| b.is_ascii_uppercase()) {
+            let selection = self.find(&path.to_ascii_lowercase(), method.clone());
+            Selection {
+                params: selection
+                    .params
+                    .map(|params| original_params(selection.pattern.map(|p| &**p), path, params)),
+                ..selection
+            }
+        } else {
+            self.find(path, method.clone())
+        };
+
+        let content_type = data
+            .and_then(|data| data.get::<RouteContentType>())
+            .map(|content_type| content_type.0.clone());
+
+        if !self.data.is_empty() {
+            req.extensions_mut().extend(self.data.clone());
+        }
+        if let Some(data) = data.filter(|data| !data.is_empty()) {
+            req.extensions_mut().extend(data.clone());
+        }
+
+        let mut params = params;
+        let rest = params
+            .as_mut()
+            .and_then(|params| params.remove(LIEWEB_NESTED_ROUTER));
 
-        RequestCtx::merge_params(&mut req, &params);
-        if let Some(rest) = params.find(LIEWEB_NESTED_ROUTER) {
+        // the outermost router sets the pattern, so its middlewares see the routes of
+        // nested routers too, with the prefixes they are merged at
+        if RequestCtx::route_pattern(&req).is_none() {
+            let pattern = match nested {
+                Some(nested) => pattern.zip(rest.as_deref()).and_then(|(pattern, rest)| {
+                    let inner = nested.matched_pattern(rest, &method)?;
+                    Some(Arc::from(join_path(mount_prefix(pattern), &inner)))
+                }),
+                None => pattern.cloned(),
+            };
+            if let Some(pattern) = pattern {
+                RequestCtx::set_route_pattern(&mut req, pattern);
+            }
+        }
+        if let Some(allowed) = allowed {
+            RequestCtx::set_allowed_methods(&mut req, allowed.clone());
+        }
+
+        // the rest of the path is for the nested router, not a param of handlers
+        if let Some(rest) = rest {
             RequestCtx::set_route_path(&mut req, rest);
         }
+        if let Some(params) = params {
+            RequestCtx::merge_params(&mut req, params, pattern.map(|p| &**p));
+        }
 
         let next = Next {
             endpoint,
             next_middleware: &self.middlewares,
+            errors: Some(&self.errors),
         };
 
-        next.run(req).await
+        let mut resp = next.run(req).await;
+
+        if let Some(content_type) = content_type {
+            if resp.status().is_success() {
+                resp.headers_mut()
+                    .insert(http::header::CONTENT_TYPE, content_type);
+            }
+        }
+
+        resp
     }
 }
 
@@ -224,10 +805,389 @@ impl std::fmt::Debug for Router {
     }
 }
 
+/// Take the values of `params`, captured from the lowercased `path`, from `path` itself.
+///
+/// Lowercasing ASCII letters keeps the offsets, and params are whole segments, or the
+/// rest of the path for wildcards, so values are found by the segments of `pattern`.
+fn original_params(pattern: Option<&str>, path: &str, mut params: Params) -> Params {
+    let Some(pattern) = pattern else {
+        return params;
+    };
+
+    let segments = path.trim_start_matches('/').split('/');
+    for (seg, value) in pattern.trim_start_matches('/').split('/').zip(segments) {
+        if let Some(name) = seg.strip_prefix(':') {
+            params.insert(name, value);
+        } else if let Some(name) = seg.strip_prefix('*') {
+            let rest = params
+                .find(name)
+                .and_then(|rest| path.get(path.len().checked_sub(rest.len())?..));
+            if let Some(rest) = rest {
+                params.insert(name, rest);
+            }
+            break;
+        }
+    }
+
+    params
+}
+
+/// Endpoint of an extended router, with the error renderers, middlewares and data of
+/// the router.
+struct Layered {
+    errors: Arc<ErrorRenderers>,
+    middlewares: Arc<[Arc<dyn Middleware>]>,
+    data: http::Extensions,
+    endpoint: Box<DynEndpoint>,
+}
+
+#[crate::async_trait]
+impl Endpoint for Layered {
+    async fn call(&self, mut req: Request) -> Response {
+        if !self.data.is_empty() {
+            req.extensions_mut().extend(self.data.clone());
+        }
+
+        let next = Next {
+            endpoint: &*self.endpoint,
+            next_middleware: &self.middlewares,
+            errors: Some(&self.errors),
+        };
+
+        next.run(req).await
+    }
+}
+
 async fn not_found_endpoint(_ctx: Request) -> Response {
     LieResponse::from(http::StatusCode::NOT_FOUND).into()
 }
 
-async fn method_not_allowed(_ctx: Request) -> Response {
-    LieResponse::from(http::StatusCode::METHOD_NOT_ALLOWED).into()
+async fn method_not_allowed(req: Request) -> Response {
+    with_allow(&req, http::StatusCode::METHOD_NOT_ALLOWED)
+}
+
+/// The path with names of params and wildcards left out, as the path router tells routes
+/// apart.
+fn path_shape(path: &str) -> String {
+    path.split('/')
+        .map(|seg| match seg.as_bytes().first() {
+            Some(b':') => ":",
+            Some(b'*') => "*",
+            _ => seg,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Answer `OPTIONS` for paths without an explicit `OPTIONS` handler.
+async fn options_allowed(req: Request) -> Response {
+    with_allow(&req, http::StatusCode::NO_CONTENT)
+}
+
+/// Response with `Allow` listing the methods registered for the path, and `OPTIONS`.
+fn with_allow(req: &Request, status: http::StatusCode) -> Response {
+    let mut methods: Vec<&str> = RequestCtx::allowed_methods(req)
+        .map(|methods| methods.value().iter().map(http::Method::as_str).collect())
+        .unwrap_or_default();
+    if !methods.contains(&http::Method::OPTIONS.as_str()) {
+        methods.push(http::Method::OPTIONS.as_str());
+    }
+
+    LieResponse::with_status(status)
+        .insert_header(http::header::ALLOW, methods.join(", "))
+        .into()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::extracts::AllowedMethods;
+    use crate::http::{Method, StatusCode};
+    use crate::test_helpers::{get, post, send, TestClient};
+    use crate::{App, Error, LieRequest, LieResponse, PathParam, Router};
+
+    #[tokio::test]
+    async fn before_routing_rejects() {
+        use std::net::IpAddr;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        #[allow(clippy::result_large_err)]
+        fn app(banned: IpAddr, hits: Arc<AtomicUsize>) -> App {
+            let mut app = App::new();
+            app.before_routing(move |req| match req.remote_addr() {
+                Some(addr) if addr.ip() == banned => {
+                    Err(LieResponse::with_status(StatusCode::FORBIDDEN).into())
+                }
+                _ => Ok(req),
+            });
+            app.get("/", move || {
+                hits.fetch_add(1, Ordering::SeqCst);
+                async { "hello" }
+            });
+            app
+        }
+
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let resp = send(app([127, 0, 0, 1].into(), hits.clone()), get("/")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+
+        let resp = send(app([10, 0, 0, 1].into(), hits.clone()), get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn route_content_type() {
+        let mut app = App::new();
+        app.get("/text", || async { r#"{"ok":true}"# });
+        app.get("/api", || async { r#"{"ok":true}"# })
+            .content_type(mime::APPLICATION_JSON);
+        app.get("/api/:id", |id: crate::Path<u32>| async move {
+            id.take().to_string()
+        })
+        .content_type(mime::APPLICATION_JSON);
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/text")).await;
+        assert_eq!(resp.headers()["content-type"], "text/plain; charset=utf-8");
+
+        let resp = client.send(get("/api")).await;
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        assert_eq!(resp.body(), r#"{"ok":true}"#);
+
+        // rejections keep their content type
+        let resp = client.send(get("/api/abc")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().get("content-type").is_none());
+    }
+
+    #[tokio::test]
+    async fn allowed_methods() {
+        async fn handler(methods: AllowedMethods) -> String {
+            methods
+                .value()
+                .iter()
+                .map(Method::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+
+        let mut app = App::new();
+        app.get("/items", handler);
+        app.post("/items", handler);
+
+        let resp = send(app, get("/items")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "GET, POST");
+    }
+
+    #[tokio::test]
+    async fn method_not_allowed_with_allow() {
+        let mut app = App::new();
+        app.get("/items", || async { "items" });
+        app.post("/items", || async { "created" });
+        app.get("/custom", || async { "custom" });
+        app.options("/custom", || async { "custom options" });
+        let client = TestClient::new(app);
+
+        let req = crate::http::Request::delete("/items")
+            .body(http_body_util::Full::<bytes::Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers()["allow"], "GET, POST, OPTIONS");
+
+        let req = crate::http::Request::options("/items")
+            .body(http_body_util::Full::<bytes::Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(resp.headers()["allow"], "GET, POST, OPTIONS");
+
+        let req = crate::http::Request::options("/custom")
+            .body(http_body_util::Full::<bytes::Bytes>::default())
+            .unwrap();
+        let resp = client.send(req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "custom options");
+    }
+
+    #[tokio::test]
+    async fn case_insensitive() {
+        async fn user(req: crate::Request) -> String {
+            use crate::LieRequest;
+            format!(
+                "{} {}",
+                req.uri().path(),
+                req.get_param::<String>("name").unwrap()
+            )
+        }
+
+        let mut app = App::new();
+        app.case_insensitive(true);
+        app.get("/users/:name", user);
+
+        // nested routers get the rest of the path in its original case
+        let mut files = Router::new();
+        files.case_insensitive(true);
+        files.get("/files/:name", |req: crate::Request| async move {
+            use crate::LieRequest;
+            req.get_param::<String>("name").unwrap()
+        });
+        app.merge("/api/", files).unwrap();
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/Users/Alice")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        // the uri and the case of params are kept
+        assert_eq!(resp.body(), "/Users/Alice Alice");
+
+        let resp = client.send(get("/USERS/bob")).await;
+        assert_eq!(resp.body(), "/USERS/bob bob");
+
+        let resp = client.send(get("/API/Files/ReadMe")).await;
+        assert_eq!(resp.body(), "ReadMe");
+
+        let mut app = App::new();
+        app.get("/users/:name", user);
+        let resp = send(app, get("/Users/Alice")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct UserId {
+        id: u32,
+    }
+
+    #[tokio::test]
+    async fn extend_router() {
+        let mut users = Router::new();
+        users.get("/users", || async { "users" });
+        users.get("/users/:id", |param: PathParam<UserId>| async move {
+            format!("user {}", param.value().id)
+        });
+
+        let mut posts = Router::new();
+        posts.get("/posts", || async { "posts" });
+        posts.post("/users", || async { "new user" });
+
+        users.extend(posts).unwrap();
+
+        let mut app = App::new();
+        app.extend(users).unwrap();
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/users/1")).await;
+        assert_eq!(resp.body(), "user 1");
+
+        let resp = client.send(get("/posts")).await;
+        assert_eq!(resp.body(), "posts");
+
+        let resp = client.send(post("/users", "text/plain", "")).await;
+        assert_eq!(resp.body(), "new user");
+    }
+
+    #[tokio::test]
+    async fn scope() {
+        let mut app = App::new();
+
+        let mut api = app.scope("/api");
+        api.get("/users", || async { "users" });
+        api.get("posts", || async { "posts" });
+        api.scope("/v2/").get("/users", || async { "users v2" });
+
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/api/users")).await;
+        assert_eq!(resp.body(), "users");
+
+        let resp = client.send(get("/api/posts")).await;
+        assert_eq!(resp.body(), "posts");
+
+        let resp = client.send(get("/api/v2/users")).await;
+        assert_eq!(resp.body(), "users v2");
+
+        let resp = client.send(get("/users")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn extend_router_conflict() {
+        let mut a = Router::new();
+        a.get("/users", || async { "a" });
+        a.post("/users", || async { "a" });
+
+        let mut b = Router::new();
+        b.get("/users", || async { "b" });
+        b.get("/posts", || async { "b" });
+
+        match a.extend(b) {
+            Err(Error::Message(msg)) => assert!(msg.contains("GET /users")),
+            _ => panic!("expect conflict error"),
+        }
+
+        // params named differently share one route
+        let mut a = Router::new();
+        a.get("/users/:id", || async { "a" });
+
+        let mut b = Router::new();
+        b.get("/users/:name", || async { "b" });
+
+        match a.extend(b) {
+            Err(Error::Message(msg)) => {
+                assert!(msg.contains("/users/:id and /users/:name"), "{}", msg)
+            }
+            _ => panic!("expect conflict error"),
+        }
+
+        let mut a = Router::new();
+        a.get("/files/*path", || async { "a" });
+
+        let mut b = Router::new();
+        b.post("/files/*rest", || async { "b" });
+        assert!(a.extend(b).is_err());
+    }
+
+    #[tokio::test]
+    async fn extend_router_errors() {
+        let mut users = Router::new();
+        users.get("/users", || async {
+            Err::<&str, _>(Error::Message("no users".to_string()))
+        });
+        users.error_renderer(|_: &Error| {
+            LieResponse::new(StatusCode::SERVICE_UNAVAILABLE, "users unavailable")
+        });
+
+        let mut app = App::new();
+        app.get("/posts", || async {
+            Err::<&str, _>(Error::Message("no posts".to_string()))
+        });
+        app.extend(users).unwrap();
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/users")).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.body(), "users unavailable");
+
+        // routes of this router keep the default response
+        let resp = client.send(get("/posts")).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn extend_router_unsupported() {
+        let mut a = Router::new();
+        a.get("/users", || async { "a" });
+
+        let mut b = Router::new();
+        b.before_routing(Ok);
+        assert!(a.extend(b).is_err());
+
+        let mut b = Router::new();
+        b.case_insensitive(true);
+        assert!(a.extend(b).is_err());
+    }
 }
@@ -0,0 +1,146 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::endpoint::Handler;
+use crate::http::StatusCode;
+use crate::request::LieRequest;
+use crate::response::IntoResponse;
+use crate::{LieResponse, Request, Response};
+
+/// Endpoint serving files under a directory, mounted at a wildcard route, e.g.
+/// `app.get("/static/*path", ServeDir::new("./public"))`.
+///
+/// The captured `path` is percent-decoded and joined to the root, paths escaping the
+/// root with `..` are rejected with `403 Forbidden`. Files are sent by
+/// `LieResponse::send_file_cached`, with the mime type guessed from the extension.
+/// Directories serve their index file if set, otherwise `404 Not Found`.
+#[derive(Debug, Clone)]
+pub struct ServeDir {
+    inner: Arc<ServeDirInner>,
+}
+
+#[derive(Debug)]
+struct ServeDirInner {
+    root: PathBuf,
+    index: Option<String>,
+}
+
+impl ServeDir {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        ServeDir {
+            inner: Arc::new(ServeDirInner {
+                root: root.as_ref().to_path_buf(),
+                index: None,
+            }),
+        }
+    }
+
+    /// Serve this file for directory requests, e.g. `index.html`.
+    pub fn index(self, file: impl Into<String>) -> Self {
+        ServeDir {
+            inner: Arc::new(ServeDirInner {
+                root: self.inner.root.clone(),
+                index: Some(file.into()),
+            }),
+        }
+    }
+
+    pub async fn serve(&self, req: Request) -> Response {
+        let path = req.get_param::<String>("path").unwrap_or_default();
+
+        let path = match crate::utils::percent_decode(&path) {
+            Some(path) => path,
+            None => return StatusCode::BAD_REQUEST.into_response(),
+        };
+
+        let relative = match safe_relative(&path) {
+            Some(relative) => relative,
+            None => {
+                tracing::debug!("ServeDir rejected path {:?}", path);
+                return StatusCode::FORBIDDEN.into_response();
+            }
+        };
+
+        let mut path = self.inner.root.join(relative);
+
+        if tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            match &self.inner.index {
+                Some(index) => path.push(index),
+                None => return StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+
+        LieResponse::send_file_cached(&req, path)
+            .await
+            .into_response()
+    }
+}
+
+/// Relative path of normal components, `None` when escaping the root.
+fn safe_relative(path: &str) -> Option<PathBuf> {
+    if path.contains('\\') || path.contains('\0') {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(relative)
+}
+
+#[crate::async_trait]
+impl Handler<ServeDir> for ServeDir {
+    async fn call(self, req: Request) -> Response {
+        self.serve(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::{header, StatusCode};
+    use crate::test_helpers::{get, TestClient};
+    use crate::App;
+
+    use super::ServeDir;
+
+    #[tokio::test]
+    async fn serve_dir() {
+        let root = std::env::temp_dir().join(format!("lieweb-serve-dir-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("app.js"), "console.log(1)").unwrap();
+        std::fs::write(root.join("docs/index.html"), "<h1>docs</h1>").unwrap();
+
+        let mut app = App::new();
+        app.get("/static/*path", ServeDir::new(&root).index("index.html"));
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/static/app.js")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(resp.body(), "console.log(1)");
+
+        let resp = client.send(get("/static/docs/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(resp.body(), "<h1>docs</h1>");
+
+        let resp = client.send(get("/static/missing.css")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        for path in ["/static/../secret", "/static/docs/%2e%2e/%2e%2e/secret"] {
+            let resp = client.send(get(path)).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{}", path);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,37 +1,84 @@
+use std::future::Future;
+use std::net::SocketAddr;
 #[cfg(feature = "tls")]
 use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use hyper::http;
 use hyper::service::service_fn;
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::rt::TokioIo;
 use lazy_static::lazy_static;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::{watch, Semaphore};
+use tracing::Instrument;
 
+use crate::config::{HttpBuilder, HttpConfig, ServerConfig};
 use crate::endpoint::Handler;
 use crate::endpoint::{Endpoint, RouterEndpoint};
 use crate::error::Error;
+use crate::extracts::{Authenticator, AuthenticatorRef, Rejected, RejectionKind};
+use crate::framing::FramingGuard;
 use crate::middleware::{Middleware, WithState};
 use crate::register_method;
-use crate::request::{Request, RequestCtx};
-use crate::response::Response;
-use crate::router::Router;
+use crate::request::{BodyLimit, Request, RequestCtx};
+use crate::response::{IntoResponse, Response};
+use crate::router::{RouteHandle, Router, Scope};
+use crate::write_timeout::WriteTimeoutIo;
+
+const FAVICON_CACHE_CONTROL: &str = "public, max-age=86400";
+const ACCEPT_ERROR_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+#[cfg(feature = "tls")]
+const DEFAULT_TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
 lazy_static! {
+    static ref FAVICON_MIME: mime::Mime = "image/x-icon".parse().unwrap();
     pub static ref SERVER_ID: String = format!("Lieweb {}", env!("CARGO_PKG_VERSION"));
 }
 
 pub struct App {
     router: Router,
+    http_config: HttpConfig,
+    max_connections: Option<usize>,
+    #[cfg(feature = "tls")]
+    tls_handshake_timeout: std::time::Duration,
 }
 
 impl App {
     pub fn new() -> App {
         App {
             router: Router::new(),
+            http_config: HttpConfig::default(),
+            max_connections: None,
+            #[cfg(feature = "tls")]
+            tls_handshake_timeout: DEFAULT_TLS_HANDSHAKE_TIMEOUT,
         }
     }
 
+    /// Create an app with HTTP protocol options.
+    pub fn with_http_config(config: HttpConfig) -> App {
+        let mut app = App::new();
+
+        app.http_config = config;
+        app
+    }
+
+    /// Set HTTP protocol options, e.g. HTTP/2 flow control windows and keep-alive.
+    pub fn http_config(&mut self, config: HttpConfig) -> &mut Self {
+        self.http_config = config;
+        self
+    }
+
+    /// Create an app rejecting request bodies larger than `limit` bytes, see `BodyLimit`.
+    pub fn with_body_limit(limit: usize) -> App {
+        let mut app = App::new();
+
+        app.data(BodyLimit(limit));
+        app
+    }
+
     pub fn with_state<T>(state: T) -> App
     where
         T: Send + Sync + 'static + Clone,
@@ -42,6 +89,11 @@ impl App {
         app
     }
 
+    /// Register routes under `prefix`, see `Router::scope`.
+    pub fn scope(&mut self, prefix: impl AsRef<str>) -> Scope<'_> {
+        self.router.scope(prefix)
+    }
+
     pub fn merge(
         &mut self,
         prefix: impl AsRef<str>,
@@ -50,7 +102,68 @@ impl App {
         self.router.merge(prefix, router)
     }
 
-    pub fn register<H, T>(&mut self, method: http::Method, path: impl AsRef<str>, handler: H)
+    /// Serve a minimal OpenAPI 3.0 document of registered routes at `/openapi.json`.
+    ///
+    /// Only paths and methods are listed, call it after all routes registered.
+    #[cfg(feature = "openapi")]
+    pub fn openapi(&mut self, title: &str, version: &str) -> &mut Self {
+        let doc = crate::openapi::document(title, version, &self.router.routes());
+
+        self.router.get("/openapi.json", move || {
+            let doc = doc.clone();
+            async move { crate::LieResponse::with_json(doc) }
+        });
+        self
+    }
+
+    /// Serve `GET /favicon.ico` with the icon, or `204 No Content` when it is empty,
+    /// cacheable for a day.
+    pub fn favicon(&mut self, icon: impl Into<bytes::Bytes>) -> &mut Self {
+        let icon = icon.into();
+
+        self.router.get("/favicon.ico", move || {
+            let icon = icon.clone();
+            async move {
+                let resp = if icon.is_empty() {
+                    crate::LieResponse::with_status(http::StatusCode::NO_CONTENT)
+                } else {
+                    crate::BytesBody::new(icon, FAVICON_MIME.clone()).into()
+                };
+                resp.insert_header(http::header::CACHE_CONTROL, FAVICON_CACHE_CONTROL)
+            }
+        });
+        self
+    }
+
+    /// Serve `GET /favicon.ico` from a file, with `Last-Modified` and `ETag` validation.
+    #[cfg(feature = "fs")]
+    pub fn favicon_file(&mut self, path: impl AsRef<std::path::Path>) -> &mut Self {
+        let path = path.as_ref().to_path_buf();
+
+        self.router.get("/favicon.ico", move |req: Request| {
+            let path = path.clone();
+            async move {
+                crate::LieResponse::send_file_cached(&req, path)
+                    .await
+                    .map(|resp| {
+                        resp.insert_header(http::header::CACHE_CONTROL, FAVICON_CACHE_CONTROL)
+                    })
+            }
+        });
+        self
+    }
+
+    /// Merge routes of another router at their existing paths, see `Router::extend`.
+    pub fn extend(&mut self, router: Router) -> Result<(), crate::error::Error> {
+        self.router.extend(router)
+    }
+
+    pub fn register<H, T>(
+        &mut self,
+        method: http::Method,
+        path: impl AsRef<str>,
+        handler: H,
+    ) -> RouteHandle<'_>
     where
         H: Handler<T> + Send + Sync + 'static,
         T: 'static,
@@ -68,11 +181,94 @@ impl App {
     register_method!(connect, http::Method::CONNECT);
     register_method!(patch, http::Method::PATCH);
 
+    /// Match paths case-insensitively, see `Router::case_insensitive`.
+    pub fn case_insensitive(&mut self, enabled: bool) -> &mut Self {
+        self.router.case_insensitive(enabled);
+        self
+    }
+
+    /// Limit simultaneous connections, bounding file descriptors and memory.
+    ///
+    /// Once `max` connections are open, accepting pauses until one of them closes, so
+    /// further clients wait in the listen backlog.
+    pub fn max_connections(&mut self, max: usize) -> &mut Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Limit the time for a TLS handshake in `run_with_tls`, default is 5 seconds.
+    ///
+    /// Connections of clients stalling the handshake are dropped when exceeded, so they
+    /// do not hold connection slots.
+    #[cfg(feature = "tls")]
+    pub fn tls_handshake_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.tls_handshake_timeout = timeout;
+        self
+    }
+
     pub fn middleware(&mut self, m: impl Middleware) -> &mut Self {
         self.router.middleware(m);
         self
     }
 
+    /// Process requests before routing, see `Router::before_routing`.
+    pub fn before_routing<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(Request) -> Result<Request, Response> + Send + Sync + 'static,
+    {
+        self.router.before_routing(f);
+        self
+    }
+
+    /// Attach data to all routes, see `Router::data`.
+    pub fn data<T>(&mut self, val: T) -> &mut Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.router.data(val);
+        self
+    }
+
+    /// Register the authenticator producing the user of `Auth<A::User>`.
+    pub fn authenticator<A>(&mut self, authenticator: A) -> &mut Self
+    where
+        A: Authenticator,
+    {
+        self.router.data(AuthenticatorRef::new(authenticator));
+        self
+    }
+
+    /// Customize the response of a well-known rejection, falling back to the default one.
+    pub fn rejection<F, R>(&mut self, kind: RejectionKind, builder: F) -> &mut Self
+    where
+        F: Fn(&Rejected) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router.rejections_mut().insert(kind, builder);
+        self
+    }
+
+    /// Render errors of type `E` in one place, see `Router::error_handler`.
+    pub fn error_handler<E, F, R>(&mut self, f: F) -> &mut Self
+    where
+        E: std::error::Error + 'static,
+        F: Fn(&E) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router.error_handler(f);
+        self
+    }
+
+    /// Render all error responses in one place, see `Router::error_renderer`.
+    pub fn error_renderer<F, R>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&crate::Error) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router.error_renderer(f);
+        self
+    }
+
     pub fn handle_not_found<H, T>(&mut self, handler: H) -> &mut Self
     where
         H: Handler<T> + Send + Sync + 'static,
@@ -84,9 +280,9 @@ impl App {
 
     pub async fn respond(self, req: Request) -> Response {
         let mut req = req;
-        RequestCtx::init(&mut req, None);
+        RequestCtx::init(&mut req, None, http::uri::Scheme::HTTP);
 
-        let App { router } = self;
+        let App { router, .. } = self;
 
         let router = Arc::new(router);
 
@@ -95,39 +291,179 @@ impl App {
     }
 
     pub async fn run(self, addr: impl ToSocketAddrs) -> Result<(), Error> {
-        let App { router } = self;
+        let listener = TcpListener::bind(addr).await?;
 
-        let router = Arc::new(router);
+        self.serve(listener, ServerConfig::default()).await
+    }
+
+    pub async fn run_with_config(
+        self,
+        addr: impl ToSocketAddrs,
+        config: ServerConfig,
+    ) -> Result<(), Error> {
+        let listener = config.bind(addr).await?;
+
+        self.serve(listener, config).await
+    }
+
+    /// Like `run_with_config`, but shutdown gracefully once `signal` completes.
+    ///
+    /// The listener is closed, idle keep-alive connections are closed immediately, and
+    /// connections with requests in flight are closed once their responses are sent.
+    /// Returns when all connections are closed.
+    pub async fn run_with_shutdown(
+        self,
+        addr: impl ToSocketAddrs,
+        config: ServerConfig,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        let listener = config.bind(addr).await?;
+
+        self.serve_with_shutdown(listener, config, signal).await
+    }
 
-        let listener = TcpListener::bind(addr).await.unwrap();
-        while let Ok((socket, remote_addr)) = listener.accept().await {
-            let server = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
-            let router = router.clone();
+    pub(crate) async fn serve(
+        self,
+        listener: TcpListener,
+        config: ServerConfig,
+    ) -> Result<(), Error> {
+        self.serve_with_shutdown(listener, config, std::future::pending())
+            .await
+    }
+
+    pub(crate) async fn serve_with_shutdown(
+        self,
+        listener: TcpListener,
+        config: ServerConfig,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tls")]
+        let tls_handshake_timeout = self.tls_handshake_timeout;
+        let App {
+            router,
+            http_config,
+            max_connections,
+            ..
+        } = self;
 
-            tokio::task::spawn(async move {
-                let router = router.clone();
+        let endpoint = RouterEndpoint::new(Arc::new(router));
+        let server = config.http_builder(&http_config);
+        #[cfg(feature = "tls")]
+        let tls = config.tls_acceptor();
+        let max_uri_length = http_config.uri_length_limit();
+        let write_timeout = config.write_timeout_limit();
+        let connections = max_connections.map(|max| Arc::new(Semaphore::new(max)));
+        let (graceful, shutdown) = watch::channel(false);
+        let mut ret = Ok(());
 
-                let ret = server.serve_connection_with_upgrades(
-                    TokioIo::new(socket),
-                    service_fn(|mut req| {
-                        let router = router.clone();
-                        RequestCtx::init(&mut req, Some(remote_addr));
+        tokio::pin!(signal);
 
-                        async move {
-                            let endpoint = RouterEndpoint::new(router);
-                            let resp = endpoint.call(req).await;
-                            Ok::<_, Error>(resp)
+        loop {
+            let permit = match &connections {
+                Some(connections) => tokio::select! {
+                    permit = connections.clone().acquire_owned() => permit.ok(),
+                    _ = &mut signal => break,
+                },
+                None => None,
+            };
+
+            let conn = tokio::select! {
+                conn = listener.accept() => conn,
+                _ = &mut signal => break,
+            };
+            let (socket, remote_addr) = match conn {
+                Ok(conn) => conn,
+                Err(err) => match accept_backoff(err) {
+                    Ok(backoff) => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(backoff) => continue,
+                            _ = &mut signal => break,
                         }
-                    }),
-                );
+                    }
+                    Err(err) => {
+                        ret = Err(err.into());
+                        break;
+                    }
+                },
+            };
 
-                if let Err(e) = ret.await {
-                    tracing::error!("serve_connection error: {:?}", e);
+            if let Err(err) = config.apply_socket(&socket) {
+                tracing::debug!("set socket options failed, {:?}", err);
+            }
+
+            let endpoint = endpoint.clone();
+            let server = server.clone();
+            let shutdown = shutdown.clone();
+            #[cfg(feature = "tls")]
+            let tls = tls.clone();
+
+            tokio::task::spawn(
+                async move {
+                    let _permit = permit;
+                    let io = WriteTimeoutIo::new(socket, write_timeout);
+
+                    #[cfg(feature = "tls")]
+                    if let Some(acceptor) = tls {
+                        if let Some(io) = tls_handshake(acceptor, io, tls_handshake_timeout).await {
+                            serve_connection(
+                                io,
+                                endpoint,
+                                server,
+                                Some(remote_addr),
+                                http::uri::Scheme::HTTPS,
+                                Some(shutdown),
+                                max_uri_length,
+                            )
+                            .await;
+                        }
+                        return;
+                    }
+
+                    serve_connection(
+                        io,
+                        endpoint,
+                        server,
+                        Some(remote_addr),
+                        http::uri::Scheme::HTTP,
+                        Some(shutdown),
+                        max_uri_length,
+                    )
+                    .await;
                 }
-            });
+                .instrument(connection_span(remote_addr)),
+            );
         }
 
-        Ok(())
+        drop(listener);
+        drop(shutdown);
+        // connections shutdown, wait for them to close
+        let _ = graceful.send(true);
+        graceful.closed().await;
+
+        ret
+    }
+
+    #[cfg(test)]
+    pub(crate) fn into_io_server(self) -> IoServer {
+        let App {
+            router,
+            http_config,
+            ..
+        } = self;
+
+        IoServer {
+            endpoint: RouterEndpoint::new(Arc::new(router)),
+            server: ServerConfig::default().http_builder(&http_config),
+            max_uri_length: http_config.uri_length_limit(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) async fn serve_io<I>(self, io: I, remote_addr: Option<SocketAddr>)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.into_io_server().serve(io, remote_addr).await
     }
 
     #[cfg(feature = "tls")]
@@ -137,51 +473,228 @@ impl App {
         cert: impl AsRef<Path>,
         key: impl AsRef<Path>,
     ) -> Result<(), Error> {
-        let App { router } = self;
+        let tls_config = crate::tls::TlsConfig::from_files(cert, key)?;
 
-        let router = Arc::new(router);
+        self.run_with_tls_config(addr, ServerConfig::default(), tls_config)
+            .await
+    }
 
-        let tls_acceptor = crate::tls::new_tls_acceptor(cert, key)?;
-
-        let listener = TcpListener::bind(addr).await.unwrap();
-        while let Ok((socket, remote_addr)) = listener.accept().await {
-            let tls_acceptor = tls_acceptor.clone();
-            let router = router.clone();
-
-            tokio::task::spawn(async move {
-                let tls_acceptor = tls_acceptor.clone();
-                let server = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
-                let router = router.clone();
-
-                match tls_acceptor.accept(socket).await {
-                    Ok(stream) => {
-                        let stream = TokioIo::new(stream);
-                        let ret = server.serve_connection(
-                            stream,
-                            service_fn(|mut req| {
-                                let router = router.clone();
-                                RequestCtx::init(&mut req, Some(remote_addr));
-
-                                async move {
-                                    let endpoint = RouterEndpoint::new(router);
-                                    let resp = endpoint.call(req).await;
-                                    Ok::<_, Error>(resp)
-                                }
-                            }),
-                        );
-
-                        if let Err(e) = ret.await {
-                            tracing::error!("serve_connection error: {:?}", e);
-                        }
-                    }
-                    Err(err) => {
-                        tracing::error!("tls accept failed, {:?}", err);
-                    }
-                }
-            });
+    /// Like `run_with_tls`, with the certificate chain and private key in PEM from memory.
+    #[cfg(feature = "tls")]
+    pub async fn run_with_tls_pem(
+        self,
+        addr: impl ToSocketAddrs,
+        cert_pem: &[u8],
+        key_pem: &[u8],
+    ) -> Result<(), Error> {
+        let tls_config = crate::tls::TlsConfig::from_pem(cert_pem, key_pem)?;
+
+        self.run_with_tls_config(addr, ServerConfig::default(), tls_config)
+            .await
+    }
+
+    /// Like `run_with_config`, serving TLS connections with `tls_config`.
+    ///
+    /// Socket options, timeouts and connection limits of `config` apply as with plain
+    /// connections. Use `ServerConfig::tls` with `run_with_shutdown` to shutdown gracefully.
+    #[cfg(feature = "tls")]
+    pub async fn run_with_tls_config(
+        self,
+        addr: impl ToSocketAddrs,
+        config: ServerConfig,
+        tls_config: crate::tls::TlsConfig,
+    ) -> Result<(), Error> {
+        self.run_with_config(addr, config.tls(tls_config)).await
+    }
+}
+
+/// Complete the TLS handshake of `io` within `timeout`, failures are logged.
+#[cfg(feature = "tls")]
+async fn tls_handshake<I>(
+    acceptor: tokio_rustls::TlsAcceptor,
+    io: I,
+    timeout: std::time::Duration,
+) -> Option<tokio_rustls::server::TlsStream<I>>
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+{
+    match tokio::time::timeout(timeout, acceptor.accept(io)).await {
+        Ok(Ok(stream)) => Some(stream),
+        Ok(Err(err)) => {
+            tracing::error!("tls accept failed, {:?}", err);
+            None
         }
+        Err(_) => {
+            tracing::debug!("tls handshake timed out");
+            None
+        }
+    }
+}
+
+/// Delay before accepting again after `err`, or `err` back when the listener is unusable.
+///
+/// Errors of the incoming connection are skipped right away, others, e.g. running out of
+/// file descriptors, are likely transient and retried after a backoff.
+fn accept_backoff(err: std::io::Error) -> Result<std::time::Duration, std::io::Error> {
+    use std::io::ErrorKind;
+
+    match err.kind() {
+        ErrorKind::ConnectionAborted
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+        | ErrorKind::Interrupted => {
+            tracing::debug!("accept connection failed, {:?}", err);
+            Ok(std::time::Duration::ZERO)
+        }
+        ErrorKind::InvalidInput | ErrorKind::NotConnected | ErrorKind::Unsupported => {
+            tracing::error!("accept failed, stop serving, {:?}", err);
+            Err(err)
+        }
+        _ => {
+            tracing::error!(
+                "accept failed, retry in {:?}, {:?}",
+                ACCEPT_ERROR_BACKOFF,
+                err
+            );
+            Ok(ACCEPT_ERROR_BACKOFF)
+        }
+    }
+}
+
+/// Length of the request target, e.g. `/path?query`.
+fn uri_length<B>(req: &http::Request<B>) -> usize {
+    req.uri().path_and_query().map_or(0, |pq| pq.as_str().len())
+}
+
+/// Span of a connection task, to identify it in diagnostics.
+fn connection_span(remote_addr: SocketAddr) -> tracing::Span {
+    tracing::info_span!("connection", %remote_addr)
+}
+
+/// Shutdown signal of `serve_with_shutdown`, held by connections until they are closed.
+type Shutdown = watch::Receiver<bool>;
+
+/// Drive `conn`, shutting it down gracefully with `graceful` once `shutdown` is signaled.
+async fn watch<C>(
+    conn: C,
+    shutdown: Option<Shutdown>,
+    graceful: impl FnOnce(Pin<&mut C>),
+) -> C::Output
+where
+    C: Future,
+{
+    tokio::pin!(conn);
+
+    if let Some(mut shutdown) = shutdown {
+        tokio::select! {
+            ret = conn.as_mut() => return ret,
+            _ = shutdown.wait_for(|shutdown| *shutdown) => graceful(conn.as_mut()),
+        }
+    }
 
-        Ok(())
+    conn.await
+}
+
+/// The app serving in-memory connections in tests, all of them routed by the same router.
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct IoServer {
+    endpoint: RouterEndpoint,
+    server: HttpBuilder,
+    max_uri_length: Option<usize>,
+}
+
+#[cfg(test)]
+impl IoServer {
+    pub(crate) async fn serve<I>(self, io: I, remote_addr: Option<SocketAddr>)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        serve_connection(
+            io,
+            self.endpoint,
+            self.server,
+            remote_addr,
+            http::uri::Scheme::HTTP,
+            None,
+            self.max_uri_length,
+        )
+        .await
+    }
+}
+
+/// Serve a connection with the router.
+///
+/// Conflicting message framing is rejected with `400 Bad Request`, against request
+/// smuggling: differing `Content-Length` values by the HTTP/1 parser, and both
+/// `Transfer-Encoding` and `Content-Length` by `FramingGuard`. The connection is closed
+/// after the response.
+async fn serve_connection<I>(
+    io: I,
+    endpoint: RouterEndpoint,
+    server: HttpBuilder,
+    remote_addr: Option<SocketAddr>,
+    scheme: http::uri::Scheme,
+    shutdown: Option<Shutdown>,
+    max_uri_length: Option<usize>,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let io = FramingGuard::new(io, max_uri_length);
+    let uri_too_long = io.uri_too_long();
+    // requests of a connection, counted as `FramingGuard` counts them
+    let requests = AtomicU64::new(0);
+
+    #[cfg(feature = "early-hints")]
+    let (io, interim) = {
+        let io = crate::early_hints::InterimIo::new(io);
+        let interim = io.sender();
+        (io, interim)
+    };
+
+    let service = service_fn(move |mut req| {
+        let request = requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let uri_too_long = uri_too_long.is_rejected(request)
+            || max_uri_length.is_some_and(|max| uri_length(&req) > max);
+        let endpoint = endpoint.clone();
+        RequestCtx::init(&mut req, remote_addr, scheme.clone());
+        #[cfg(feature = "early-hints")]
+        let interim = interim.open();
+        #[cfg(feature = "early-hints")]
+        req.extensions_mut().insert(interim.clone());
+
+        async move {
+            if uri_too_long {
+                return Ok(http::StatusCode::URI_TOO_LONG.into_response());
+            }
+
+            let resp = endpoint.call(req).await;
+            #[cfg(feature = "early-hints")]
+            interim.close();
+            Ok::<_, Error>(resp)
+        }
+    });
+
+    let io = TokioIo::new(io);
+    let ret = match &server {
+        HttpBuilder::Auto(server) if server.is_http1_available() => {
+            let conn = server.serve_connection_with_upgrades(io, service);
+            watch(conn, shutdown, |conn| conn.graceful_shutdown()).await
+        }
+        HttpBuilder::Auto(server) => {
+            let conn = server.serve_connection(io, service);
+            watch(conn, shutdown, |conn| conn.graceful_shutdown()).await
+        }
+        HttpBuilder::Http1(server) => {
+            let conn = server.serve_connection(io, service).with_upgrades();
+            watch(conn, shutdown, |conn| conn.graceful_shutdown())
+                .await
+                .map_err(Into::into)
+        }
+    };
+
+    if let Err(e) = ret {
+        tracing::error!("serve_connection error: {:?}", e);
     }
 }
 
@@ -194,3 +707,403 @@ impl Default for App {
 pub fn server_id() -> &'static str {
     &SERVER_ID
 }
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::http::{header, StatusCode};
+    use crate::middleware::AccessLog;
+    use crate::test_helpers::{get, send, send_bytes, LogCapture, TestClient};
+    use crate::{App, BytesBody, ServerConfig};
+
+    #[tokio::test]
+    async fn instrumented_connection() {
+        let capture = LogCapture::new();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.middleware(AccessLog);
+        app.get("/", || async { "ok" });
+        tokio::spawn(app.serve(listener, ServerConfig::default()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let local_addr = stream.local_addr().unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        let resp = String::from_utf8_lossy(&buf);
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.ends_with("ok"));
+        assert!(capture
+            .logs()
+            .contains(&format!("connection{{remote_addr={}}}", local_addr)));
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn tls_handshake_timeout() {
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        use tokio_rustls::rustls::server::ResolvesServerCertUsingSni;
+
+        let capture = LogCapture::new();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // the handshake never starts, so no certificate is needed
+        let config = tokio_rustls::rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(ResolvesServerCertUsingSni::new()));
+        let tls = crate::TlsConfig::from_rustls(config);
+
+        let mut app = App::new();
+        app.tls_handshake_timeout(Duration::from_millis(100));
+        app.get("/", || async { "ok" });
+        tokio::spawn(app.serve(listener, ServerConfig::new().tls(tls)));
+
+        // connect without sending a ClientHello
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+            .await
+            .expect("connection not dropped")
+            .unwrap_or_default();
+
+        assert_eq!(n, 0);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        capture.assert_logged(tracing::Level::DEBUG, "tls handshake timed out");
+    }
+
+    #[test]
+    fn accept_backoff() {
+        use std::io::{Error, ErrorKind};
+        use std::time::Duration;
+
+        let backoff = |kind| super::accept_backoff(Error::from(kind)).ok();
+
+        assert_eq!(backoff(ErrorKind::ConnectionAborted), Some(Duration::ZERO));
+        assert_eq!(
+            backoff(ErrorKind::OutOfMemory),
+            Some(super::ACCEPT_ERROR_BACKOFF)
+        );
+        // EMFILE, too many open files
+        #[cfg(unix)]
+        assert_eq!(
+            super::accept_backoff(Error::from_raw_os_error(24)).ok(),
+            Some(super::ACCEPT_ERROR_BACKOFF)
+        );
+        assert_eq!(backoff(ErrorKind::InvalidInput), None);
+    }
+
+    #[tokio::test]
+    async fn bind_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let err = App::new().run(addr).await.unwrap_err();
+        assert!(
+            matches!(&err, crate::Error::IOError(e) if e.kind() == std::io::ErrorKind::AddrInUse),
+            "{:?}",
+            err
+        );
+
+        #[cfg(feature = "tls")]
+        {
+            let err = App::new()
+                .run_with_tls_pem(
+                    addr,
+                    include_bytes!("../testdata/ec.crt"),
+                    include_bytes!("../testdata/ec-sec1.key"),
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(err, crate::Error::IOError(_)), "{:?}", err);
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn tls_alpn_h2() {
+        use std::sync::Arc;
+
+        use tokio_rustls::rustls::pki_types::ServerName;
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+        const CERT: &[u8] = include_bytes!("../testdata/ec.crt");
+        const KEY: &[u8] = include_bytes!("../testdata/ec-sec1.key");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let tls = crate::TlsConfig::from_pem(CERT, KEY).unwrap();
+        let mut app = App::new();
+        app.get("/", |req: crate::Request| async move {
+            format!("{:?}", req.version())
+        });
+        tokio::spawn(app.serve(listener, ServerConfig::new().tls(tls)));
+
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &CERT[..]) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(
+            hyper_util::rt::TokioExecutor::new(),
+            hyper_util::rt::TokioIo::new(stream),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(conn);
+
+        let req = crate::http::Request::get("https://localhost/")
+            .body(http_body_util::Empty::<bytes::Bytes>::new())
+            .unwrap();
+        let resp = sender.send_request(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = http_body_util::BodyExt::collect(resp.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(body, "HTTP/2.0");
+    }
+
+    async fn read_response(stream: &mut TcpStream, body: &str) -> String {
+        let mut buf = Vec::new();
+        while !buf.ends_with(body.as_bytes()) {
+            let mut chunk = [0; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before response");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_idle_connections_first() {
+        use std::time::{Duration, Instant};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.get("/", || async { "ok" });
+        app.get("/slow", || async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            "slow"
+        });
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let server =
+            tokio::spawn(
+                app.serve_with_shutdown(listener, ServerConfig::default(), async {
+                    let _ = rx.await;
+                }),
+            );
+
+        // idle keep-alive connection, after one request
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+        idle.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        read_response(&mut idle, "ok").await;
+
+        // active connection, with a request in flight
+        let mut active = TcpStream::connect(addr).await.unwrap();
+        active
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let start = Instant::now();
+        tx.send(()).unwrap();
+
+        let mut buf = Vec::new();
+        idle.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert!(start.elapsed() < Duration::from_millis(300));
+
+        let resp = read_response(&mut active, "slow").await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+
+        server.await.unwrap().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn max_connections() {
+        use std::time::Duration;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut app = App::new();
+        app.max_connections(1);
+        app.get("/", || async { "ok" });
+        tokio::spawn(app.serve(listener, ServerConfig::default()));
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        read_response(&mut first, "ok").await;
+
+        // the second connection is not served while the first is open
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        second
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut chunk = [0; 64];
+        let pending = tokio::time::timeout(Duration::from_millis(300), second.read(&mut chunk));
+        assert!(pending.await.is_err());
+
+        drop(first);
+        let resp = tokio::time::timeout(Duration::from_secs(5), read_response(&mut second, "ok"))
+            .await
+            .expect("served once the first connection closed");
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn reject_conflicting_content_length() {
+        let mut app = App::new();
+        app.post("/", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        app.get("/admin", || async { "admin" });
+
+        let resp = send_bytes(
+            app,
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nhello",
+        )
+        .await;
+
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request"));
+    }
+
+    #[tokio::test]
+    async fn reject_transfer_encoding_with_content_length() {
+        let mut app = App::new();
+        app.post("/", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        app.get("/admin", || async { "admin" });
+        let client = TestClient::new(app);
+
+        // the smuggled request must not be served on the same connection
+        let resp = client
+            .send_bytes(
+                b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n\
+                  5\r\nhello\r\n0\r\n\r\n\
+                  GET /admin HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            )
+            .await;
+
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request"));
+        assert_eq!(resp.matches("HTTP/1.1").count(), 1);
+
+        // in any order, after a chunked request on the same connection
+        let resp = client
+            .send_bytes(
+                b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  5;ext=1\r\nhello\r\n0\r\nX-Trailer: 1\r\n\r\n\
+                  POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  5\r\nhello\r\n0\r\n\r\n",
+            )
+            .await;
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(resp.matches("HTTP/1.1 400 Bad Request").count(), 1);
+        assert_eq!(resp.matches("HTTP/1.1").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn request_heads_in_bodies_ignored() {
+        let mut app = App::new();
+        app.post("/", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        app.get("/admin", || async { "admin" });
+
+        // a body looking like a conflicting request is left alone
+        let body = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n";
+        let raw = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}\
+             GET /admin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            body.len(),
+            body
+        );
+        let resp = send_bytes(app, raw.as_bytes()).await;
+
+        assert_eq!(resp.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(resp.contains(body));
+        assert!(resp.ends_with("admin"));
+    }
+
+    #[tokio::test]
+    async fn checked_after_refused_upgrade() {
+        let mut app = App::new();
+        app.post("/", |body: BytesBody| async move {
+            String::from_utf8_lossy(body.value()).into_owned()
+        });
+        app.get("/admin", || async { "admin" });
+
+        // hyper answers the upgrade as a plain request, the next one is checked still
+        let raw = "GET /admin HTTP/1.1\r\nHost: localhost\r\nUpgrade: foo\r\n\r\n\
+                   POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\
+                   Content-Length: 3\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        let resp = send_bytes(app, raw.as_bytes()).await;
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.contains("admin"));
+        assert!(resp.contains("HTTP/1.1 400 Bad Request"));
+        assert!(!resp.contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn favicon() {
+        let mut app = App::new();
+        app.favicon(&b"\0\0\x01\0icon"[..]);
+
+        let resp = send(app, get("/favicon.ico")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/x-icon");
+        assert_eq!(
+            resp.headers()[header::CACHE_CONTROL],
+            "public, max-age=86400"
+        );
+        assert_eq!(resp.body(), &b"\0\0\x01\0icon"[..]);
+
+        let mut app = App::new();
+        app.favicon(bytes::Bytes::new());
+
+        let resp = send(app, get("/favicon.ico")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(resp.body().is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use cookie::Cookie;
+use ring::hmac;
+
+use crate::error::Error;
+use crate::extracts::Cookies;
+use crate::request::{FromRequest, RequestParts};
+
+/// Secret key signing cookies for `SignedCookies`, registered with `App::data`.
+#[derive(Clone)]
+pub struct CookieKey {
+    key: Arc<hmac::Key>,
+}
+
+impl CookieKey {
+    /// Use a random secret of at least 32 bytes, rotating it invalidates signed cookies.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        CookieKey {
+            key: Arc::new(hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref())),
+        }
+    }
+
+    /// The signature covers the name too, so a value can not be moved to another cookie.
+    fn tag(&self, name: &str, value: &str) -> hmac::Tag {
+        let mut ctx = hmac::Context::with_key(&self.key);
+        ctx.update(name.as_bytes());
+        ctx.update(b"=");
+        ctx.update(value.as_bytes());
+        ctx.sign()
+    }
+
+    fn sign_value(&self, name: &str, value: &str) -> String {
+        let tag = URL_SAFE_NO_PAD.encode(self.tag(name, value));
+        format!("{}.{}", value, tag)
+    }
+
+    fn verify_value<'a>(&self, name: &str, signed: &'a str) -> Option<&'a str> {
+        let (value, tag) = signed.rsplit_once('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+
+        let mut message = Vec::with_capacity(name.len() + 1 + value.len());
+        message.extend_from_slice(name.as_bytes());
+        message.push(b'=');
+        message.extend_from_slice(value.as_bytes());
+
+        hmac::verify(&self.key, &message, &tag).ok()?;
+        Some(value)
+    }
+}
+
+impl std::fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieKey").finish()
+    }
+}
+
+/// Cookies signed with the `CookieKey` registered by `App::data`, to issue tamper-proof
+/// cookies, e.g. session ids. Values are readable by the client, but not forgeable.
+///
+/// Only cookies with a valid signature are visible, others are ignored. Without a
+/// registered key, no cookie is visible and `sign` fails.
+#[derive(Debug, Clone)]
+pub struct SignedCookies {
+    key: Option<CookieKey>,
+    cookies: Cookies,
+}
+
+impl SignedCookies {
+    /// Verified value of the first validly signed cookie named `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let key = self.key.as_ref()?;
+
+        self.cookies
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .find_map(|(_, value)| key.verify_value(name, value))
+    }
+
+    /// Sign the cookie value, to send with `LieResponse::append_cookie`.
+    ///
+    /// Fails with `Error::MissingAppState` when no `CookieKey` is registered, rather than
+    /// sending the cookie unsigned.
+    pub fn sign(&self, mut cookie: Cookie<'static>) -> Result<Cookie<'static>, Error> {
+        let key = self
+            .key
+            .as_ref()
+            .ok_or(Error::MissingAppState { name: "CookieKey" })?;
+
+        let value = key.sign_value(cookie.name(), cookie.value());
+        cookie.set_value(value);
+        Ok(cookie)
+    }
+}
+
+#[crate::async_trait]
+impl FromRequest for SignedCookies {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts) -> Result<Self, Self::Rejection> {
+        Ok(SignedCookies {
+            key: req.extensions().get::<CookieKey>().cloned(),
+            cookies: Cookies::from_headers(req.headers()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use crate::http::{self, header};
+    use crate::test_helpers::{get, send, TestClient};
+    use crate::{App, Cookie, LieResponse};
+
+    use super::{CookieKey, SignedCookies};
+
+    async fn login(jar: SignedCookies) -> Result<LieResponse, crate::Error> {
+        let cookie = jar.sign(Cookie::new("session", "alice"))?;
+        Ok(LieResponse::from("ok").append_cookie(cookie))
+    }
+
+    fn with_cookie(cookie: &str) -> http::Request<Full<Bytes>> {
+        http::Request::get("/me")
+            .header(header::COOKIE, cookie)
+            .body(Full::default())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_cookies() {
+        let mut app = App::new();
+        app.data(CookieKey::new([7u8; 32]));
+        app.get("/login", login);
+        app.get("/me", |jar: SignedCookies| async move {
+            jar.get("session").unwrap_or("anonymous").to_string()
+        });
+        let client = TestClient::new(app);
+
+        let resp = client.send(get("/login")).await;
+        let set_cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = Cookie::parse(set_cookie.to_string()).unwrap();
+        assert_ne!(cookie.value(), "alice");
+
+        let resp = client
+            .send(with_cookie(&format!("session={}", cookie.value())))
+            .await;
+        assert_eq!(resp.body(), "alice");
+
+        // tampered value
+        let forged = cookie.value().replacen("alice", "admin", 1);
+        let resp = client
+            .send(with_cookie(&format!("session={}", forged)))
+            .await;
+        assert_eq!(resp.body(), "anonymous");
+
+        // value moved to another cookie name
+        let resp = client
+            .send(with_cookie(&format!("other={}", cookie.value())))
+            .await;
+        assert_eq!(resp.body(), "anonymous");
+
+        let resp = client.send(with_cookie("session=alice")).await;
+        assert_eq!(resp.body(), "anonymous");
+    }
+
+    #[tokio::test]
+    async fn signed_cookies_without_key() {
+        let mut app = App::new();
+        app.get("/login", login);
+
+        let resp = send(app, get("/login")).await;
+        assert_eq!(resp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+    }
+}
@@ -0,0 +1,124 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Incoming};
+use hyper_util::rt::TokioIo;
+
+use crate::http;
+use crate::server::IoServer;
+use crate::App;
+
+pub(crate) use crate::test_util::{capture_logs, LogCapture};
+
+pub(crate) const REMOTE_ADDR: &str = "127.0.0.1:12345";
+
+/// Client of one app instance, sending each request over a new in-memory connection to it.
+///
+/// Build the app once per test and share it between requests, state of middlewares and
+/// `App::data` included.
+#[derive(Clone)]
+pub(crate) struct TestClient {
+    server: IoServer,
+}
+
+impl TestClient {
+    pub(crate) fn new(app: App) -> Self {
+        TestClient {
+            server: app.into_io_server(),
+        }
+    }
+
+    /// Send `req` to the app.
+    pub(crate) async fn send_raw<B>(&self, req: http::Request<B>) -> http::Response<Incoming>
+    where
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+
+        let remote_addr: SocketAddr = REMOTE_ADDR.parse().unwrap();
+        tokio::spawn(self.server.clone().serve(server, Some(remote_addr)));
+
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(client))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        sender.send_request(req).await.unwrap()
+    }
+
+    /// Like `send_raw`, but collect the whole response body.
+    pub(crate) async fn send<B>(&self, req: http::Request<B>) -> http::Response<Bytes>
+    where
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let resp = self.send_raw(req).await;
+
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+
+        http::Response::from_parts(parts, body)
+    }
+
+    /// Write `raw` to a new connection and read until it closes.
+    pub(crate) async fn send_bytes(&self, raw: &[u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+
+        let remote_addr: SocketAddr = REMOTE_ADDR.parse().unwrap();
+        tokio::spawn(self.server.clone().serve(server, Some(remote_addr)));
+
+        client.write_all(raw).await.unwrap();
+
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+}
+
+/// Serve the app for a single request, see `TestClient` to send more.
+pub(crate) async fn send_raw<B>(app: App, req: http::Request<B>) -> http::Response<Incoming>
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    TestClient::new(app).send_raw(req).await
+}
+
+/// Serve the app for a single request and collect the whole response body.
+pub(crate) async fn send<B>(app: App, req: http::Request<B>) -> http::Response<Bytes>
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    TestClient::new(app).send(req).await
+}
+
+pub(crate) fn get(uri: &str) -> http::Request<Full<Bytes>> {
+    http::Request::get(uri).body(Full::default()).unwrap()
+}
+
+pub(crate) fn post(
+    uri: &str,
+    content_type: &str,
+    body: impl Into<Bytes>,
+) -> http::Request<Full<Bytes>> {
+    http::Request::post(uri)
+        .header(http::header::CONTENT_TYPE, content_type)
+        .body(Full::new(body.into()))
+        .unwrap()
+}
+
+/// Serve the app for a single connection, write `raw` and read until it closes.
+pub(crate) async fn send_bytes(app: App, raw: &[u8]) -> String {
+    TestClient::new(app).send_bytes(raw).await
+}
@@ -0,0 +1,194 @@
+//! Testing utilities, enabled by the `test-util` feature.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A `tracing` event recorded by `LogCapture`.
+#[derive(Debug, Clone)]
+pub struct CapturedEvent {
+    level: Level,
+    target: String,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl CapturedEvent {
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The `message` field, empty for events without a message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Value of a field other than `message`, formatted with `Debug`, or `Display` for
+    /// fields recorded with `%`.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Visit for CapturedEvent {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields
+                .push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct EventRecorder(Arc<Mutex<Vec<CapturedEvent>>>);
+
+impl<S: Subscriber> Layer<S> for EventRecorder {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut captured = CapturedEvent {
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: String::new(),
+            fields: Vec::new(),
+        };
+        event.record(&mut captured);
+
+        self.0.lock().unwrap().push(captured);
+    }
+}
+
+/// Collect `tracing` events emitted on the current thread while alive.
+///
+/// Tasks spawned on a current-thread runtime, e.g. `#[tokio::test]`, are covered.
+pub struct LogCapture {
+    buffer: LogBuffer,
+    events: EventRecorder,
+    _guard: tracing::subscriber::DefaultGuard,
+}
+
+impl LogCapture {
+    pub fn new() -> Self {
+        let buffer = LogBuffer::default();
+        let events = EventRecorder::default();
+
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(move || writer.clone()),
+            )
+            .with(events.clone());
+
+        LogCapture {
+            buffer,
+            events,
+            _guard: tracing::subscriber::set_default(subscriber),
+        }
+    }
+
+    /// Logs formatted as by `tracing_subscriber::fmt`, including span context.
+    pub fn logs(&self) -> String {
+        let logs = self.buffer.0.lock().unwrap();
+        String::from_utf8_lossy(&logs).into_owned()
+    }
+
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.events.0.lock().unwrap().clone()
+    }
+
+    /// Whether an event of `level` has a message containing `message`.
+    pub fn contains(&self, level: Level, message: &str) -> bool {
+        self.events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.level == level && e.message.contains(message))
+    }
+
+    /// Panic unless an event of `level` has a message containing `message`.
+    #[track_caller]
+    pub fn assert_logged(&self, level: Level, message: &str) {
+        assert!(
+            self.contains(level, message),
+            "no {} event containing {:?}, logs:\n{}",
+            level,
+            message,
+            self.logs()
+        );
+    }
+
+    /// Panic unless an event has the field `name` with `value`.
+    #[track_caller]
+    pub fn assert_field(&self, name: &str, value: &str) {
+        let found = self
+            .events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.field(name) == Some(value));
+
+        assert!(
+            found,
+            "no event with {}={:?}, logs:\n{}",
+            name,
+            value,
+            self.logs()
+        );
+    }
+}
+
+impl Default for LogCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LogCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogCapture").finish()
+    }
+}
+
+/// Run `f` and collect the logs it emits on the current thread.
+pub fn capture_logs(f: impl FnOnce()) -> String {
+    let capture = LogCapture::new();
+    f();
+    capture.logs()
+}
@@ -1,50 +1,155 @@
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 use std::sync::Arc;
 
-use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
-use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+use rustls_pemfile::{certs, private_key};
 use tokio_rustls::rustls::ServerConfig;
 use tokio_rustls::TlsAcceptor;
 
 use crate::error::Error;
 
-pub(crate) fn new_tls_acceptor(
+/// TLS settings of the server, loaded from the certificate chain and private key in PEM,
+/// or a rustls `ServerConfig` for full control, e.g. of ALPN and session tickets.
+///
+/// Loaded from PEM, HTTP/2 and HTTP/1.1 are offered by ALPN, HTTP/2 preferred.
+#[derive(Clone)]
+pub struct TlsConfig {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsConfig {
+    /// Load the certificate chain and private key from PEM files.
+    pub fn from_files(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let config = new_tls_config(cert_path, key_path)?;
+        Ok(TlsConfig { config })
+    }
+
+    /// Load the certificate chain and private key from in-memory PEM, e.g. from a secret
+    /// store or environment variable.
+    pub fn from_pem(cert_pem: &[u8], key_pem: &[u8]) -> Result<Self, Error> {
+        let config = tls_config_from_pem(&mut &cert_pem[..], &mut &key_pem[..])?;
+        Ok(TlsConfig { config })
+    }
+
+    /// Use a pre-built rustls `ServerConfig` as is.
+    pub fn from_rustls(config: impl Into<Arc<ServerConfig>>) -> Self {
+        TlsConfig {
+            config: config.into(),
+        }
+    }
+
+    pub fn server_config(&self) -> &Arc<ServerConfig> {
+        &self.config
+    }
+
+    pub(crate) fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.config.clone())
+    }
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig").finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn new_tls_config(
     cert_path: impl AsRef<Path>,
     key_path: impl AsRef<Path>,
-) -> Result<TlsAcceptor, Error> {
-    let cert_chain = certs(&mut BufReader::new(
+) -> Result<Arc<ServerConfig>, Error> {
+    let mut cert_reader = BufReader::new(
         File::open(cert_path.as_ref())
             .map_err(|e| crate::error_msg!("open cert file failed, err:{:?}", e))?,
-    ))
-    .collect::<Result<_, _>>()
-    .map_err(|_| crate::error_msg!("invalid cert"))?;
+    );
 
     let mut key_bytes = Vec::new();
     File::open(key_path.as_ref())?.read_to_end(&mut key_bytes)?;
 
-    let mut reader = BufReader::new(key_bytes.as_slice());
+    tls_config_from_pem(&mut cert_reader, &mut key_bytes.as_slice())
+}
 
-    // try pkcs8 first
-    let key_der = match pkcs8_private_keys(&mut reader).next() {
-        Some(Ok(pkcs8)) => PrivateKeyDer::Pkcs8(pkcs8),
-        None => {
-            let mut reader = BufReader::new(key_bytes.as_slice());
-            let key = rsa_private_keys(&mut reader)
-                .next()
-                .ok_or(crate::error_msg!("invalid key"))?;
+fn tls_config_from_pem(
+    cert_reader: &mut dyn BufRead,
+    key_reader: &mut dyn BufRead,
+) -> Result<Arc<ServerConfig>, Error> {
+    let cert_chain: Vec<_> = certs(cert_reader)
+        .collect::<Result<_, _>>()
+        .map_err(|_| crate::error_msg!("invalid cert"))?;
+    if cert_chain.is_empty() {
+        return Err(crate::error_msg!("invalid cert, no certificate found"));
+    }
 
-            PrivateKeyDer::Pkcs1(key.map_err(|_| crate::error_msg!("invalid key"))?)
-        }
-        _ => {
-            return Err(crate::error_msg!("invalid key, not data"));
-        }
-    };
+    // PKCS8 (RSA or EC), RSA (PKCS1) or EC (SEC1), whichever comes first
+    let key_der = private_key(key_reader)
+        .map_err(|e| crate::error_msg!("invalid key, err:{:?}", e))?
+        .ok_or(crate::error_msg!(
+            "invalid key, no PKCS8, RSA (PKCS1) or EC (SEC1) private key found"
+        ))?;
 
-    let config = ServerConfig::builder()
+    let mut config = ServerConfig::builder()
         .with_no_client_auth()
         .with_single_cert(cert_chain, key_der)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{new_tls_config, TlsConfig};
+
+    const TESTDATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+
+    #[test]
+    fn ec_keys() {
+        for key in ["ec-sec1.key", "ec-pkcs8.key"] {
+            let ret = new_tls_config(
+                format!("{}/ec.crt", TESTDATA),
+                format!("{}/{}", TESTDATA, key),
+            );
+            assert!(ret.is_ok(), "{}: {:?}", key, ret.err());
+        }
+    }
+
+    #[test]
+    fn missing_key() {
+        let err = new_tls_config(
+            format!("{}/ec.crt", TESTDATA),
+            format!("{}/ec.crt", TESTDATA),
+        )
+        .err()
+        .unwrap();
+        assert!(format!("{:?}", err).contains("EC (SEC1)"), "{:?}", err);
+    }
+
+    #[test]
+    fn from_pem() {
+        let cert = include_bytes!("../testdata/ec.crt");
+        let key = include_bytes!("../testdata/ec-sec1.key");
+
+        assert!(TlsConfig::from_pem(cert, key).is_ok());
+        assert!(TlsConfig::from_pem(cert, b"").is_err());
+        assert!(TlsConfig::from_pem(b"", key).is_err());
+    }
+
+    #[test]
+    fn from_rustls() {
+        use std::sync::Arc;
+
+        use tokio_rustls::rustls::server::ResolvesServerCertUsingSni;
+        use tokio_rustls::rustls::ServerConfig;
+
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(ResolvesServerCertUsingSni::new()));
+        config.alpn_protocols = vec![b"h2".to_vec()];
 
-    Ok(TlsAcceptor::from(Arc::new(config)))
+        let tls = TlsConfig::from_rustls(config);
+        assert_eq!(tls.server_config().alpn_protocols, [b"h2".to_vec()]);
+        assert!(Arc::ptr_eq(tls.acceptor().config(), tls.server_config()));
+    }
 }
@@ -1,5 +1,11 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use bytes::Bytes;
-use http_body_util::Full;
+use hyper::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use tokio::time::{Instant, Sleep};
 
 pub struct Form<T> {
     pub(crate) value: T,
@@ -20,14 +26,12 @@ impl<T> Form<T> {
 }
 
 pub struct Html {
-    pub(crate) body: Full<Bytes>,
+    pub(crate) body: Bytes,
 }
 
 impl Html {
     pub fn new(body: impl Into<Bytes>) -> Self {
-        Html {
-            body: Full::new(body.into()),
-        }
+        Html { body: body.into() }
     }
 }
 
@@ -49,6 +53,91 @@ impl<T> Json<T> {
     }
 }
 
+/// JSON response pretty-printed with indentation, for debugging. `Json` stays compact.
+pub struct PrettyJson<T> {
+    pub(crate) value: T,
+}
+
+impl<T> PrettyJson<T> {
+    pub fn new(value: T) -> Self {
+        PrettyJson { value }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+impl<T> From<Json<T>> for PrettyJson<T> {
+    fn from(json: Json<T>) -> Self {
+        PrettyJson { value: json.value }
+    }
+}
+
+/// Request body decoded from JSON or form, according to `Content-Type`.
+pub struct JsonOrForm<T> {
+    pub(crate) value: T,
+}
+
+impl<T> JsonOrForm<T> {
+    pub fn new(value: T) -> Self {
+        JsonOrForm { value }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn take(self) -> T {
+        self.value
+    }
+}
+
+/// Redirect response, handlers can return it directly, e.g. `Redirect::to("/login")`.
+///
+/// A location which is not a valid header value is answered with
+/// `500 Internal Server Error`.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub(crate) status: hyper::StatusCode,
+    pub(crate) location: String,
+}
+
+impl Redirect {
+    /// Redirect with `303 See Other`, followed with `GET`.
+    pub fn to(location: impl Into<String>) -> Self {
+        Redirect::with_status(hyper::StatusCode::SEE_OTHER, location)
+    }
+
+    /// Redirect with `307 Temporary Redirect`, keeping the method and body.
+    pub fn temporary(location: impl Into<String>) -> Self {
+        Redirect::with_status(hyper::StatusCode::TEMPORARY_REDIRECT, location)
+    }
+
+    /// Redirect with `308 Permanent Redirect`, keeping the method and body.
+    pub fn permanent(location: impl Into<String>) -> Self {
+        Redirect::with_status(hyper::StatusCode::PERMANENT_REDIRECT, location)
+    }
+
+    pub fn with_status(status: hyper::StatusCode, location: impl Into<String>) -> Self {
+        Redirect {
+            status,
+            location: location.into(),
+        }
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+/// Streaming response body, handlers can return it directly.
+///
+/// Each item is written to the connection as soon as the stream yields it.
 pub struct StreamBody<S> {
     pub(crate) s: S,
     pub(crate) content_type: mime::Mime,
@@ -63,8 +152,66 @@ where
     pub fn new(s: S, content_type: mime::Mime) -> Self {
         StreamBody { s, content_type }
     }
+
+    /// Send `frame` whenever the stream yields nothing for `interval`, e.g. to keep
+    /// long-running progress output alive through proxies.
+    pub fn keep_alive(
+        self,
+        interval: Duration,
+        frame: impl Into<Bytes>,
+    ) -> StreamBody<KeepAliveStream<S>> {
+        let StreamBody { s, content_type } = self;
+
+        StreamBody {
+            s: KeepAliveStream {
+                s: Box::pin(s),
+                interval,
+                frame: frame.into(),
+                sleep: Box::pin(tokio::time::sleep(interval)),
+            },
+            content_type,
+        }
+    }
+}
+
+/// Stream with keep-alive frames, see `StreamBody::keep_alive`.
+pub struct KeepAliveStream<S> {
+    s: Pin<Box<S>>,
+    interval: Duration,
+    frame: Bytes,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<S, B, E> futures::Stream for KeepAliveStream<S>
+where
+    S: futures::Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let deadline = Instant::now() + self.interval;
+
+        match self.s.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                self.sleep.as_mut().reset(deadline);
+                Poll::Ready(Some(item.map(Into::into)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => match self.sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    self.sleep.as_mut().reset(deadline);
+                    Poll::Ready(Some(Ok(self.frame.clone())))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
 }
 
+/// Alias of [`StreamBody`], e.g. `Streaming::new(stream, mime::TEXT_PLAIN)`.
+pub type Streaming<S> = StreamBody<S>;
+
 pub struct BytesBody {
     pub(crate) body: Bytes,
     pub(crate) content_type: mime::Mime,
@@ -86,3 +233,182 @@ impl BytesBody {
         self.body
     }
 }
+
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Rate limit state, rendered as `X-RateLimit-Limit`, `X-RateLimit-Remaining`,
+/// `X-RateLimit-Reset` (epoch seconds) and `Retry-After` (delay seconds).
+///
+/// Use [`RateLimitHeaders::apply`] to decorate a normal response, or return it
+/// from a handler to reply `429 Too Many Requests`.
+#[derive(Debug, Clone)]
+pub struct RateLimitHeaders {
+    limit: u64,
+    remaining: u64,
+    reset: SystemTime,
+    retry_after: Option<Duration>,
+}
+
+impl RateLimitHeaders {
+    pub fn new(limit: u64, remaining: u64, reset: SystemTime) -> Self {
+        RateLimitHeaders {
+            limit,
+            remaining,
+            reset,
+            retry_after: None,
+        }
+    }
+
+    /// Set `Retry-After` explicitly, otherwise it is derived from the reset time
+    /// when nothing remains.
+    pub fn retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = Some(delay);
+        self
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    pub fn reset(&self) -> SystemTime {
+        self.reset
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let reset = self
+            .reset
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(X_RATELIMIT_RESET, HeaderValue::from(reset));
+
+        let retry_after = match self.retry_after {
+            Some(delay) => Some(delay),
+            None if self.remaining == 0 => Some(
+                self.reset
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            ),
+            None => None,
+        };
+
+        if let Some(delay) = retry_after {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(ceil_secs(delay)));
+        }
+    }
+}
+
+fn ceil_secs(d: Duration) -> u64 {
+    if d.subsec_nanos() > 0 {
+        d.as_secs() + 1
+    } else {
+        d.as_secs()
+    }
+}
+
+const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// A page of `items` of a list, serialized as JSON with the total count in
+/// `X-Total-Count` and `Link` headers (`first`, `prev`, `next`, `last`) to the
+/// neighbouring pages.
+///
+/// Page links keep the path and query of `uri`, with `offset` and `limit` replaced.
+#[derive(Debug, Clone)]
+pub struct Paginated<T> {
+    items: Vec<T>,
+    uri: hyper::http::Uri,
+    offset: usize,
+    limit: usize,
+    total: usize,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(
+        uri: &hyper::http::Uri,
+        items: Vec<T>,
+        offset: usize,
+        limit: usize,
+        total: usize,
+    ) -> Self {
+        Paginated {
+            items,
+            uri: uri.clone(),
+            offset,
+            limit,
+            total,
+        }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The `Link` header, `None` with a zero limit.
+    pub fn link(&self) -> Option<String> {
+        if self.limit == 0 {
+            return None;
+        }
+
+        let last = self.total.saturating_sub(1) / self.limit * self.limit;
+
+        let mut pages = vec![("first", 0)];
+        if self.offset > 0 {
+            pages.push(("prev", self.offset.saturating_sub(self.limit)));
+        }
+        if self.offset + self.limit < self.total {
+            pages.push(("next", self.offset + self.limit));
+        }
+        pages.push(("last", last));
+
+        let links = pages
+            .into_iter()
+            .map(|(rel, offset)| format!("<{}>; rel=\"{}\"", self.page_url(offset), rel))
+            .collect::<Vec<_>>();
+
+        Some(links.join(", "))
+    }
+
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(X_TOTAL_COUNT, HeaderValue::from(self.total));
+
+        if let Some(link) = self.link().and_then(|l| HeaderValue::from_str(&l).ok()) {
+            headers.insert(header::LINK, link);
+        }
+    }
+
+    fn page_url(&self, offset: usize) -> String {
+        let mut pairs: Vec<(String, String)> = self
+            .uri
+            .query()
+            .and_then(|q| serde_urlencoded::from_str(q).ok())
+            .unwrap_or_default();
+        pairs.retain(|(k, _)| k != "offset" && k != "limit");
+        pairs.push(("offset".to_string(), offset.to_string()));
+        pairs.push(("limit".to_string(), self.limit.to_string()));
+
+        let query = serde_urlencoded::to_string(&pairs).expect("encode string pairs");
+
+        format!("{}?{}", self.uri.path(), query)
+    }
+}
@@ -35,6 +35,26 @@ where
     Ok((name, value))
 }
 
+/// Decode `%XX` escapes, `None` for malformed escapes or invalid UTF-8.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(out).ok()
+}
+
 pub(crate) fn gen_random_string(length: usize) -> String {
     rand::thread_rng()
         .sample_iter(&Alphanumeric)
@@ -46,7 +66,11 @@ pub(crate) fn gen_random_string(length: usize) -> String {
 #[macro_export]
 macro_rules! register_method {
     ($func_name: ident, $method: expr) => {
-        pub fn $func_name<H, T>(&mut self, path: impl AsRef<str>, handler: H)
+        pub fn $func_name<H, T>(
+            &mut self,
+            path: impl AsRef<str>,
+            handler: H,
+        ) -> $crate::RouteHandle<'_>
         where
             H: Handler<T> + Send + Sync + 'static,
             T: 'static,
@@ -0,0 +1,98 @@
+//! Write timeout of connections, against clients reading responses too slowly.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Connection io failing writes which make no progress within the timeout, hyper then
+/// closes the connection. Without a timeout, io is passed through.
+pub(crate) struct WriteTimeoutIo<I> {
+    inner: I,
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<I> WriteTimeoutIo<I> {
+    pub(crate) fn new(inner: I, timeout: Option<Duration>) -> Self {
+        WriteTimeoutIo {
+            inner,
+            timeout,
+            sleep: None,
+        }
+    }
+
+    /// Track a pending write, the timer restarts once the write made progress.
+    fn poll_timeout<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        ret: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        let timeout = match (self.timeout, &ret) {
+            (Some(timeout), Poll::Pending) => timeout,
+            _ => {
+                self.sleep = None;
+                return ret;
+            }
+        };
+
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                tracing::debug!("write timed out after {:?}, closing connection", timeout);
+                Poll::Ready(Err(io::ErrorKind::TimedOut.into()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for WriteTimeoutIo<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for WriteTimeoutIo<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let ret = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.poll_timeout(cx, ret)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let ret = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.poll_timeout(cx, ret)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let ret = Pin::new(&mut self.inner).poll_flush(cx);
+        self.poll_timeout(cx, ret)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}